@@ -0,0 +1,106 @@
+//! Measures the time `backprop` takes for a mini batch of an MNIST sized network.
+//!
+//! The sigmoid layers of the network cache their outputs and calculate the derivatives from them.
+//! For comparison the same network is also run with sigmoid layers that keep their inputs and
+//! evaluate the sigmoid again during backpropagation, as `ActivationLayer` did before.
+//!
+//! Run with `cargo bench`.
+
+extern crate nalgebra as na;
+extern crate rustle_my_net;
+
+use na::DMatrix;
+use rustle_my_net::nn::{Activation, ActivationLayer, Cost, Dense, Layer, Network};
+use rustle_my_net::nn::learning;
+use rustle_my_net::structs::serialnet::SerializableLayer;
+use std::time::Instant;
+
+// The sizes of the dense layers
+const TOPOLOGY: [usize; 4] = [784, 100, 30, 10];
+// Number of samples per mini batch
+const BATCH_SIZE: usize = 32;
+// Number of timed mini batches per run
+const ITERATIONS: u32 = 200;
+
+// A sigmoid layer that evaluates the sigmoid again for the derivatives
+#[derive(Debug, Clone)]
+struct RecomputingSigmoid {
+    input: Option<DMatrix<f32>>,
+}
+
+impl Layer<f32> for RecomputingSigmoid {
+    fn kind(&self) -> &'static str {
+        "activation"
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        Ok(input_size)
+    }
+
+    fn forward_into(&self, input: &DMatrix<f32>, output: &mut DMatrix<f32>) {
+        for (o, z) in output.as_mut_vector().iter_mut().zip(input.as_vector().iter()) {
+            *o = Activation::Sigmoid.apply(*z);
+        }
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<f32>) -> DMatrix<f32> {
+        self.input = Some(input.clone());
+        let mut output = input.clone();
+        self.forward_into(input, &mut output);
+        output
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<f32>) -> DMatrix<f32> {
+        let input = self.input.as_ref().unwrap();
+        let mut grad_input = grad_output.clone();
+        for (g, z) in grad_input.as_mut_vector().iter_mut().zip(input.as_vector().iter()) {
+            *g *= Activation::Sigmoid.derivative(*z);
+        }
+        grad_input
+    }
+
+    fn serialize(&self) -> SerializableLayer<f32> {
+        SerializableLayer::Activation { function: Activation::Sigmoid }
+    }
+
+    fn box_clone(&self) -> Box<Layer<f32>> {
+        Box::new(self.clone())
+    }
+}
+
+// A network of dense layers with sigmoid activations, the activations are built by `activation`
+fn network<F: Fn() -> Box<Layer<f32>>>(activation: F) -> Network<f32> {
+    let mut layers: Vec<Box<Layer<f32>>> = Vec::new();
+    for sizes in TOPOLOGY.windows(2) {
+        layers.push(Box::new(Dense::new(sizes[0], sizes[1])));
+        layers.push(activation());
+    }
+    Network::from_layers(TOPOLOGY[0], layers).unwrap()
+}
+
+// The mean time of `backprop` for a mini batch in milliseconds
+fn time_backprop(mut nn: Network<f32>) -> f64 {
+    let n_outputs = TOPOLOGY[TOPOLOGY.len() - 1];
+    let inputs = DMatrix::from_fn(TOPOLOGY[0], BATCH_SIZE, |i, k| ((i * 7 + k) % 13) as f32 / 13.0);
+    let desired = DMatrix::from_fn(n_outputs, BATCH_SIZE, |i, k| (k % n_outputs == i) as u8 as f32);
+    // warm up the caches and the allocator
+    for _ in 0..10 {
+        learning::backprop(&mut nn, &inputs, &desired, Cost::Quadratic, None);
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        learning::backprop(&mut nn, &inputs, &desired, Cost::Quadratic, None);
+    }
+    let elapsed = start.elapsed();
+    (elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 * 1e-6) / ITERATIONS as f64
+}
+
+fn main() {
+    let recomputing = time_backprop(network(|| Box::new(RecomputingSigmoid { input: None })));
+    let cached = time_backprop(network(|| Box::new(ActivationLayer::new(Activation::Sigmoid))));
+    println!("backprop of {:?} with mini batches of {} samples:", TOPOLOGY, BATCH_SIZE);
+    println!("  recomputed sigmoid: {:.3} ms", recomputing);
+    println!("  cached outputs:     {:.3} ms ({:.1}% less)",
+             cached,
+             100.0 * (1.0 - cached / recomputing));
+}
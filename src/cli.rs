@@ -0,0 +1,621 @@
+//! The command line interface: the arguments of the subcommands and the configuration they are
+//! parsed into
+
+extern crate clap;
+
+use log::LogLevelFilter;
+use rustle_my_net::input;
+use rustle_my_net::input::config::{GradCheckConfig, LearningConfig, ReportConfig, Search,
+                                   TuneConfig};
+use rustle_my_net::input::experiment::Experiment;
+use rustle_my_net::input::search::SearchSpace;
+use rustle_my_net::nn::Topology;
+use rustle_my_net::structs::Data;
+use rustle_my_net::structs::flower::Flower;
+use rustle_my_net::structs::mnist::Mnist;
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+use self::clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+
+const DEFAULT_SAVE_FILE: &'static str = "model_state.ser";
+
+/// Reads the arguments given to this program at execution and returns them
+pub fn read_arguments() -> GlobalConfig {
+    GlobalConfig::from_arguments(app().get_matches())
+}
+
+// The arguments of this program and of its subcommands
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("rustle my net")
+        .subcommand(SubCommand::with_name("learn")
+            .arg(topology_arg().required_unless("config"))
+            .args(&learn_args()))
+        .subcommand(SubCommand::with_name("classify"))
+        .subcommand(SubCommand::with_name("gradcheck")
+            .about("Compares the gradients of backpropagation with numerical derivatives on small \
+                    random networks.")
+            .arg(Arg::with_name("topology")
+                .long("topology")
+                .short("t")
+                .help("The topology of the random networks to check, see 'learn --help'. \
+                       Default: '4 5 3'.")
+                .multiple(true)
+                .value_delimiter(" ")
+                .min_values(2)
+                .default_value("4 5 3"))
+            .arg(Arg::with_name("samples")
+                .long("samples")
+                .takes_value(true)
+                .help("The number of random samples in the batch the gradients are checked for. \
+                       Default: 10.")
+                .default_value("10"))
+            .arg(Arg::with_name("batch_norm")
+                .long("batchnorm")
+                .help("Check a network with batch normalization on all hidden layers."))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .possible_values(&["sigmoid", "linear"])
+                .help("The activation function of the output layer. Default: sigmoid.")
+                .default_value("sigmoid"))
+            .arg(Arg::with_name("cost")
+                .long("cost")
+                .takes_value(true)
+                .help("The cost function whose gradients are checked, see 'learn --help'. \
+                       Default: mse.")
+                .default_value("mse"))
+            .arg(Arg::with_name("epsilon")
+                .long("epsilon")
+                .takes_value(true)
+                .help("The step size of the finite differences. Default: 1e-5.")
+                .default_value("1e-5"))
+            .arg(Arg::with_name("tolerance")
+                .long("tolerance")
+                .takes_value(true)
+                .help("The largest relative error that is accepted. Default: 1e-6.")
+                .default_value("1e-6"))
+            .arg(Arg::with_name("precision")
+                .long("precision")
+                .takes_value(true)
+                .possible_values(&["f32", "f64"])
+                .help("The floating point precision of the check. Results in f32 are not \
+                       reliable. Default: f64.")
+                .default_value("f64")))
+        .subcommand(SubCommand::with_name("report")
+            .about("Draws SVG charts of the training history saved with the network and the \
+                    confusion matrix of the network on the data.")
+            .arg(Arg::with_name("out_dir")
+                .long("out")
+                .short("o")
+                .takes_value(true)
+                .help("The directory the charts are written to. Default: report.")
+                .default_value("report")))
+        .subcommand(SubCommand::with_name("tune")
+            .about("Searches the settings of 'learn' for the best network: every trial trains a \
+                    network on the same data and validates it. The results are written to a \
+                    leaderboard and the network of the best trial is saved like with 'learn', \
+                    with cross-validation the one trained without the first fold. Takes all \
+                    arguments of 'learn', the searched settings replace them.")
+            .arg(topology_arg())
+            .args(&learn_args())
+            .arg(Arg::with_name("search")
+                .long("search")
+                .help("The settings to search, as 'KEY=VALUES' with the keys of experiment \
+                       files (see '--config'). The values are a list separated by commas, a \
+                       range 'MIN..MAX' or a range 'MIN..MAX:log' on a log scale, e.g. \
+                       '--search eta=0.01..1:log batch_size=8,16,32 \"topology=4 5 3,4 10 3\"'.")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(false)
+                .required(true))
+            .arg(Arg::with_name("method")
+                .long("method")
+                .takes_value(true)
+                .possible_values(&["grid", "random"])
+                .help("Try every combination of values ('grid') or draw random values in every \
+                       trial ('random'). Default: grid.")
+                .default_value("grid"))
+            .arg(Arg::with_name("trials")
+                .long("trials")
+                .takes_value(true)
+                .help("The number of trials of random search. Default: 20.")
+                .default_value("20"))
+            .arg(Arg::with_name("steps")
+                .long("steps")
+                .takes_value(true)
+                .help("The number of values grid search tries in each range. Default: 3.")
+                .default_value("3"))
+            .arg(Arg::with_name("folds")
+                .long("folds")
+                .takes_value(true)
+                .help("Cross-validate every trial on this many folds of the data instead of \
+                       validating it on the last '--testsize' samples."))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("The seed of random search and of the split of the data. Default: 0.")
+                .default_value("0"))
+            .arg(Arg::with_name("leaderboard")
+                .long("leaderboard")
+                .takes_value(true)
+                .help("The CSV file the results of all trials are written to, best first. \
+                       Default: leaderboard.csv.")
+                .default_value("leaderboard.csv")))
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("verbosity")
+            .long("verbosity")
+            .short("v")
+            .takes_value(true)
+            .possible_values(&["debug", "info", "error", "off"])
+            .default_value("info"))
+        .arg(Arg::with_name("data")
+            .long("data")
+            .short("i")
+            .takes_value(true)
+            .default_value("data/iris_flowers.txt"))
+        .arg(Arg::with_name("datatype")
+            .long("type")
+            .short("d")
+            .takes_value(true)
+            .possible_values(&["flower", "mnist", "sequence", "regression", "multilabel"])
+            .default_value("flower"))
+        .arg(Arg::with_name("labels")
+            .long("labels")
+            .takes_value(true)
+            .help("The number of label columns at the end of each line of multi-label data, \
+                   the columns before them are the features."))
+        .arg(Arg::with_name("threshold")
+            .long("threshold")
+            .takes_value(true)
+            .help("The decision threshold of multi-label data: every output above it counts as \
+                   a predicted label. Default: 0.5.")
+            .default_value("0.5"))
+        .arg(Arg::with_name("save_file")
+            .long("file")
+            .short("f")
+            .takes_value(true)
+            .help("The file the network is saved to and loaded from, in the model directory. \
+                   Default: model_state.ser.")
+            .default_value(DEFAULT_SAVE_FILE))
+        .arg(Arg::with_name("workdir")
+            .long("workdir")
+            .takes_value(true)
+            .help("The directory all relative paths are resolved against. Default: the current \
+                   directory."))
+        .arg(Arg::with_name("log_dir")
+            .long("log-dir")
+            .takes_value(true)
+            .help("The directory the log file of every run is written to. Default: logs.")
+            .default_value("logs"))
+        .arg(Arg::with_name("model_dir")
+            .long("model-dir")
+            .takes_value(true)
+            .help("The directory networks and their settings are saved to and loaded from. \
+                   Default: data.")
+            .default_value("data"))
+}
+
+
+// The argument for the topology of the network
+fn topology_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("topology")
+        .long("topology")
+        .short("t")
+        .help("A list of values representing the topology of the neural network. For \
+               example, the input '-t 4 5 3' would create a network with: 4 nodes in \
+               the input layer, a single hidden layer of 5 nodes and 3 nodes in the \
+               output layer. Images are given as HEIGHTxWIDTHxCHANNELS and can be fed \
+               into convolutions 'conv:CHANNELS:KERNEL[:STRIDE[:PADDING]]' and pooling \
+               layers 'maxpool:SIZE[:STRIDE]' or 'avgpool:SIZE[:STRIDE]', e.g. \
+               '-t 28x28x1 conv:8:5:1:2 maxpool:2 100 10' for MNIST. Sequences of \
+               STEPS steps with FEATURES features are given as STEPSxFEATURESx1 and \
+               can be fed into recurrent layers 'rnn:SIZE', 'lstm:SIZE' or 'gru:SIZE', \
+               which pass on only their last output, or all outputs if ':seq' is \
+               appended.")
+        .multiple(true)
+        .value_delimiter(" ")
+        .min_values(3)
+}
+
+
+// The settings of `learn`, which `tune` shares. Most of them can also be set in experiment
+// files, see `experiment`.
+fn learn_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("config")
+             .long("config")
+             .takes_value(true)
+             .help("An experiment file in TOML, or in JSON if the name ends in '.json', with \
+                    the settings of the run in the sections 'data', 'preprocessing', \
+                    'network', 'optimizer', 'schedule' and 'output'. Settings given on the \
+                    command line take precedence. The effective settings are saved next to \
+                    the network, with the extension '.toml'."),
+         Arg::with_name("learning_rate")
+             .long("eta")
+             .takes_value(true)
+             .help("The learning rate eta. Should be between 0.0 and 1.0. Default is 0.05.")
+             .default_value("0.05"),
+         Arg::with_name("epochs")
+             .long("epochs")
+             .takes_value(true)
+             .help("The number of training epochs. The default value is 100.")
+             .default_value("100"),
+         Arg::with_name("mini_batch_size")
+             .long("batchsize")
+             .takes_value(true)
+             .help("The size of the mini batches for the learning process. Default: 32.")
+             .default_value("32"),
+         Arg::with_name("test_data_size")
+             .long("testsize")
+             .takes_value(true)
+             .help("The size of the data that is used for validation. Defaults to 20.")
+             .default_value("20"),
+         Arg::with_name("keep_prob")
+             .long("keep-prob")
+             .help("Dropout keep probabilities of the hidden layers. Either a single value for \
+                    all hidden layers or one value per hidden layer, e.g. '--keep-prob 0.5 \
+                    0.8'. Default: 1.0 (no dropout).")
+             .multiple(true)
+             .value_delimiter(" ")
+             .default_value("1.0"),
+         Arg::with_name("batch_norm")
+             .long("batchnorm")
+             .help("Use batch normalization on all hidden layers."),
+         Arg::with_name("input_keep_prob")
+             .long("input-keep-prob")
+             .takes_value(true)
+             .help("Dropout keep probability of the input layer. Default: 1.0 (no dropout).")
+             .default_value("1.0"),
+         Arg::with_name("bptt")
+             .long("bptt")
+             .takes_value(true)
+             .help("The number of steps the error is backpropagated through time in \
+                    recurrent layers (truncated BPTT). Default: 0 (the whole sequence).")
+             .default_value("0"),
+         Arg::with_name("output")
+             .long("output")
+             .takes_value(true)
+             .possible_values(&["sigmoid", "linear"])
+             .help("The activation function of the output layer. Use 'linear' for regression. \
+                    Default: sigmoid.")
+             .default_value("sigmoid"),
+         Arg::with_name("cost")
+             .long("cost")
+             .takes_value(true)
+             .help("The cost function: 'mse' (quadratic), 'mae' (absolute error), \
+                    'huber[:DELTA]' (Huber loss, DELTA defaults to 1) or 'bce' (binary \
+                    cross-entropy of sigmoid outputs, for multi-label data). Default: bce for \
+                    multi-label data, mse otherwise."),
+         Arg::with_name("on_divergence")
+             .long("on-divergence")
+             .takes_value(true)
+             .help("What happens when costs, gradients or weights stop being finite numbers, \
+                    usually because the learning rate is too high: 'abort' stops the training \
+                    with the network of the last complete epoch, 'rollback[:FACTOR]' repeats \
+                    the epoch with the learning rate multiplied with FACTOR (default 0.5), at \
+                    most 5 times. Default: abort.")
+             .default_value("abort"),
+         Arg::with_name("clip_value")
+             .long("clip-value")
+             .takes_value(true)
+             .help("Clip every element of the gradients of a mini batch to [-VALUE, VALUE]. \
+                    Default: no clipping."),
+         Arg::with_name("clip_norm")
+             .long("clip-norm")
+             .takes_value(true)
+             .help("Scale the gradients of a mini batch down so that their global L2 norm over \
+                    all layers is at most this value, applied after '--clip-value'. The norms \
+                    before clipping are recorded in the history. Default: no clipping."),
+         Arg::with_name("class_weights")
+             .long("class-weights")
+             .help("Weights of the cost of each class for imbalanced data, either one value \
+                    per class, e.g. '--class-weights 1 5 2', or 'auto' for weights inversely \
+                    proportional to the frequency of each class in the training data. \
+                    Default: all classes are weighted equally.")
+             .multiple(true)
+             .value_delimiter(" "),
+         Arg::with_name("resample")
+             .long("resample")
+             .takes_value(true)
+             .possible_values(&["none", "over", "under"])
+             .help("Resample the training data in each epoch so all classes are equally \
+                    frequent: 'over' repeats samples of the smaller classes, 'under' leaves \
+                    out samples of the larger classes. Default: none.")
+             .default_value("none"),
+         Arg::with_name("augment")
+             .long("augment")
+             .help("Randomly transform the training images of each mini batch (mnist only): \
+                    'shift:PIXELS', 'rotate:DEGREES', 'scale:FRACTION', \
+                    'elastic:ALPHA[:SIGMA]' (SIGMA defaults to 4) and 'noise:STDDEV', e.g. \
+                    '--augment shift:2 rotate:10 elastic:34'. The test data is never \
+                    transformed. Default: no augmentation.")
+             .multiple(true)
+             .value_delimiter(" "),
+         Arg::with_name("augment_seed")
+             .long("augment-seed")
+             .takes_value(true)
+             .help("The seed of the random transformations. Default: 0.")
+             .default_value("0"),
+         Arg::with_name("precision")
+             .long("precision")
+             .takes_value(true)
+             .possible_values(&["f32", "f64"])
+             .help("The floating point precision the network is trained in. Default: f32.")
+             .default_value("f32"),
+         Arg::with_name("history")
+             .long("history")
+             .takes_value(true)
+             .help("Write the training cost, validation cost, validation and training \
+                    accuracy, learning rate, gradient norms and duration of every epoch to this \
+                    file, as JSON if it ends in '.json' and as CSV otherwise."),
+         Arg::with_name("report")
+             .long("report")
+             .takes_value(true)
+             .help("Write an HTML page to this file that summarizes the run: the settings, \
+                    the data, the learning curves, the confusion matrix and metrics of each \
+                    class and the worst misclassified test samples. It needs no internet \
+                    connection to be viewed.")]
+}
+
+
+/// Represents a configuration from command line arguments
+#[derive(Debug)]
+pub struct GlobalConfig {
+    /// Holds level of verbosity for output (`Debug`, `Info`, `Error` or `Off`)
+    pub verbosity: LogLevelFilter,
+    /// Path to where saved network object is located, in the model directory,
+    /// will currently default to "data/model_state.ser" if not specified
+    pub save_file: PathBuf,
+    /// Directory the log file of the run is written to
+    pub log_dir: PathBuf,
+    /// Directory networks and their settings are saved to and loaded from
+    pub model_dir: PathBuf,
+    /// Path of the file the data is read from
+    pub data_file: PathBuf,
+    /// The actual data that the network will use to learn / classify
+    pub data: Result<Vec<Data>, io::Error>,
+    /// Outputs above the threshold count as predicted labels of multi-label data
+    pub threshold: f64,
+    /// Hyperparameters for network learning
+    pub learn_config: Option<LearningConfig>,
+    /// Settings for searching the hyperparameters
+    pub tune_config: Option<TuneConfig>,
+    /// Settings for checking the gradients of backpropagation
+    pub gradcheck_config: Option<GradCheckConfig>,
+    /// Settings for drawing charts of a trained network
+    pub report_config: Option<ReportConfig>,
+}
+
+impl GlobalConfig {
+    /// Parse the given `ArgMatches` into a `GlobalConfig`
+    pub fn from_arguments(matches: ArgMatches) -> Self {
+        // get verbosity level or set highest in case of failure
+        let verbosity = match matches.value_of("verbosity") {
+            Some("debug") => LogLevelFilter::Debug,
+            Some("info") => LogLevelFilter::Info,
+            Some("error") => LogLevelFilter::Error,
+            Some("off") => LogLevelFilter::Off,
+            // if something went wrong during parsing, we use the most verbose level
+            _ => LogLevelFilter::Trace,
+        };
+
+        // all relative paths, also those in experiment files, are resolved against the working
+        // directory
+        if let Some(dir) = matches.value_of("workdir") {
+            // like the arguments clap rejects, an invalid directory ends the program with a message
+            env::set_current_dir(dir).unwrap_or_else(|e| {
+                let msg = format!("Unable to change into working directory {}: {}", dir, e);
+                clap::Error::with_description(&msg, ErrorKind::InvalidValue).exit()
+            });
+        }
+        let model_dir = PathBuf::from(matches.value_of("model_dir").unwrap());
+
+        // learn and tune can also take their settings, including the global ones, from an
+        // experiment file
+        let learn_settings = matches.subcommand_matches("learn")
+            .map(|sub_matches| merge_settings(&matches, sub_matches));
+        let tune_settings = matches.subcommand_matches("tune")
+            .map(|sub_matches| merge_settings(&matches, sub_matches));
+        let global_value = |arg| {
+            learn_settings.as_ref()
+                .or(tune_settings.as_ref())
+                .and_then(|settings| settings.value(arg))
+                .or_else(|| matches.value_of(arg))
+        };
+        let global = |arg| global_value(arg).unwrap();
+
+        let s_file = global("save_file");
+        let threshold = global("threshold").parse().unwrap();
+        let datatype = global("datatype");
+
+        // create the learning configuration
+        let learn_config = learn_settings.as_ref()
+            .map(|settings| LearningConfig::from_settings(settings, &model_dir));
+
+        // create the tuning configuration, the searched values are checked right away
+        let tune_config = matches.subcommand_matches("tune").map(|sub_matches| {
+            let settings = tune_settings.clone().unwrap();
+            let space = SearchSpace::parse(sub_matches.values_of("search").unwrap())
+                .unwrap_or_else(|e| panic!("Unable to parse search space: {}", e));
+            for trial in space.validation_trials() {
+                LearningConfig::from_settings(&space.apply(&trial, &settings), &model_dir);
+            }
+            let test_size: usize = settings.value("test_data_size").unwrap().parse().unwrap();
+            let folds = sub_matches.value_of("folds")
+                .map(|folds| folds.parse().expect("Unable to parse number of folds!"));
+            match folds {
+                Some(folds) if folds < 2 => panic!("Cross-validation needs at least two folds"),
+                None if test_size == 0 => panic!("Tuning needs validation data, see --testsize"),
+                _ => {}
+            }
+            TuneConfig {
+                search: match sub_matches.value_of("method").unwrap() {
+                    "random" => {
+                        Search::Random(sub_matches.value_of("trials").unwrap().parse().unwrap())
+                    }
+                    _ => Search::Grid(sub_matches.value_of("steps").unwrap().parse().unwrap()),
+                },
+                settings: settings,
+                space: space,
+                folds: folds,
+                test_size: test_size,
+                seed: sub_matches.value_of("seed").unwrap().parse().unwrap(),
+                leaderboard: sub_matches.value_of("leaderboard").unwrap().to_string(),
+                model_dir: model_dir.clone(),
+            }
+        });
+
+        // create the gradient check configuration
+        let gradcheck_config = matches.subcommand_matches("gradcheck").map(|sub_matches| {
+            GradCheckConfig {
+                topology: Topology::parse(sub_matches.values_of("topology").unwrap())
+                    .unwrap_or_else(|e| panic!("Unable to parse topology: {}", e)),
+                samples: sub_matches.value_of("samples").unwrap().parse().unwrap(),
+                batch_norm: sub_matches.is_present("batch_norm"),
+                output: sub_matches.value_of("output")
+                    .unwrap()
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Unable to parse output activation: {}", e)),
+                cost: sub_matches.value_of("cost")
+                    .unwrap()
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Unable to parse cost function: {}", e)),
+                epsilon: sub_matches.value_of("epsilon").unwrap().parse().unwrap(),
+                tolerance: sub_matches.value_of("tolerance").unwrap().parse().unwrap(),
+                precision: sub_matches.value_of("precision").unwrap().parse().unwrap(),
+            }
+        });
+
+        // create the report configuration
+        let report_config = matches.subcommand_matches("report").map(|sub_matches| {
+            ReportConfig { out_dir: sub_matches.value_of("out_dir").unwrap().to_string() }
+        });
+
+        // determine which dataset to use
+        // if we add other datasets here, we also need to implement a Datatype for it
+        // and add it to the possible values in clap (read_arguments())
+        let data = match datatype {
+            "flower" => input::parse_data::<Flower>(global("data")),
+            "mnist" => input::parse_data::<Mnist>(global("data")),
+            "sequence" => input::parse_sequences(global("data")),
+            "regression" => input::parse_regression(global("data")),
+            "multilabel" => {
+                let labels = global_value("labels")
+                    .expect("Multi-label data needs the number of label columns, see --labels")
+                    .parse()
+                    .expect("Unable to parse number of labels!");
+                input::parse_multi_label(global("data"), labels)
+            }
+            // clap checks the datatypes given on the command line, but not those in files
+            datatype => panic!("Unknown datatype: {}", datatype),
+        };
+
+        GlobalConfig {
+            verbosity: verbosity,
+            save_file: model_dir.join(s_file),
+            log_dir: PathBuf::from(matches.value_of("log_dir").unwrap()),
+            model_dir: model_dir,
+            data_file: PathBuf::from(global("data")),
+            data: data,
+            threshold: threshold,
+            learn_config: learn_config,
+            tune_config: tune_config,
+            gradcheck_config: gradcheck_config,
+            report_config: report_config,
+        }
+    }
+}
+
+// The settings of `learn`, or of the trials of `tune`: the values given on the command line, else
+// those in the experiment file given with `--config`, else the defaults of clap
+fn merge_settings(matches: &ArgMatches, sub_matches: &ArgMatches) -> Experiment {
+    let file = sub_matches.value_of("config").map(|path| {
+        Experiment::from_file(path)
+            .unwrap_or_else(|e| panic!("Unable to read config file {}: {}", path, e))
+    });
+    // flags have no values, they are `true` if present
+    let values_of = |m: &ArgMatches, arg| {
+        let values: Vec<String> = m.values_of(arg)
+            .into_iter()
+            .flat_map(|values| values.map(|s| s.to_string()))
+            .collect();
+        if !values.is_empty() {
+            Some(values)
+        } else if m.is_present(arg) {
+            Some(vec!["true".to_string()])
+        } else {
+            None
+        }
+    };
+
+    let mut settings = Experiment::default();
+    for arg in Experiment::args() {
+        // global arguments are given before the subcommand
+        let given = [sub_matches, matches].iter().cloned().find(|m| m.occurrences_of(arg) > 0);
+        let from_file = file.as_ref()
+            .and_then(|f| f.values(arg))
+            .map(|values| values.iter().map(|s| s.to_string()).collect());
+        let values = match (given, from_file) {
+            (Some(m), _) => values_of(m, arg),
+            (None, Some(values)) => Some(values),
+            (None, None) => values_of(sub_matches, arg).or_else(|| values_of(matches, arg)),
+        };
+        if let Some(values) = values {
+            settings.set(arg, values);
+        }
+    }
+    // multi-label data is learned with independent sigmoid outputs and the binary cross-entropy
+    if settings.value("cost").is_none() {
+        let cost = if settings.value("datatype") == Some("multilabel") {
+            "bce"
+        } else {
+            "mse"
+        };
+        settings.set("cost", vec![cost.to_string()]);
+    }
+    settings
+}
+
+#[test]
+fn test_workdir() {
+    use rustle_my_net::input::util::absolute;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    let workdir = env::temp_dir().join("rustle-my-net-workdir");
+    fs::create_dir_all(&workdir).unwrap();
+    // the temporary directory may be behind a symbolic link, the working directory is not
+    let workdir = workdir.canonicalize().unwrap();
+    File::create(workdir.join("experiment.toml"))
+        .and_then(|mut file| {
+            file.write_all(b"[data]\npath = \"flowers.txt\"\n[network]\ntopology = \"4 5 3\"\n")
+        })
+        .unwrap();
+    let cwd = env::current_dir().unwrap();
+
+    let matches = app().get_matches_from(vec!["rustle-my-net",
+                                              "--workdir",
+                                              workdir.to_str().unwrap(),
+                                              "--model-dir",
+                                              "models",
+                                              "--log-dir",
+                                              "../logs",
+                                              "learn",
+                                              "--config",
+                                              "experiment.toml"]);
+    let config = GlobalConfig::from_arguments(matches);
+    let resolved = (absolute(&config.model_dir),
+                    absolute(&config.save_file),
+                    absolute(&config.log_dir),
+                    absolute(&config.data_file));
+    env::set_current_dir(cwd).unwrap();
+    fs::remove_dir_all(&workdir).unwrap();
+
+    assert_eq!(resolved.0, workdir.join("models"));
+    assert_eq!(resolved.1, workdir.join("models").join(DEFAULT_SAVE_FILE));
+    assert_eq!(resolved.2, workdir.join("../logs"));
+    // the experiment file and the data file it names are found in the working directory
+    assert_eq!(resolved.3, workdir.join("flowers.txt"));
+    assert_eq!(config.learn_config.unwrap().topology.to_string(), "4 5 3");
+}
@@ -1,75 +1,10 @@
-use input;
-use input::clap::ArgMatches;
-use log::LogLevelFilter;
-use std::io;
-use structs::Data;
-use structs::flower::Flower;
-use structs::mnist::Mnist;
+//! The settings of the training, tuning, gradient checking and reporting runs
 
-/// Represents a configuration from command line arguments
-#[derive(Debug)]
-pub struct GlobalConfig {
-    /// Holds level of verbosity for output (`Debug`, `Info`, `Error` or `Off`)
-    pub verbosity: LogLevelFilter,
-    /// Path to where saved network object is located,
-    /// will currently default to "model_state.ser" if not specified
-    pub save_file: String,
-    /// The actual data that the network will use to learn / classify
-    pub data: Result<Vec<Data>, io::Error>,
-    /// Hyperparameters for network learning
-    pub learn_config: Option<LearningConfig>,
-}
-
-impl GlobalConfig {
-    /// Parse the given `ArgMatches` into a `GlobalConfig`
-    pub fn from_arguments<'a>(matches: ArgMatches) -> Self {
-        // get verbosity level or set highest in case of failure
-        let verbosity = match matches.value_of("verbosity") {
-            Some("debug") => LogLevelFilter::Debug,
-            Some("info") => LogLevelFilter::Info,
-            Some("error") => LogLevelFilter::Error,
-            Some("off") => LogLevelFilter::Off,
-            // if something went wrong during parsing, we use the most verbose level
-            _ => LogLevelFilter::Trace,
-        };
-
-        let s_file = matches.value_of("save_file").unwrap();
-
-        // create the learning configuration
-        let learn_config = matches.subcommand_matches("learn").map(|sub_matches| {
-            LearningConfig {
-                learning_rate: sub_matches.value_of("learning_rate").unwrap().parse().unwrap(),
-                epochs: sub_matches.value_of("epochs").unwrap().parse().unwrap(),
-                batch_size: sub_matches.value_of("mini_batch_size").unwrap().parse().unwrap(),
-                init_vec: {
-                    sub_matches.values_of("topology")
-                        .unwrap()
-                        .map(|s| s.parse().expect("Unable to parse topology vector!"))
-                        .collect()
-
-                },
-                test_size: sub_matches.value_of("test_data_size").unwrap().parse().unwrap(),
-                save_file: s_file.to_string(),
-            }
-        });
-
-        // determine which dataset to use
-        // if we add other datasets here, we also need to implement a Datatype for it
-        // and add it to the possible values in clap (input::read_arguments())
-        let data = match matches.value_of("datatype").unwrap() {
-            "flower" => input::parse_data::<Flower>(matches.value_of("data").unwrap()),
-            "mnist" => input::parse_data::<Mnist>(matches.value_of("data").unwrap()),
-            _ => unreachable!(),
-        };
-
-        GlobalConfig {
-            verbosity: verbosity,
-            save_file: s_file.to_string(),
-            data: data,
-            learn_config: learn_config,
-        }
-    }
-}
+use input::experiment::Experiment;
+use input::search::SearchSpace;
+use nn::{Activation, Augmentation, Cost, Precision, Topology};
+use nn::learning::{Divergence, Resampling};
+use std::path::{Path, PathBuf};
 
 /// The hyperparameters used for nn-learning.
 ///
@@ -82,7 +17,7 @@ pub struct LearningConfig {
     /// Influences speed and success of the learning progress. High values increase speed, too high
     /// values will result in the network not being able to learn. In most cases covered in this NN
     /// the value should be <1.
-    pub learning_rate: f32,
+    pub learning_rate: f64,
     /// Number of learning epochs. Higher values yield better results by increasing
     /// total training time. Setting this too high might result in
     /// overfitting on your training data.
@@ -91,13 +26,220 @@ pub struct LearningConfig {
     /// will result in slower networks, too small samples will result in bad gradients and thus
     /// badly influence learning success. In all examples covered by this net the size was ~10.
     pub batch_size: u32,
-    /// Network topology: holds number of neurons in each layer (including in- and output layer).
-    /// Example: [4, 5, 3] will result in a network with 4 input neurons, 5 neurons in a hidden
-    /// layer, and three output neurons.
-    pub init_vec: Vec<u32>,
+    /// Network topology: the shape of the input and the layers of the network, see
+    /// `nn::topology`. Example: `4 5 3` will result in a network with 4 input neurons, 5 neurons
+    /// in a hidden layer, and three output neurons.
+    pub topology: Topology,
     /// Number of samples to use for testing. Larger number of samples (especially when you don't
     /// have many samples in total) will decrease learning success.
     pub test_size: usize,
-    /// Path to where the nn is to be loaded from
-    pub save_file: String,
+    /// Dropout keep probabilities of the hidden layers, either one for all hidden layers or one
+    /// per hidden layer. Lower values switch off more neurons during training, which reduces
+    /// overfitting of wide hidden layers. 1.0 disables dropout.
+    pub keep_probs: Vec<f64>,
+    /// Dropout keep probability of the input layer, 1.0 disables dropout.
+    pub input_keep_prob: f64,
+    /// Whether the hidden layers use batch normalization, which speeds up learning of deep
+    /// networks.
+    pub batch_norm: bool,
+    /// Number of steps the error of recurrent layers is backpropagated through time, 0 means
+    /// the whole sequence. Shorter lengths speed up learning of long sequences.
+    pub bptt_length: usize,
+    /// Activation function of the output layer, `Linear` for regression.
+    pub output: Activation,
+    /// Cost function that is minimized. The quadratic cost suits most problems, the absolute
+    /// error and the Huber loss are less sensitive to outliers in regression targets.
+    pub cost: Cost,
+    /// Largest absolute value of each element of the gradients, `None` disables clipping by
+    /// value.
+    pub clip_value: Option<f64>,
+    /// Largest global L2 norm of the gradients of all layers, `None` disables clipping by norm.
+    /// Clipping keeps single mini batches with huge gradients from throwing deep or recurrent
+    /// networks off course.
+    pub clip_norm: Option<f64>,
+    /// What happens when the training diverges, i.e. costs, gradients or weights are no longer
+    /// finite numbers.
+    pub divergence: Divergence,
+    /// Outputs above the threshold count as predicted labels when evaluating multi-label data.
+    pub threshold: f64,
+    /// Weights of the cost of each class. Higher weights for rare classes keep the network from
+    /// ignoring them on imbalanced data.
+    pub class_weights: ClassWeights,
+    /// How the training data is resampled in each epoch to balance the classes.
+    pub resampling: Resampling,
+    /// Random transformations of the training images in each mini batch, see `nn::augment`.
+    /// More varied images reduce overfitting on small image datasets.
+    pub augmentation: Option<Augmentation>,
+    /// Floating point precision the network is trained in. `f64` is slower but more exact, which
+    /// helps when debugging.
+    pub precision: Precision,
+    /// Path the per-epoch training history is written to, as JSON if it ends in `.json` and as
+    /// CSV otherwise.
+    pub history_file: Option<String>,
+    /// Path of an HTML page that summarizes the run: the settings, the data, the learning curves
+    /// and the evaluation on the test data.
+    pub report_file: Option<String>,
+    /// Path to where the nn is saved to, in the model directory
+    pub save_file: PathBuf,
+    /// All settings of the run in the layout of an experiment file, they are saved next to the
+    /// network so the run can be repeated.
+    pub experiment: Experiment,
+}
+
+impl LearningConfig {
+    /// Create the configuration of `learn` from its settings, which need to include the global
+    /// ones. The network is saved in `model_dir`. Panics if a setting is missing or invalid.
+    pub fn from_settings(settings: &Experiment, model_dir: &Path) -> LearningConfig {
+        let datatype = settings.value("datatype").unwrap();
+        LearningConfig {
+            learning_rate: settings.value("learning_rate").unwrap().parse().unwrap(),
+            epochs: settings.value("epochs").unwrap().parse().unwrap(),
+            batch_size: match settings.value("mini_batch_size").unwrap().parse() {
+                Ok(size) if size > 0 => size,
+                _ => {
+                    panic!("The mini batch size has to be a positive integer, got {}",
+                           settings.value("mini_batch_size").unwrap())
+                }
+            },
+            topology: Topology::parse(settings.values("topology")
+                    .expect("A topology is required, either with --topology or in the \
+                             config file!"))
+                .unwrap_or_else(|e| panic!("Unable to parse topology: {}", e)),
+            test_size: settings.value("test_data_size").unwrap().parse().unwrap(),
+            keep_probs: {
+                settings.values("keep_prob")
+                    .unwrap()
+                    .iter()
+                    .map(|s| s.parse().expect("Unable to parse keep probabilities!"))
+                    .collect()
+            },
+            input_keep_prob: settings.value("input_keep_prob").unwrap().parse().unwrap(),
+            batch_norm: settings.is_present("batch_norm"),
+            bptt_length: settings.value("bptt").unwrap().parse().unwrap(),
+            output: settings.value("output")
+                .unwrap()
+                .parse()
+                .unwrap_or_else(|e| panic!("Unable to parse output activation: {}", e)),
+            cost: settings.value("cost")
+                .unwrap()
+                .parse()
+                .unwrap_or_else(|e| panic!("Unable to parse cost function: {}", e)),
+            clip_value: settings.value("clip_value")
+                .map(|s| parse_positive(s, "gradient clipping value")),
+            clip_norm: settings.value("clip_norm")
+                .map(|s| parse_positive(s, "gradient clipping norm")),
+            divergence: settings.value("on_divergence")
+                .unwrap()
+                .parse()
+                .unwrap_or_else(|e| panic!("Unable to parse divergence handling: {}", e)),
+            threshold: settings.value("threshold").unwrap().parse().unwrap(),
+            class_weights: match settings.values("class_weights") {
+                None => ClassWeights::Equal,
+                Some(ref values) if values[..] == ["auto"] => ClassWeights::InverseFrequency,
+                Some(values) => {
+                    ClassWeights::Manual(values.iter()
+                        .map(|s| s.parse().expect("Unable to parse class weights!"))
+                        .collect())
+                }
+            },
+            resampling: settings.value("resample").unwrap().parse().unwrap(),
+            augmentation: settings.values("augment").map(|tokens| {
+                if datatype != "mnist" {
+                    panic!("Augmentation is only supported for mnist data");
+                }
+                let mut augmentation = Augmentation::parse(tokens)
+                    .unwrap_or_else(|e| panic!("Unable to parse augmentation: {}", e));
+                augmentation.seed = settings.value("augment_seed")
+                    .unwrap()
+                    .parse()
+                    .expect("Unable to parse augmentation seed!");
+                augmentation
+            }),
+            precision: settings.value("precision").unwrap().parse().unwrap(),
+            history_file: settings.value("history").map(|s| s.to_string()),
+            report_file: settings.value("report").map(|s| s.to_string()),
+            save_file: model_dir.join(settings.value("save_file").unwrap()),
+            experiment: settings.clone(),
+        }
+    }
+}
+
+// Parses a setting that has to be a positive number, panics with the `name` of the setting
+// otherwise
+fn parse_positive(value: &str, name: &str) -> f64 {
+    match value.parse::<f64>() {
+        Ok(x) if x > 0.0 => x,
+        _ => panic!("The {} has to be a positive number, got {}", name, value),
+    }
+}
+
+/// How the costs of the classes are weighted during learning
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassWeights {
+    /// every sample has the same weight
+    Equal,
+    /// the weights are inversely proportional to the frequencies of the classes in the training
+    /// data
+    InverseFrequency,
+    /// one given weight per class
+    Manual(Vec<f64>),
+}
+
+/// Settings for searching the hyperparameters of `learn`
+#[derive(Debug, Clone)]
+pub struct TuneConfig {
+    /// Settings of `learn` that are used by every trial unless they are searched
+    pub settings: Experiment,
+    /// The searched settings and the values they are searched in
+    pub space: SearchSpace,
+    /// How the search space is searched
+    pub search: Search,
+    /// Number of folds every trial is cross-validated on, `None` to validate on the last
+    /// `test_size` samples
+    pub folds: Option<usize>,
+    /// Number of samples the trials are validated on without cross-validation
+    pub test_size: usize,
+    /// Seed of random search and of the shuffling of the data before it is split
+    pub seed: usize,
+    /// Path the results of all trials are written to as CSV
+    pub leaderboard: String,
+    /// Directory the network of the best settings is saved to
+    pub model_dir: PathBuf,
+}
+
+/// How the trials of `tune` are chosen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Search {
+    /// every combination of values, every range is split into the given number of values
+    Grid(usize),
+    /// the given number of trials with random values
+    Random(usize),
+}
+
+/// Settings for comparing the gradients of backpropagation with numerical derivatives
+#[derive(Debug, Clone)]
+pub struct GradCheckConfig {
+    /// Topology of the random networks that are checked
+    pub topology: Topology,
+    /// Number of random samples in the batch the gradients are checked for
+    pub samples: usize,
+    /// Whether the hidden layers of the checked networks use batch normalization
+    pub batch_norm: bool,
+    /// Activation function of the output layer of the checked networks
+    pub output: Activation,
+    /// Cost function whose gradients are checked
+    pub cost: Cost,
+    /// Step size of the central finite differences
+    pub epsilon: f64,
+    /// Largest relative error between analytic and numerical derivatives that is accepted
+    pub tolerance: f64,
+    /// Floating point precision of the check, `f32` is too inexact for reliable results
+    pub precision: Precision,
+}
+
+/// Settings for drawing the charts of a trained network
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    /// Directory the charts are written to, it is created if it does not exist
+    pub out_dir: String,
 }
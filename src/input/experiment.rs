@@ -0,0 +1,324 @@
+//! Experiment files that hold the settings of `learn`, so runs can be repeated exactly.
+//!
+//! An experiment file is written in TOML, or in JSON if its name ends in `.json`, and may set any
+//! of the following values. Settings given on the command line take precedence over the file.
+//!
+//! ```toml
+//! [data]
+//! type = "mnist"
+//! path = "data/mnist_train.csv"
+//! test_size = 1000
+//! labels = 3  # the number of label columns of multi-label data
+//!
+//! [preprocessing]
+//! augment = ["shift:2", "rotate:10"]
+//! augment_seed = 7
+//! class_weights = "auto"
+//! resample = "none"
+//!
+//! [network]
+//! topology = "28x28x1 conv:8:5:1:2 maxpool:2 100 10"
+//! output = "sigmoid"
+//! batch_norm = true
+//! keep_prob = [0.8]
+//! input_keep_prob = 1.0
+//! bptt = 0
+//! precision = "f32"
+//! threshold = 0.5
+//!
+//! [optimizer]
+//! eta = 0.5
+//! cost = "mse"
+//! batch_size = 32
+//! on_divergence = "abort"
+//! clip_value = 5.0
+//! clip_norm = 1.0
+//!
+//! [schedule]
+//! epochs = 30
+//!
+//! [output]
+//! model = "mnist.ser"
+//! history = "history.csv"
+//! report = "report.html"
+//! ```
+
+extern crate serde_json;
+extern crate toml;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// The values of an experiment file: the section and key they are found at, the name of the
+// command line argument they set, whether the argument takes a list of values and the TOML type
+// they are written as. A string is split at whitespace into such a list.
+const SETTINGS: [(&'static str, &'static str, &'static str, bool, Kind); 26] = [
+    ("data", "type", "datatype", false, Kind::Text),
+    ("data", "path", "data", false, Kind::Text),
+    ("data", "test_size", "test_data_size", false, Kind::Integer),
+    ("data", "labels", "labels", false, Kind::Integer),
+    ("preprocessing", "augment", "augment", true, Kind::Text),
+    ("preprocessing", "augment_seed", "augment_seed", false, Kind::Integer),
+    ("preprocessing", "class_weights", "class_weights", true, Kind::Float),
+    ("preprocessing", "resample", "resample", false, Kind::Text),
+    ("network", "topology", "topology", true, Kind::Text),
+    ("network", "output", "output", false, Kind::Text),
+    ("network", "batch_norm", "batch_norm", false, Kind::Flag),
+    ("network", "keep_prob", "keep_prob", true, Kind::Float),
+    ("network", "input_keep_prob", "input_keep_prob", false, Kind::Float),
+    ("network", "bptt", "bptt", false, Kind::Integer),
+    ("network", "precision", "precision", false, Kind::Text),
+    ("network", "threshold", "threshold", false, Kind::Float),
+    ("optimizer", "eta", "learning_rate", false, Kind::Float),
+    ("optimizer", "cost", "cost", false, Kind::Text),
+    ("optimizer", "batch_size", "mini_batch_size", false, Kind::Integer),
+    ("optimizer", "on_divergence", "on_divergence", false, Kind::Text),
+    ("optimizer", "clip_value", "clip_value", false, Kind::Float),
+    ("optimizer", "clip_norm", "clip_norm", false, Kind::Float),
+    ("schedule", "epochs", "epochs", false, Kind::Integer),
+    ("output", "model", "save_file", false, Kind::Text),
+    ("output", "history", "history", false, Kind::Text),
+    ("output", "report", "report", false, Kind::Text),
+];
+
+// The TOML type of a setting, values that do not parse as it are written as strings
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Text,
+    Integer,
+    Float,
+    Flag,
+}
+
+/// The settings of an experiment, stored as the values of their command line arguments
+///
+/// Flags like `batch_norm` have the value `true` or `false`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Experiment {
+    values: BTreeMap<&'static str, Vec<String>>,
+}
+
+impl Experiment {
+    /// Read an experiment file, as JSON if the name ends in `.json` and as TOML otherwise
+    pub fn from_file(path: &str) -> Result<Experiment, io::Error> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+        if path.ends_with(".json") {
+            Experiment::parse_json(&content)
+        } else {
+            Experiment::parse_toml(&content)
+        }
+    }
+
+    /// Parse the settings from TOML, unknown sections and keys are an error
+    pub fn parse_toml(content: &str) -> Result<Experiment, io::Error> {
+        let mut parser = toml::Parser::new(content);
+        match parser.parse() {
+            Some(table) => Experiment::from_table(&table),
+            None => {
+                let error = &parser.errors[0];
+                let (line, column) = parser.to_linecol(error.lo);
+                Err(invalid(format!("line {}, column {}: {}", line + 1, column + 1, error.desc)))
+            }
+        }
+    }
+
+    /// Parse the settings from JSON, an object with one object per section
+    pub fn parse_json(content: &str) -> Result<Experiment, io::Error> {
+        let json: serde_json::Value = serde_json::from_str(content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match json_to_toml(json) {
+            Some(toml::Value::Table(table)) => Experiment::from_table(&table),
+            _ => Err(invalid("the experiment needs to be an object of sections".to_string())),
+        }
+    }
+
+    // Read the settings from the sections of a table
+    fn from_table(table: &toml::Table) -> Result<Experiment, io::Error> {
+        let mut experiment = Experiment::default();
+        for (section, keys) in table {
+            let keys = keys.as_table()
+                .ok_or_else(|| invalid(format!("'{}' needs to be a section", section)))?;
+            for (key, value) in keys {
+                let setting = SETTINGS.iter()
+                    .find(|s| s.0 == section && s.1 == key)
+                    .ok_or_else(|| invalid(format!("unknown setting '{}.{}'", section, key)))?;
+                let values = match (value, setting.3) {
+                    (&toml::Value::Array(ref values), true) => {
+                        values.iter().map(scalar).collect::<Option<Vec<_>>>()
+                    }
+                    (&toml::Value::String(ref s), true) => {
+                        Some(s.split_whitespace().map(|s| s.to_string()).collect())
+                    }
+                    (value, _) => scalar(value).map(|s| vec![s]),
+                };
+                let values = values.ok_or_else(|| {
+                        invalid(format!("invalid value of '{}.{}'", section, key))
+                    })?;
+                experiment.set(setting.2, values);
+            }
+        }
+        Ok(experiment)
+    }
+
+    /// The first value of the argument `arg`, if it is set
+    pub fn value(&self, arg: &str) -> Option<&str> {
+        self.values.get(arg).and_then(|values| values.first()).map(|s| s.as_str())
+    }
+
+    /// All values of the argument `arg`, if it is set
+    pub fn values(&self, arg: &str) -> Option<Vec<&str>> {
+        self.values.get(arg).map(|values| values.iter().map(|s| s.as_str()).collect())
+    }
+
+    /// Whether the flag `arg` is set to `true`
+    pub fn is_present(&self, arg: &str) -> bool {
+        self.value(arg) == Some("true")
+    }
+
+    /// Set the values of the argument `arg`, arguments that cannot be in an experiment file are
+    /// ignored
+    pub fn set(&mut self, arg: &str, values: Vec<String>) {
+        if let Some(setting) = SETTINGS.iter().find(|s| s.2 == arg) {
+            self.values.insert(setting.2, values);
+        }
+    }
+
+    /// The names of all arguments that can be set in an experiment file
+    pub fn args() -> Vec<&'static str> {
+        SETTINGS.iter().map(|s| s.2).collect()
+    }
+
+    /// Look up the setting with the key `key`, optionally preceded by its section like in
+    /// `optimizer.eta`. Returns its section, the name of its argument and whether the argument
+    /// takes a list of values.
+    pub fn setting(key: &str) -> Option<(&'static str, &'static str, bool)> {
+        SETTINGS.iter()
+            .find(|s| s.1 == key || format!("{}.{}", s.0, s.1) == key)
+            .map(|s| (s.0, s.2, s.3))
+    }
+
+    /// Write the settings as TOML in the layout of an experiment file
+    pub fn to_toml(&self) -> String {
+        let mut sections = toml::Table::new();
+        for &(section, key, arg, multiple, kind) in SETTINGS.iter() {
+            let values = match self.values.get(arg) {
+                Some(values) if !values.is_empty() => values,
+                _ => continue,
+            };
+            let value = if arg == "topology" {
+                toml::Value::String(values.join(" "))
+            } else if multiple {
+                // a TOML array has a single type, so one odd value turns the whole list into
+                // strings
+                let strings = || values.iter().map(|v| toml::Value::String(v.clone())).collect();
+                toml::Value::Array(values.iter()
+                    .map(|v| typed(v, kind))
+                    .collect::<Option<_>>()
+                    .unwrap_or_else(strings))
+            } else {
+                typed(&values[0], kind).unwrap_or_else(|| toml::Value::String(values[0].clone()))
+            };
+            let section = sections.entry(section.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let toml::Value::Table(ref mut keys) = *section {
+                keys.insert(key.to_string(), value);
+            }
+        }
+        toml::Value::Table(sections).to_string()
+    }
+
+    /// Write the settings as a TOML experiment file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        File::create(path)?.write_all(self.to_toml().as_bytes())
+    }
+}
+
+// An error about the content of an experiment file
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// A single value as it would be given on the command line, `None` for arrays and tables
+fn scalar(value: &toml::Value) -> Option<String> {
+    match *value {
+        toml::Value::String(ref s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// A command line value as a TOML value of the type `kind`, `None` if it does not parse as one
+fn typed(value: &str, kind: Kind) -> Option<toml::Value> {
+    match kind {
+        Kind::Text => Some(toml::Value::String(value.to_string())),
+        Kind::Integer => value.parse().ok().map(toml::Value::Integer),
+        Kind::Float => value.parse().ok().map(toml::Value::Float),
+        Kind::Flag => value.parse().ok().map(toml::Value::Boolean),
+    }
+}
+
+// Convert JSON into the equivalent TOML, `None` for `null`
+fn json_to_toml(json: serde_json::Value) -> Option<toml::Value> {
+    match json {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(b) => Some(toml::Value::Boolean(b)),
+        serde_json::Value::Number(n) => {
+            n.as_i64().map(toml::Value::Integer).or_else(|| n.as_f64().map(toml::Value::Float))
+        }
+        serde_json::Value::String(s) => Some(toml::Value::String(s)),
+        serde_json::Value::Array(values) => {
+            values.into_iter().map(json_to_toml).collect::<Option<_>>().map(toml::Value::Array)
+        }
+        serde_json::Value::Object(map) => {
+            map.into_iter()
+                .map(|(k, v)| json_to_toml(v).map(|v| (k, v)))
+                .collect::<Option<_>>()
+                .map(toml::Value::Table)
+        }
+    }
+}
+
+
+#[test]
+fn test_experiment_files() {
+    let experiment = Experiment::parse_toml("[network]\ntopology = \"4 5 3\"\nbatch_norm = true\n\
+                                             keep_prob = [0.5, 0.8]\n[optimizer]\neta = 0.1\n")
+        .unwrap();
+    assert_eq!(experiment.values("topology"), Some(vec!["4", "5", "3"]));
+    assert_eq!(experiment.values("keep_prob"), Some(vec!["0.5", "0.8"]));
+    assert_eq!(experiment.value("learning_rate"), Some("0.1"));
+    assert!(experiment.is_present("batch_norm"));
+
+    // the same settings in JSON, and written back into TOML
+    let json = Experiment::parse_json("{\"network\": {\"topology\": \"4 5 3\", \"batch_norm\": \
+                                       true, \"keep_prob\": [0.5, 0.8]}, \"optimizer\": \
+                                       {\"eta\": 0.1}}")
+        .unwrap();
+    assert_eq!(json, experiment);
+    assert_eq!(Experiment::parse_toml(&experiment.to_toml()).unwrap(), experiment);
+
+    assert!(Experiment::parse_toml("[network]\nsize = 3\n").is_err());
+    assert!(Experiment::parse_toml("[network\n").is_err());
+}
+
+#[test]
+fn test_experiment_round_trip() {
+    // values as the command line gives them, written and read back with the same meaning
+    let mut experiment = Experiment::default();
+    experiment.set("keep_prob", vec!["0.5".to_string(), "1".to_string()]);
+    experiment.set("class_weights", vec!["auto".to_string()]);
+    experiment.set("save_file", vec!["1".to_string()]);
+    experiment.set("mini_batch_size", vec!["10".to_string()]);
+    experiment.set("learning_rate", vec!["3".to_string()]);
+    experiment.set("batch_norm", vec!["true".to_string()]);
+    experiment.set("topology", vec!["4".to_string(), "5".to_string(), "3".to_string()]);
+    let toml = experiment.to_toml();
+    assert!(toml.contains("keep_prob = [0.5, 1.0]"), "{}", toml);
+    assert!(toml.contains("model = \"1\""), "{}", toml);
+    assert_eq!(Experiment::parse_toml(&toml).unwrap(), experiment);
+}
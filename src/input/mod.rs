@@ -1,97 +1,29 @@
+//! Reading datasets from files and the settings of runs
+
 pub mod config;
+pub mod experiment;
+pub mod search;
 pub mod util;
 
-extern crate clap;
-
 use std::convert;
 use std::fs::File;
 use std::fmt::Debug;
 use std::io::{self, BufReader, BufRead};
 use std::iter::FromIterator;
-use std::path::Path;
 use std::str::FromStr;
+use na::DVector;
 use structs::Data;
-
-use self::clap::{App, AppSettings, Arg, SubCommand};
-
-const DEFAULT_SAVE_FILE: &'static str = "model_state.ser";
-
-/// Reads the arguments given to this program at execution and returns them
-pub fn read_arguments() -> config::GlobalConfig {
-    let matches = App::new("rustle my net")
-        .subcommand(SubCommand::with_name("learn")
-            .arg(Arg::with_name("topology")
-                .long("topology")
-                .short("t")
-                .help("A list of values representing the topology of the neural network. For \
-                       example, the input '-t 4 5 3' would create a network with: 4 nodes in \
-                       the input layer, a single hidden layer of 5 nodes and 3 nodes in the \
-                       output layer.")
-                .multiple(true)
-                .value_delimiter(" ")
-                .required(true)
-                .min_values(3))
-            .arg(Arg::with_name("learning_rate")
-                .long("eta")
-                .takes_value(true)
-                .help("The learning rate eta. Should be between 0.0 and 1.0. Default is 0.05.")
-                .default_value("0.05"))
-            .arg(Arg::with_name("epochs")
-                .long("epochs")
-                .takes_value(true)
-                .help("The number of training epochs. The default value is 100.")
-                .default_value("100"))
-            .arg(Arg::with_name("mini_batch_size")
-                .long("batchsize")
-                .takes_value(true)
-                .help("The size of the mini batches for the learning process. Default: 32.")
-                .default_value("32"))
-            .arg(Arg::with_name("test_data_size")
-                .long("testsize")
-                .takes_value(true)
-                .help("The size of the data that is used for validation. Defaults to 20.")
-                .default_value("20")))
-        .subcommand(SubCommand::with_name("classify"))
-        .setting(AppSettings::SubcommandRequiredElseHelp)
-        .arg(Arg::with_name("verbosity")
-            .long("verbosity")
-            .short("v")
-            .takes_value(true)
-            .possible_values(&["debug", "info", "error", "off"])
-            .default_value("info"))
-        .arg(Arg::with_name("data")
-            .long("data")
-            .short("i")
-            .takes_value(true)
-            .default_value("data/iris_flowers.txt"))
-        .arg(Arg::with_name("datatype")
-            .long("type")
-            .short("d")
-            .takes_value(true)
-            .possible_values(&["flower", "mnist"])
-            .default_value("flower"))
-        .arg(Arg::with_name("save_file")
-            .long("file")
-            .short("f")
-            .takes_value(true)
-            .default_value(DEFAULT_SAVE_FILE))
-        .get_matches();
-
-    config::GlobalConfig::from_arguments(matches)
-}
-
+use structs::regression::Observation;
+use structs::sequence::Sequence;
 
 /// Generically parse data from given input file into a Vec<Data>
-fn parse_data<T>(datafile: &str) -> Result<Vec<Data>, io::Error>
+pub fn parse_data<T>(datafile: &str) -> Result<Vec<Data>, io::Error>
     where T: FromStr + Into<Data>,
           T::Err: convert::From<io::Error> + Debug,
           Result<Vec<T>, T::Err>: FromIterator<Result<T, io::Error>>,
           Result<Vec<T>, io::Error>: FromIterator<Result<T, T::Err>>
 {
-    let mut path = self::util::get_root_dir();
-    path.push(Path::new(datafile));
-
-    let f = File::open(path)?;
+    let f = File::open(datafile)?;
     let reader = BufReader::new(&f);
 
     // read lines, map string to type T, convert T to Data, collect into a vec, return as Result
@@ -100,3 +32,137 @@ fn parse_data<T>(datafile: &str) -> Result<Vec<Data>, io::Error>
         .collect())
 
 }
+
+
+/// Parse sequences from the given input file into a Vec<Data>
+///
+/// Every line holds one sequence, see `Sequence`. All sequences need the same number of steps
+/// and features, the number of classes is the largest class in the file plus one.
+pub fn parse_sequences(datafile: &str) -> Result<Vec<Data>, io::Error> {
+    let f = File::open(datafile)?;
+    let reader = BufReader::new(&f);
+
+    let mut sequences: Vec<Sequence> = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        // allow empty lines, e.g. at the end of the file
+        if line.trim().is_empty() {
+            continue;
+        }
+        let sequence: Sequence = line.parse()?;
+        if let Some(first) = sequences.first() {
+            if sequence.get_steps().len() != first.get_steps().len() ||
+               sequence.get_steps()[0].len() != first.get_steps()[0].len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "All sequences need the same number of steps and \
+                                           features."));
+            }
+        }
+        sequences.push(sequence);
+    }
+
+    let n_classes = sequences.iter().map(|s| s.get_class() as usize + 1).max().unwrap_or(0);
+    Ok(sequences.iter()
+        .map(|s| Data::new_sequence(s.get_steps(), s.get_class(), n_classes))
+        .collect())
+}
+
+
+/// Parse observations with real-valued targets from the given input file into a Vec<Data>
+///
+/// Every line holds one observation, see `Observation`. All observations need the same number
+/// of features and targets.
+pub fn parse_regression(datafile: &str) -> Result<Vec<Data>, io::Error> {
+    Ok(parse_observations(datafile)?.into_iter().map(Data::from).collect())
+}
+
+
+/// Parse multi-label samples from the given CSV file into a Vec<Data>
+///
+/// Every line holds the features, followed by `n_labels` label columns that are 1 if the sample
+/// has the label and 0 otherwise, e.g. `0.5,1.2,3.0,1,0,1` for 3 features and 3 labels. A first
+/// line that does not hold numbers is taken as the header and skipped. All lines need the same
+/// number of columns.
+pub fn parse_multi_label(datafile: &str, n_labels: usize) -> Result<Vec<Data>, io::Error> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let f = File::open(datafile)?;
+    let reader = BufReader::new(&f);
+
+    let mut data: Vec<Data> = Vec::new();
+    let mut n_columns = None;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        // allow empty lines, e.g. at the end of the file
+        if line.trim().is_empty() {
+            continue;
+        }
+        let columns: Vec<f32> = match line.split(',').map(|c| c.trim().parse()).collect() {
+            Ok(columns) => columns,
+            Err(_) if i == 0 => continue,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        if columns.len() <= n_labels {
+            return Err(invalid("Every line needs at least one feature besides the labels."));
+        }
+        if *n_columns.get_or_insert(columns.len()) != columns.len() {
+            return Err(invalid("All lines need the same number of columns."));
+        }
+        let (features, labels) = columns.split_at(columns.len() - n_labels);
+        if labels.iter().any(|&l| l != 0.0 && l != 1.0) {
+            return Err(invalid("Labels need to be either 0 or 1."));
+        }
+        data.push(Data::new_multi_label(DVector::from_slice(features.len(), features),
+                                        DVector::from_slice(n_labels, labels)));
+    }
+    Ok(data)
+}
+
+
+// Parse the observations of the given input file, all of them need the same number of features
+// and targets
+fn parse_observations(datafile: &str) -> Result<Vec<Observation>, io::Error> {
+    let f = File::open(datafile)?;
+    let reader = BufReader::new(&f);
+
+    let mut observations: Vec<Observation> = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        // allow empty lines, e.g. at the end of the file
+        if line.trim().is_empty() {
+            continue;
+        }
+        let observation: Observation = line.parse()?;
+        if let Some(first) = observations.first() {
+            if observation.get_features().len() != first.get_features().len() ||
+               observation.get_targets().len() != first.get_targets().len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "All observations need the same number of features \
+                                           and targets."));
+            }
+        }
+        observations.push(observation);
+    }
+    Ok(observations)
+}
+
+
+#[test]
+fn test_parse_multi_label() {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    let path = env::temp_dir().join("rustle-my-net-multi-label.csv");
+    let parse = |content: &str| {
+        File::create(&path).and_then(|mut f| f.write_all(content.as_bytes())).unwrap();
+        parse_multi_label(path.to_str().unwrap(), 2)
+    };
+    let data = parse("x,y,z,cat,dog\n0.5,1.2,3.0,1,0\n0.1,0.2,0.3,1,1\n\n").unwrap();
+    assert_eq!(data.len(), 2);
+    assert_eq!(data[0].get_input().at, vec![0.5, 1.2, 3.0]);
+    assert_eq!(data[1].get_class_vector().at, vec![1.0, 1.0]);
+    assert!(parse("0.5,1.2,3.0,1,2\n").is_err());
+    assert!(parse("0.5,1.2,3.0,1,0\n0.5,1,0\n0.5,x,0\n").is_err());
+    assert!(parse("1,0\n").is_err());
+    fs::remove_file(&path).unwrap();
+}
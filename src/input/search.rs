@@ -0,0 +1,385 @@
+//! Search spaces of the hyperparameters that `tune` tries.
+//!
+//! Every dimension of a search space is a setting of an experiment file, see `input::experiment`,
+//! and is given as `KEY=VALUES`. The key is the name of the setting, optionally preceded by its
+//! section like in `optimizer.eta`. The values are either
+//!
+//! * a list of values separated by commas, e.g. `batch_size=8,16,32` or
+//!   `topology=4 5 3,4 10 3`,
+//! * a range `MIN..MAX` of numbers, which are integers if both bounds are, e.g. `epochs=10..50`,
+//! * or a range `MIN..MAX:log` whose numbers are spread evenly on a log scale, which suits
+//!   learning rates, e.g. `eta=0.01..1:log`.
+//!
+//! Grid search tries every combination of the values and splits ranges into a number of steps.
+//! Random search draws the value of every dimension independently in each trial.
+
+use input::experiment::Experiment;
+use rand::Rng;
+use std::cmp::Ordering;
+use std::f64;
+use std::io::{self, Write};
+
+/// The values a setting is searched in
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    /// one of the listed values, each of them a list like the values of a command line argument
+    List(Vec<Vec<String>>),
+    /// a number between `min` and `max`, spread evenly on a log scale if `log` is set and rounded
+    /// if `integer` is set
+    Range {
+        min: f64,
+        max: f64,
+        log: bool,
+        integer: bool,
+    },
+}
+
+/// A setting that is searched
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dimension {
+    /// the key of the setting as it was given
+    pub key: String,
+    /// the name of the command line argument of the setting
+    pub arg: &'static str,
+    /// the values that are tried
+    pub values: Values,
+}
+
+/// The settings that are searched and their values
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchSpace {
+    pub dimensions: Vec<Dimension>,
+}
+
+/// The values of a single trial, one for each dimension of the search space in the same order
+pub type Trial = Vec<Vec<String>>;
+
+impl SearchSpace {
+    /// Parse a search space from dimensions like `eta=0.01..1:log`. The settings of the data and
+    /// the output files cannot be searched.
+    pub fn parse<'a, I>(specs: I) -> Result<SearchSpace, &'static str>
+        where I: IntoIterator<Item = &'a str>
+    {
+        let mut dimensions: Vec<Dimension> = Vec::new();
+        for spec in specs {
+            let mut parts = spec.splitn(2, '=');
+            let key = parts.next().unwrap().trim();
+            let values = parts.next().ok_or("Search dimensions need the form KEY=VALUES")?;
+            let (section, arg, multiple) = Experiment::setting(key)
+                .ok_or("Unknown setting in the search space")?;
+            if section == "data" || section == "output" {
+                return Err("Data and output settings cannot be searched");
+            }
+            if dimensions.iter().any(|d| d.arg == arg) {
+                return Err("Every setting can only be searched once");
+            }
+            dimensions.push(Dimension {
+                key: key.to_string(),
+                arg: arg,
+                values: parse_values(values, multiple)?,
+            });
+        }
+        if dimensions.is_empty() {
+            return Err("The search space needs at least one setting");
+        }
+        Ok(SearchSpace { dimensions: dimensions })
+    }
+
+    /// Every combination of the values, ranges are split into `steps` values including both
+    /// bounds
+    pub fn grid(&self, steps: usize) -> Vec<Trial> {
+        let ts: Vec<f64> = (0..steps)
+            .map(|i| if steps > 1 {
+                i as f64 / (steps - 1) as f64
+            } else {
+                0.0
+            })
+            .collect();
+        self.combinations(|min, max, log, integer| {
+            ts.iter().map(|&t| format_number(interpolate(min, max, t, log), integer)).collect()
+        })
+    }
+
+    /// The trials that show whether every value the search can try is valid
+    ///
+    /// These are all combinations of the listed values and of both bounds of each range. Values
+    /// between the bounds are only checked for ranges of fractional numbers, with a value that
+    /// has fraction digits, so settings that only take integers are caught before the search.
+    pub fn validation_trials(&self) -> Vec<Trial> {
+        self.combinations(|min, max, log, integer| {
+            let mut values = vec![format_number(min, integer), format_number(max, integer)];
+            if !integer {
+                // values that are rounded to integers are also checked by the bounds
+                let fractional = [0.5, 0.382, 0.618, 0.1, 0.9]
+                    .iter()
+                    .map(|&t| format_number(interpolate(min, max, t, log), false))
+                    .find(|value| value.contains('.'));
+                values.extend(fractional);
+            }
+            values
+        })
+    }
+
+    // Every combination of the values, `range_values` picks the values of a range from its
+    // bounds, whether it is on a log scale and whether it holds integers
+    fn combinations<F>(&self, range_values: F) -> Vec<Trial>
+        where F: Fn(f64, f64, bool, bool) -> Vec<String>
+    {
+        let mut trials: Vec<Trial> = vec![Vec::new()];
+        for dimension in &self.dimensions {
+            let values = match dimension.values {
+                Values::List(ref values) => values.clone(),
+                Values::Range { min, max, log, integer } => {
+                    let mut values: Vec<Vec<String>> = range_values(min, max, log, integer)
+                        .into_iter()
+                        .map(|value| vec![value])
+                        .collect();
+                    // integer ranges can have fewer distinct values than steps
+                    values.dedup();
+                    values
+                }
+            };
+            trials = trials.iter()
+                .flat_map(|trial| {
+                    values.iter().map(move |value| {
+                        let mut trial = trial.clone();
+                        trial.push(value.clone());
+                        trial
+                    })
+                })
+                .collect();
+        }
+        trials
+    }
+
+    /// Draw a random value of every dimension
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Trial {
+        self.dimensions
+            .iter()
+            .map(|dimension| match dimension.values {
+                Values::List(ref values) => values[rng.gen_range(0, values.len())].clone(),
+                Values::Range { min, max, integer: true, log: false } => {
+                    vec![rng.gen_range(min as i64, max as i64 + 1).to_string()]
+                }
+                Values::Range { min, max, log, integer } => {
+                    vec![format_number(interpolate(min, max, rng.gen::<f64>(), log), integer)]
+                }
+            })
+            .collect()
+    }
+
+    /// The settings of `trial`: `settings` with the values of the trial
+    pub fn apply(&self, trial: &Trial, settings: &Experiment) -> Experiment {
+        let mut settings = settings.clone();
+        for (dimension, values) in self.dimensions.iter().zip(trial) {
+            settings.set(dimension.arg, values.clone());
+        }
+        settings
+    }
+
+    /// The values of `trial` in the form they are given in, e.g. `eta=0.1, batch_size=16`
+    pub fn describe(&self, trial: &Trial) -> String {
+        let values: Vec<String> = self.dimensions
+            .iter()
+            .zip(trial)
+            .map(|(dimension, values)| format!("{}={}", dimension.key, values.join(" ")))
+            .collect();
+        values.join(", ")
+    }
+
+    /// Write the results of the trials as CSV with a header line, one column per dimension
+    ///
+    /// `seed` is the seed the data was split with, it is written to every row so the split of
+    /// each result can be repeated.
+    pub fn write_leaderboard<W: Write>(&self,
+                                       mut writer: W,
+                                       results: &[TrialResult],
+                                       seed: usize)
+                                       -> io::Result<()> {
+        let keys: Vec<&str> = self.dimensions.iter().map(|d| d.key.as_str()).collect();
+        writeln!(writer,
+                 "rank,trial,{},accuracy,validation_cost,seconds,seed",
+                 keys.join(","))?;
+        for (rank, result) in results.iter().enumerate() {
+            let values: Vec<String> = result.values.iter().map(|v| v.join(" ")).collect();
+            writeln!(writer,
+                     "{},{},{},{},{},{},{}",
+                     rank + 1,
+                     result.trial,
+                     values.join(","),
+                     result.accuracy.map_or(String::new(), |a| a.to_string()),
+                     result.validation_cost,
+                     result.seconds,
+                     seed)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a single trial
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrialResult {
+    /// number of the trial, starting at 1
+    pub trial: usize,
+    /// the values that were tried
+    pub values: Trial,
+    /// mean accuracy on the validation data, `None` for regression
+    pub accuracy: Option<f64>,
+    /// mean cost per validation sample
+    pub validation_cost: f64,
+    /// wall time of the trial in seconds
+    pub seconds: f64,
+}
+
+/// Sort the results from best to worst, see `compare`. Equally good trials keep their order.
+pub fn rank(results: &mut Vec<TrialResult>) {
+    results.sort_by(compare);
+}
+
+/// Compare two results, the better one is `Less`: by accuracy, and by validation cost between
+/// equally accurate trials and for regression. Trials that diverged to NaN are worst.
+pub fn compare(a: &TrialResult, b: &TrialResult) -> Ordering {
+    let accuracy = |r: &TrialResult| {
+        r.accuracy.map_or(0.0, |a| if a.is_nan() { -1.0 } else { a })
+    };
+    let cost = |r: &TrialResult| {
+        if r.validation_cost.is_nan() {
+            f64::INFINITY
+        } else {
+            r.validation_cost
+        }
+    };
+    accuracy(b)
+        .partial_cmp(&accuracy(a))
+        .unwrap_or(Ordering::Equal)
+        .then(cost(a).partial_cmp(&cost(b)).unwrap_or(Ordering::Equal))
+}
+
+// Parse the values of a dimension, see the module documentation
+fn parse_values(values: &str, multiple: bool) -> Result<Values, &'static str> {
+    let (range, log) = if values.ends_with(":log") {
+        (&values[..values.len() - 4], true)
+    } else {
+        (values, false)
+    };
+    let bounds: Vec<&str> = range.splitn(2, "..").map(|b| b.trim()).collect();
+    if bounds.len() == 2 {
+        let (min, max): (f64, f64) = match (bounds[0].parse(), bounds[1].parse()) {
+            (Ok(min), Ok(max)) => (min, max),
+            _ => return Err("The bounds of a range need to be numbers"),
+        };
+        if !(min < max) {
+            return Err("The lower bound of a range needs to be below the upper bound");
+        }
+        if log && min <= 0.0 {
+            return Err("The bounds of a log range need to be positive");
+        }
+        return Ok(Values::Range {
+            min: min,
+            max: max,
+            log: log,
+            integer: bounds[0].parse::<i64>().is_ok() && bounds[1].parse::<i64>().is_ok(),
+        });
+    }
+    if log {
+        return Err("Only ranges can be searched on a log scale");
+    }
+
+    let list: Vec<Vec<String>> = values.split(',')
+        .map(|value| if multiple {
+            value.split_whitespace().map(|s| s.to_string()).collect()
+        } else {
+            vec![value.trim().to_string()]
+        })
+        .collect();
+    if list.iter().any(|value| value.is_empty() || value[0].is_empty()) {
+        return Err("The values of a list cannot be empty");
+    }
+    Ok(Values::List(list))
+}
+
+// The number at `t` between `min` (t = 0) and `max` (t = 1), on a log scale if `log` is set
+fn interpolate(min: f64, max: f64, t: f64, log: bool) -> f64 {
+    if log {
+        (min.ln() + t * (max.ln() - min.ln())).exp()
+    } else {
+        min + t * (max - min)
+    }
+}
+
+// Format a number of a range as a value, rounded to an integer or to four significant digits
+fn format_number(x: f64, integer: bool) -> String {
+    if integer {
+        return (x.round() as i64).to_string();
+    }
+    if x == 0.0 {
+        return "0".to_string();
+    }
+    let scale = 10f64.powi(3 - x.abs().log10().floor() as i32);
+    ((x * scale).round() / scale).to_string()
+}
+
+
+#[test]
+fn test_search_space() {
+    use rand::{SeedableRng, StdRng};
+
+    let space = SearchSpace::parse(vec!["eta=0.01..1:log", "topology=4 5 3,4 10 3", "epochs=1..3"])
+        .unwrap();
+    assert_eq!(space.dimensions[1].values,
+               Values::List(vec![vec!["4".to_string(), "5".to_string(), "3".to_string()],
+                                 vec!["4".to_string(), "10".to_string(), "3".to_string()]]));
+    let grid = space.grid(3);
+    assert_eq!(grid.len(), 3 * 2 * 3);
+    assert_eq!(space.describe(&grid[0]), "eta=0.01, topology=4 5 3, epochs=1");
+    assert_eq!(grid.iter().filter(|t| t[0] == ["0.1"]).count(), 6);
+    assert_eq!(space.grid(10).len(), 10 * 2 * 3);
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    for _ in 0..100 {
+        let trial = space.sample(&mut rng);
+        let eta: f64 = trial[0][0].parse().unwrap();
+        assert!(eta >= 0.01 && eta <= 1.0);
+        assert!(["1", "2", "3"].contains(&trial[2][0].as_str()));
+    }
+
+    let settings = space.apply(&grid[0], &Experiment::default());
+    assert_eq!(settings.values("topology"), Some(vec!["4", "5", "3"]));
+    assert_eq!(settings.value("learning_rate"), Some("0.01"));
+
+    assert!(SearchSpace::parse(vec!["eta"]).is_err());
+    assert!(SearchSpace::parse(vec!["size=1,2"]).is_err());
+    assert!(SearchSpace::parse(vec!["data.type=flower,mnist"]).is_err());
+    assert!(SearchSpace::parse(vec!["eta=0..1:log"]).is_err());
+    assert!(SearchSpace::parse(vec!["eta=1..0.1"]).is_err());
+
+    // more accurate trials first, then lower costs, NaN last
+    let result = |trial, accuracy, cost| {
+        TrialResult {
+            trial: trial,
+            values: grid[0].clone(),
+            accuracy: accuracy,
+            validation_cost: cost,
+            seconds: 1.0,
+        }
+    };
+    let mut results = vec![result(1, Some(0.5), 0.1),
+                           result(2, Some(::std::f64::NAN), ::std::f64::NAN),
+                           result(3, Some(0.9), 0.3),
+                           result(4, Some(0.9), 0.2)];
+    rank(&mut results);
+    assert_eq!(results.iter().map(|r| r.trial).collect::<Vec<_>>(), vec![4, 3, 1, 2]);
+    let mut csv = Vec::new();
+    space.write_leaderboard(&mut csv, &results[..1], 7).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(),
+               "rank,trial,eta,topology,epochs,accuracy,validation_cost,seconds,seed\n\
+                1,4,0.01,4 5 3,1,0.9,0.2,1,7\n");
+
+    // the bounds, the listed values and a fractional value between the bounds are validated
+    let space = SearchSpace::parse(vec!["epochs=1.0..4.0", "batch_size=8,16", "bptt=0..9"])
+        .unwrap();
+    let trials = space.validation_trials();
+    assert_eq!(trials.len(), 3 * 2 * 2);
+    let epochs: Vec<&str> = trials.iter().step_by(4).map(|t| t[0][0].as_str()).collect();
+    assert_eq!(epochs, vec!["1", "4", "2.5"]);
+}
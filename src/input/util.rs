@@ -1,19 +1,15 @@
+//! Helpers to prepare data for a network and to work with paths
+
 use std::env;
-use std::path::PathBuf;
+use nn::Float;
+use std::path::{Path, PathBuf};
 use structs::Data;
 
-/// Get the root directory that the program runs in.
+/// Get the absolute path of `path`, relative paths are resolved against the working directory.
 ///
-/// Will try to find the Cargo Root Directory, if that fails it will use the directory
-/// from where the program was called.
-pub fn get_root_dir() -> PathBuf {
-    if let Ok(path) = env::var("CARGO_MANIFEST_DIR") {
-        info!("Using $CARGO_MANIFEST_DIR as root directory.");
-        PathBuf::from(path)
-    } else {
-        info!("Could not find $CARGO_MANIFEST_DIR, using current working directory instead.");
-        env::current_dir().unwrap()
-    }
+/// The path does not need to exist.
+pub fn absolute<P: AsRef<Path>>(path: P) -> PathBuf {
+    env::current_dir().unwrap().join(path)
 }
 
 
@@ -22,19 +18,21 @@ pub fn get_root_dir() -> PathBuf {
 /// The output will be `(training_data, test_data)`. Before the data is split `input` will be
 /// shuffled to ensure randomness in picking the test data. `test_data` will hold `test_data_size`,
 /// `training_data` will contain all other elements from `input`.
-pub fn split_data(mut input: &mut Vec<Data>, test_data_size: usize) -> (Vec<Data>, Vec<Data>) {
+pub fn split_data<N: Float>(mut input: &mut Vec<Data<N>>,
+                            test_data_size: usize)
+                            -> (Vec<Data<N>>, Vec<Data<N>>) {
     use rand::{self, Rng};
 
     // shuffle data to make sure that not always the same data is picked as training and test data
     let mut rng = rand::thread_rng();
     rng.shuffle(&mut input);
 
-    let mut training_data: Vec<Data> = Vec::with_capacity(input.len() - test_data_size);
+    let mut training_data: Vec<Data<N>> = Vec::with_capacity(input.len() - test_data_size);
     for i in 0..input.len() - test_data_size {
         training_data.push(input[i].clone());
     }
 
-    let mut test_data: Vec<Data> = Vec::with_capacity(test_data_size);
+    let mut test_data: Vec<Data<N>> = Vec::with_capacity(test_data_size);
     for i in input.len() - test_data_size..input.len() {
         test_data.push(input[i].clone());
     }
@@ -54,3 +52,15 @@ pub fn generic_to_data<T: Into<Data> + Clone>(input: Vec<T>) -> Vec<Data> {
     }
     input_data
 }
+
+#[test]
+fn test_absolute() {
+    let cwd = env::current_dir().unwrap();
+    assert_eq!(absolute("data/model_state.ser"), cwd.join("data").join("model_state.ser"));
+    assert_eq!(absolute(&cwd), cwd);
+    // `..` is kept, it is only resolved when the path is used
+    let parent = absolute("../logs");
+    assert!(parent.is_absolute());
+    assert_eq!(parent, cwd.join("..").join("logs"));
+    assert_eq!(parent.parent().unwrap().parent(), Some(cwd.as_path()));
+}
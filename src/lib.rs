@@ -0,0 +1,71 @@
+// Copyright (c) 2017 rustle-my-net developers
+// Licensed under the MIT license
+//! #Rustle-My-Net
+//!
+//! An implementation of a simple Artificial Neural Network (ANN) in Rust.
+//!
+//! ##About
+//! Rustle-My-Net is the basic implementation of a very simple ANN in Rust. The code was designed
+//! using [this book](http://neuralnetworksanddeeplearning.com/) written by Michael Nielsen. While
+//! this is a layman implementation it might help you understand how a neural network works and
+//! how to implement one in rust.
+//!
+//! The Network uses Sigmoid Neurons and Stochastic Gradient Descent for learning. This version of
+//! the network uses the quadratic cost function which is not ideal in terms of learning but easier
+//! to implement.
+//!
+//! ##Usage
+//! The library is split into the following modules, the `rustle-my-net` binary is a command line
+//! interface on top of them:
+//!
+//! * `nn`: the `Network`, its layers, training with `nn::learning` and saving to files
+//! * `structs`: the `Data` the network learns from and the datasets it can be read from
+//! * `input`: reading datasets from files and the settings of runs
+//! * `preprocessing`: splitting data, scaling inputs, balancing classes and augmenting images
+//! * `report`: charts and HTML pages of trained networks
+//!
+//! ```
+//! extern crate nalgebra;
+//! extern crate rustle_my_net;
+//!
+//! use nalgebra::DVector;
+//! use rustle_my_net::nn::{Cost, NetworkBuilder, Topology, Trainer};
+//! use rustle_my_net::nn::learning::{self, Evaluation};
+//! use rustle_my_net::structs::Data;
+//!
+//! # fn main() {
+//! // the two classes of XOR
+//! let data: Vec<Data> = vec![(0.0, 0.0, 0), (0.0, 1.0, 1), (1.0, 0.0, 1), (1.0, 1.0, 0)]
+//!     .into_iter()
+//!     .map(|(a, b, class)| Data::new(DVector::from_slice(2, &[a, b]), class, 2))
+//!     .collect();
+//!
+//! let nn = NetworkBuilder::new(Topology::dense(&[2, 4, 2])).seed(1).build().unwrap();
+//! let (nn, history) = Trainer::new()
+//!     .epochs(10)
+//!     .batch_size(2)
+//!     .learning_rate(0.5)
+//!     .cost(Cost::Quadratic)
+//!     .train(nn, data.clone(), &data);
+//! assert_eq!(history.epochs.len(), 10);
+//!
+//! match learning::evaluate_metrics(&nn, &data, 0.5) {
+//!     Evaluation::Classification(_, total) => assert_eq!(total, 4),
+//!     _ => unreachable!(),
+//! }
+//! # }
+//! ```
+
+extern crate rand;
+extern crate nalgebra as na;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+#[macro_use]
+extern crate log;
+
+pub mod input;
+pub mod nn;
+pub mod preprocessing;
+pub mod report;
+pub mod structs;
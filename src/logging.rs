@@ -1,7 +1,6 @@
 extern crate fern;
 extern crate time;
 
-use input::util;
 use log;
 use std::ffi::OsStr;
 use std::fs;
@@ -14,20 +13,17 @@ const MAX_LOG_FILES: usize = 10;
 /// Initializes a global logger that logs to its own file for each run
 ///
 /// It can be used with the macros in the log crate. The resulting log files can
-/// be found in the logs subdirectory.
-pub fn init_logger(verbosity: log::LogLevelFilter) {
+/// be found in `logs_dir_path`.
+pub fn init_logger(verbosity: log::LogLevelFilter, logs_dir_path: &Path) {
 
     // remove old logs if the amount of logs in log directory exceeds MAX_LOG_FILES
-    if let Err(e) = clean_logs() {
+    if let Err(e) = clean_logs(logs_dir_path) {
         panic!("Error trying to clean logs: {} ... exiting!", e);
     }
 
-    // get the log directory path
-    let logs_dir_path = get_log_dir();
-
     // check if the log directory exists, create it if not
-    if fs::metadata(&logs_dir_path).is_err() {
-        fs::create_dir(Path::new("logs/")).unwrap();
+    if fs::metadata(logs_dir_path).is_err() {
+        fs::create_dir_all(logs_dir_path).unwrap();
     }
 
     // the logfiles are named with a timestamp
@@ -56,47 +52,79 @@ pub fn init_logger(verbosity: log::LogLevelFilter) {
     }
 }
 
-/// Deletes logfiles if there are more than MAX_LOG_FILES in the log/ directory
+/// Deletes the oldest logfiles if there are MAX_LOG_FILES or more in the log directory `path`, so
+/// there are at most MAX_LOG_FILES together with the one of the current run
 ///
-/// it returns the amount of files deleted, or an io::Error if the operation fails
-fn clean_logs() -> Result<usize, io::Error> {
-
-    // get the log dir path
-    let path = get_log_dir();
-
-    // if reading the directory fails, n_logs stays 0 and nothing is deleted
-    let mut n_logs = 0;
-    if let Ok(iter) = fs::read_dir(&path) {
-        n_logs = iter.filter_map(|f| f.ok())
-            .filter(|f| f.path().extension() == Some(OsStr::new("log")))
-            .count();
-    }
-
-    // calculate how many logfiles too many there are
-    // - 2 because the current execution also generates a logfile
-    // and we want to have at most MAX_LOG_FILES files.
-    let excess_files = n_logs.saturating_sub(MAX_LOG_FILES - 2);
-
-    // collect paths to the old files in a vector
-    let mut delete_vec: Vec<PathBuf> = Vec::new();
-    if let Ok(iter) = fs::read_dir(&path) {
-        delete_vec = iter.take(excess_files)
-            .filter_map(|f| f.ok())
-            .filter(|f| f.path().extension() == Some(OsStr::new("log")))
-            .map(|f| f.path())
-            .collect();
-    }
+/// Only files named like the logfiles of this program are deleted, the log directory can be shared
+/// with other files. It returns the amount of files deleted, or an io::Error if the operation
+/// fails.
+fn clean_logs(path: &Path) -> Result<usize, io::Error> {
+    // if reading the directory fails, e.g. because it does not exist yet, nothing is deleted
+    let mut logs: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(iter) => {
+            iter.filter_map(|f| f.ok())
+                .map(|f| f.path())
+                .filter(|f| {
+                    f.is_file() && f.file_name().and_then(OsStr::to_str).map_or(false, is_log_name)
+                })
+                .collect()
+        }
+        Err(_) => return Ok(0),
+    };
 
-    // delete the selected files
-    for f in delete_vec {
-        fs::remove_file(&path.join(f))?;
+    // the names start with the time of the run, so sorting them sorts the logs from oldest to
+    // newest
+    logs.sort();
+    let excess_files = logs.len().saturating_sub(MAX_LOG_FILES - 1);
+    for f in &logs[..excess_files] {
+        fs::remove_file(f)?;
     }
     Ok(excess_files)
 }
 
-/// returns the log directory
-fn get_log_dir() -> PathBuf {
-    let mut p = util::get_root_dir();
-    p.push(Path::new("logs/"));
-    p
+// Whether `name` is the name of a logfile of this program: the start time of the run in RFC 3339,
+// e.g. `2017-06-01T12:00:00Z.log` or `2017-06-01T12:00:00+02:00.log`
+fn is_log_name(name: &str) -> bool {
+    // `d` stands for a digit and `s` for the sign of the UTC offset
+    let matches = |pattern: &str| {
+        name.len() == pattern.len() &&
+        name.bytes().zip(pattern.bytes()).all(|(c, p)| match p {
+            b'd' => (c as char).is_digit(10),
+            b's' => c == b'+' || c == b'-',
+            _ => c == p,
+        })
+    };
+    matches("dddd-dd-ddTdd:dd:ddZ.log") || matches("dddd-dd-ddTdd:dd:ddsdd:dd.log")
+}
+
+
+#[test]
+fn test_clean_logs() {
+    use std::env;
+    use std::fs::File;
+
+    let dir = env::temp_dir().join("rustle-my-net-logs");
+    fs::create_dir_all(&dir).unwrap();
+    // the logs of 12 runs, one with a different UTC offset, and files of other programs
+    let mut logs: Vec<String> =
+        (0..11).map(|i| format!("2017-06-01T12:{:02}:00Z.log", 59 - i)).collect();
+    logs.push("2017-05-31T09:00:00+02:00.log".to_string());
+    let others = ["syslog.log", "2017-06-01.log", "notes.txt"];
+    for name in logs.iter().map(|s| s.as_str()).chain(others.iter().cloned()) {
+        File::create(dir.join(name)).unwrap();
+    }
+
+    assert_eq!(clean_logs(&dir).unwrap(), 3);
+    let mut left: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|f| f.unwrap().file_name().into_string().unwrap())
+        .collect();
+    left.sort();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // the oldest logs are gone, the other files are kept
+    assert_eq!(left.len(), MAX_LOG_FILES - 1 + others.len());
+    assert!(!left.contains(&logs[11]) && !left.contains(&logs[10]) && !left.contains(&logs[9]));
+    assert!(left.contains(&logs[8]) && left.contains(&logs[0]));
+    assert!(others.iter().all(|name| left.contains(&name.to_string())));
 }
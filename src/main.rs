@@ -2,53 +2,56 @@
 // Licensed under the MIT license
 //! #Rustle-My-Net
 //!
-//! An implementation of a simple Artificial Neural Network (ANN) in Rust.
-//!
-//! ##About
-//! Rustle-My-Net is the basic implementation of a very simple ANN in Rust. The code was designed
-//! using [this book](http://neuralnetworksanddeeplearning.com/) written by Michael Nielsen. While
-//! this is a layman implementation it might help you understand how a neural network works and
-//! how to implement one in rust.
-//!
-//! The Network uses Sigmoid Neurons and Stochastic Gradient Descent for learning. This version of
-//! the network uses the quadratic cost function which is not ideal in terms of learning but easier
-//! to implement.
+//! The command line interface of Rustle-My-Net, see the library for the network itself.
 //!
 //! The results of the learning progress are stored in the log folder’s log files along with all
 //! the other outputs that occur during the invocation of the learning progress.
 
 extern crate rand;
-extern crate nalgebra as na;
-#[macro_use]
-extern crate serde_derive;
-extern crate serde;
+extern crate rustle_my_net;
 #[macro_use]
 extern crate log;
-mod input;
-mod structs;
-mod nn;
+mod cli;
 mod logging;
 mod model;
 
-use input::config;
-use input::util::generic_to_data;
-
+use rustle_my_net::input::util::{self, generic_to_data};
+use std::env;
 fn main() {
 
     // read command line arguments
-    let config: config::GlobalConfig = input::read_arguments();
+    let config: cli::GlobalConfig = cli::read_arguments();
 
     // initialize the global logger --> we can use info!(), debug!(), etc. from here on
-    logging::init_logger(config.verbosity);
+    logging::init_logger(config.verbosity, &config.log_dir);
 
     info!("Starting_up...");
     info!("Running with Logging Level: {:?}", config.verbosity);
+    info!("Working directory: {}", env::current_dir().unwrap().display());
+    info!("Log directory: {}", util::absolute(&config.log_dir).display());
+    info!("Model directory: {}", util::absolute(&config.model_dir).display());
 
     // Program logic starts here
-    let data = generic_to_data(config.data.unwrap());
+    if let Some(gc_cfg) = config.gradcheck_config {
+        model::gradcheck(&gc_cfg);
+        return;
+    }
+
+    info!("Data file: {}", util::absolute(&config.data_file).display());
+    let data = match config.data {
+        Ok(data) => generic_to_data(data),
+        Err(e) => {
+            error!("Could not read data: {}", e);
+            return;
+        }
+    };
     if let Some(learn_cfg) = config.learn_config {
         model::train(&learn_cfg, data);
+    } else if let Some(tune_cfg) = config.tune_config {
+        model::tune(&tune_cfg, data);
+    } else if let Some(report_cfg) = config.report_config {
+        model::report(&config.save_file, &data, &report_cfg);
     } else {
-        model::classify(&config.save_file, &data);
+        model::classify(&config.save_file, &data, config.threshold);
     }
 }
@@ -1,50 +1,585 @@
-use input::config;
-use input::util;
-use nn;
-use structs::Data;
+//! The runs of the command line interface: training, tuning, classifying, reporting and gradient
+//! checking
+
+use rustle_my_net::input::config::{self, ClassWeights, Search};
+use rustle_my_net::input::search::{self, TrialResult};
+use rustle_my_net::input::util;
+use rustle_my_net::nn::{self, Float, NetworkBuilder, Precision, Shape, Topology, Trainer,
+                        TrainingHistory};
+use rustle_my_net::nn::callbacks::HistoryLogger;
+use rustle_my_net::nn::learning::{self, ClassBalance, Resampling};
+use rustle_my_net::nn::gradcheck;
+use rustle_my_net::report;
+use rustle_my_net::report::html::TrainingReport;
+use rustle_my_net::structs::Data;
+use std::cmp::Ordering;
+use std::f64;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Instant;
+
+// the number of misclassified samples shown in the training report
+const REPORT_SAMPLES: usize = 10;
+
+// A network trained with the settings of a run, together with its topology and history
+struct Run<N: Float> {
+    nn: nn::Network<N>,
+    topology: Topology,
+    history: TrainingHistory,
+}
+
+// The run of a trial of `tune` in the precision it was trained in
+enum TrialRun {
+    Single(Run<f32>),
+    Double(Run<f64>),
+}
 
 /// Prepare data, construct the neural network and call training methods.
 /// After training has run the network state will be saved.
+///
+/// The network is trained in the precision given in `learn_cfg`, the data is converted if needed.
 pub fn train(learn_cfg: &config::LearningConfig, mut data: Vec<Data>) {
     // split data into training and test data
     let (training_data, test_data) = util::split_data(&mut data, learn_cfg.test_size);
+    train_split(learn_cfg, training_data, test_data);
+}
+
+// Train like `train` on data that is already split into training and test data
+fn train_split(learn_cfg: &config::LearningConfig, training_data: Vec<Data>, test_data: Vec<Data>) {
+    info!("Training in {} precision", learn_cfg.precision);
+    match learn_cfg.precision {
+        Precision::Single => train_with::<f32>(learn_cfg, training_data, test_data),
+        Precision::Double => {
+            train_with(learn_cfg,
+                       training_data.iter().map(Data::convert::<f64>).collect(),
+                       test_data.iter().map(Data::convert::<f64>).collect())
+        }
+    }
+}
 
+fn train_with<N: Float>(learn_cfg: &config::LearningConfig,
+                        training_data: Vec<Data<N>>,
+                        test_data: Vec<Data<N>>) {
     info!("Initialising network...");
+    let (nn, topology, mut trainer) = match build_network(learn_cfg, &training_data) {
+        Ok(network) => network,
+        Err(msg) => {
+            error!("{}", msg);
+            return;
+        }
+    };
+
+    // the report needs to know about the training data, which is moved into the trainer
+    let n_training = training_data.len();
+    let class_counts = class_counts(&training_data, nn.output_size());
 
-    // create the network
-    let mut nn = nn::Network::new(&learn_cfg.init_vec).unwrap();
+    // the history is written after every epoch, so it can be watched during long runs
+    if let Some(ref path) = learn_cfg.history_file {
+        info!("Writing training history to {}...", path);
+        trainer = trainer.callback(HistoryLogger::new(path.as_str()));
+    }
 
     info!("Starting learning...");
     // learn!
-    nn::learning::sgd(&mut nn,
-                      training_data,
-                      learn_cfg.epochs,
-                      learn_cfg.batch_size,
-                      learn_cfg.learning_rate,
-                      test_data);
-
-    // save network state
-    info!("Saving network...");
-    nn.save_to_file(&learn_cfg.save_file).unwrap_or_else(|e| {
+    let (nn, history) = trainer.train(nn, training_data, &test_data);
+    let run = Run {
+        nn: nn,
+        topology: topology,
+        history: history,
+    };
+    save_run(learn_cfg, run, n_training, class_counts, &test_data);
+
+    info!("...terminated!");
+}
+
+// Write the report of a run if `learn_cfg` asks for it, and save its network and settings. The
+// network was trained on `n_training` samples with `class_counts` and tested on `test_data`.
+fn save_run<N: Float>(learn_cfg: &config::LearningConfig,
+                      run: Run<N>,
+                      n_training: usize,
+                      class_counts: Option<Vec<usize>>,
+                      test_data: &Vec<Data<N>>) {
+    let Run { nn, topology, history } = run;
+    if let Some(ref path) = learn_cfg.report_file {
+        info!("Writing training report to {}...", path);
+        let mut report = TrainingReport {
+            config: learn_cfg,
+            topology: &topology,
+            n_training: n_training,
+            n_test: test_data.len(),
+            input_shape: topology.input,
+            class_counts: class_counts,
+            history: &history,
+            evaluation: None,
+            confusion: None,
+            misclassified: Vec::new(),
+        };
+        if !test_data.is_empty() {
+            let threshold = N::from_f64(learn_cfg.threshold);
+            report.evaluation = Some(learning::evaluate_metrics(&nn, test_data, threshold));
+        }
+        if !test_data.is_empty() && test_data.iter().all(|d| d.get_class().is_some()) {
+            report.confusion = Some(learning::confusion_matrix(&nn, test_data));
+            let input = |i: usize| test_data[i].get_input().at.iter().map(|&x| x.to_f64());
+            report.misclassified = learning::worst_misclassified(&nn, test_data, REPORT_SAMPLES)
+                .into_iter()
+                .map(|m| (m, input(m.index).collect()))
+                .collect();
+        }
+        report.save(path).unwrap_or_else(|e| {
+            error!("Could not write training report: {}", e);
+        });
+    }
+
+    // save network state, the history is kept with it
+    info!("Saving network to {}...", learn_cfg.save_file.display());
+    if let Some(dir) = learn_cfg.save_file.parent() {
+        fs::create_dir_all(dir).unwrap_or_else(|e| {
+            error!("Could not create model directory: {}", e);
+        });
+    }
+    nn.save_with_history(&learn_cfg.save_file, Some(history)).unwrap_or_else(|e| {
         error!("Could not save network state to file: {}", e);
     });
 
+    // the settings are saved next to the network, so the run can be repeated with --config
+    let experiment_file = learn_cfg.save_file.with_extension("toml");
+    info!("Saving settings to {}...", experiment_file.display());
+    learn_cfg.experiment.save(&experiment_file).unwrap_or_else(|e| {
+        error!("Could not save settings: {}", e);
+    });
+}
+
+// Create the network of `learn_cfg` for the training data, together with its topology and the
+// trainer. Returns a message if the settings do not fit together.
+fn build_network<N: Float>(learn_cfg: &config::LearningConfig,
+                           training_data: &[Data<N>])
+                           -> Result<(nn::Network<N>, Topology, Trainer<N>), String> {
+    // a flat input of the right size takes the shape of the data, e.g. images or sequences
+    let mut topology = learn_cfg.topology.clone();
+    if let Some(sample) = training_data.first() {
+        let shape = sample.get_shape();
+        if topology.input == Shape::flat(shape.len()) {
+            topology.input = shape;
+        }
+    }
+    topology.set_bptt_length(learn_cfg.bptt_length);
+
+    // configure dropout, a single keep probability is used for all hidden layers
+    let n_hidden = topology.layers.len() - 1;
+    let mut keep_probs = vec![N::from_f64(learn_cfg.input_keep_prob)];
+    if learn_cfg.keep_probs.len() == 1 {
+        keep_probs.extend(vec![N::from_f64(learn_cfg.keep_probs[0]); n_hidden]);
+    } else {
+        keep_probs.extend(learn_cfg.keep_probs.iter().map(|p| N::from_f64(*p)));
+    }
+
+    // create the network, batch normalization is only used on the hidden layers
+    info!("Topology: {}", topology);
+    let nn = NetworkBuilder::new(topology.clone())
+        .keep_probs(&keep_probs)
+        .batch_norm(learn_cfg.batch_norm)
+        .output(learn_cfg.output)
+        .build()
+        .map_err(|msg| format!("Invalid network configuration: {}", msg))?;
+    // data that does not fit the network would only fail deep inside the matrix operations
+    nn.check_data(training_data)
+        .map_err(|e| format!("The topology does not fit the data: {}", e))?;
+
+    // counter imbalanced classes, this only works if every sample has a single class
+    let balance = ClassBalance {
+        weights: match learn_cfg.class_weights {
+            ClassWeights::Equal => None,
+            ClassWeights::InverseFrequency => {
+                Some(learning::inverse_frequency_weights(training_data, nn.output_size()))
+            }
+            ClassWeights::Manual(ref weights) => {
+                Some(weights.iter().map(|w| N::from_f64(*w)).collect())
+            }
+        },
+        resampling: learn_cfg.resampling,
+    };
+    if balance != ClassBalance::default() {
+        if training_data.iter().any(|d| d.get_class().is_none()) {
+            return Err("Class weights and resampling need data with a single class per sample"
+                .to_string());
+        }
+        if balance.weights.as_ref().map_or(false, |w| w.len() != nn.output_size()) {
+            return Err("One class weight per output required".to_string());
+        }
+        if let Some(ref weights) = balance.weights {
+            info!("Class weights: {:?}", weights);
+        }
+        if balance.resampling != Resampling::None {
+            info!("Resampling: {:?}", balance.resampling);
+        }
+    }
+
+    let mut trainer = Trainer::new()
+        .epochs(learn_cfg.epochs)
+        .batch_size(learn_cfg.batch_size)
+        .learning_rate(learn_cfg.learning_rate)
+        .cost(learn_cfg.cost)
+        .divergence(learn_cfg.divergence)
+        .threshold(learn_cfg.threshold)
+        .class_balance(balance);
+    if let Some(max) = learn_cfg.clip_value {
+        info!("Clipping gradients to [-{}, {}]", max, max);
+        trainer = trainer.clip_value(max);
+    }
+    if let Some(max_norm) = learn_cfg.clip_norm {
+        info!("Clipping the gradient norm to {}", max_norm);
+        trainer = trainer.clip_norm(max_norm);
+    }
+    if let Some(ref augmentation) = learn_cfg.augmentation {
+        info!("Augmentation: {:?}", augmentation);
+        trainer = trainer.augmentation(augmentation.clone());
+    }
+
+    Ok((nn, topology, trainer))
+}
+
+// The number of samples of each class, `None` unless every sample has a single class
+fn class_counts<N: Float>(data: &[Data<N>], n_classes: usize) -> Option<Vec<usize>> {
+    let mut counts = vec![0; n_classes];
+    for d in data {
+        counts[d.get_class()?] += 1;
+    }
+    Some(counts)
+}
+
+/// Search the hyperparameters of `learn` in the search space of `tune_cfg`.
+///
+/// Every trial trains a network with its settings on the same training data and validates it on
+/// the same validation data, or on every fold of the data with cross-validation. The results of
+/// the trials are written to the leaderboard, best first, together with the seed of the split. The
+/// network the best trial trained on the first split is saved like with `learn`, together with the
+/// settings. Its score is logged, with cross-validation it is the score of the first fold only.
+pub fn tune(tune_cfg: &config::TuneConfig, mut data: Vec<Data>) {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // the data is split only once, so the results of the trials are comparable
+    let mut rng: StdRng = SeedableRng::from_seed(&[tune_cfg.seed][..]);
+    rng.shuffle(&mut data);
+    let n = data.len();
+    let validation: Vec<Range<usize>> = match tune_cfg.folds {
+        Some(k) if k <= n => (0..k).map(|i| i * n / k..(i + 1) * n / k).collect(),
+        None if tune_cfg.test_size < n => vec![n - tune_cfg.test_size..n],
+        _ => {
+            error!("Not enough data to validate the trials");
+            return;
+        }
+    };
+
+    let trials = match tune_cfg.search {
+        Search::Grid(steps) => tune_cfg.space.grid(steps),
+        Search::Random(n_trials) => {
+            (0..n_trials).map(|_| tune_cfg.space.sample(&mut rng)).collect()
+        }
+    };
+    info!("Searching {} settings in {} trials...",
+          tune_cfg.space.dimensions.len(),
+          trials.len());
+
+    let mut results = Vec::new();
+    // only the run of the best trial so far is kept
+    let mut best: Option<(TrialResult, TrialRun)> = None;
+    for (i, trial) in trials.iter().enumerate() {
+        info!("Trial {}/{}: {}",
+              i + 1,
+              trials.len(),
+              tune_cfg.space.describe(trial));
+        let settings = tune_cfg.space.apply(trial, &tune_cfg.settings);
+        let learn_cfg = config::LearningConfig::from_settings(&settings, &tune_cfg.model_dir);
+        let start = Instant::now();
+        let scores = match learn_cfg.precision {
+            Precision::Single => {
+                validate::<f32>(&learn_cfg, &data, &validation)
+                    .map(|(accuracy, cost, run)| (accuracy, cost, TrialRun::Single(run)))
+            }
+            Precision::Double => {
+                let data: Vec<Data<f64>> = data.iter().map(Data::convert::<f64>).collect();
+                validate(&learn_cfg, &data, &validation)
+                    .map(|(accuracy, cost, run)| (accuracy, cost, TrialRun::Double(run)))
+            }
+        };
+        let elapsed = start.elapsed();
+        match scores {
+            Ok((accuracy, validation_cost, run)) => {
+                match accuracy {
+                    Some(accuracy) => {
+                        info!("Accuracy {:.4}, validation cost {:.6}",
+                              accuracy,
+                              validation_cost)
+                    }
+                    None => info!("Validation cost {:.6}", validation_cost),
+                }
+                let result = TrialResult {
+                    trial: i + 1,
+                    values: trial.clone(),
+                    accuracy: accuracy,
+                    validation_cost: validation_cost,
+                    seconds: elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9,
+                };
+                // like in the ranking, the earlier of two equally good trials is better
+                let better = |b: &(TrialResult, TrialRun)| {
+                    search::compare(&result, &b.0) == Ordering::Less
+                };
+                if best.as_ref().map_or(true, better) {
+                    best = Some((result.clone(), run));
+                }
+                results.push(result);
+            }
+            Err(msg) => error!("Trial {} failed: {}", i + 1, msg),
+        }
+    }
+
+    search::rank(&mut results);
+    info!("Writing leaderboard to {}...", tune_cfg.leaderboard);
+    File::create(&tune_cfg.leaderboard)
+        .and_then(|file| {
+            tune_cfg.space.write_leaderboard(BufWriter::new(file), &results, tune_cfg.seed)
+        })
+        .unwrap_or_else(|e| {
+            error!("Could not write leaderboard: {}", e);
+        });
+
+    let (best, run) = match best {
+        Some(best) => best,
+        None => {
+            error!("No trial succeeded!");
+            return;
+        }
+    };
+    info!("Best settings: {} (trial {})",
+          tune_cfg.space.describe(&best.values),
+          best.trial);
+    let settings = tune_cfg.space.apply(&best.values, &tune_cfg.settings);
+    let learn_cfg = config::LearningConfig::from_settings(&settings, &tune_cfg.model_dir);
+    let (training_data, test_data) = split_at_range(&data, &validation[0]);
+    match run {
+        TrialRun::Single(run) => save_trial(&learn_cfg, run, &training_data, &test_data),
+        TrialRun::Double(run) => {
+            let convert = |data: &[Data]| data.iter().map(Data::convert::<f64>).collect::<Vec<_>>();
+            save_trial(&learn_cfg, run, &convert(&training_data), &convert(&test_data))
+        }
+    }
     info!("...terminated!");
 }
 
+// Save the run of the best trial of `tune`, which was trained on `training_data` and validated
+// on `test_data`, and log the score of its network
+fn save_trial<N: Float>(learn_cfg: &config::LearningConfig,
+                        run: Run<N>,
+                        training_data: &[Data<N>],
+                        test_data: &Vec<Data<N>>) {
+    // with cross-validation this is the score on the first fold, not the mean of the leaderboard
+    if let Some(last) = run.history.epochs.last() {
+        match last.accuracy {
+            Some(accuracy) => {
+                info!("Saved network: accuracy {:.4} and validation cost {:.6} on {} samples",
+                      accuracy,
+                      last.validation_cost.unwrap_or(f64::NAN),
+                      test_data.len())
+            }
+            None => {
+                info!("Saved network: validation cost {:.6} on {} samples",
+                      last.validation_cost.unwrap_or(f64::NAN),
+                      test_data.len())
+            }
+        }
+    }
+    if let Some(ref path) = learn_cfg.history_file {
+        info!("Writing training history to {}...", path);
+        run.history.save(path).unwrap_or_else(|e| {
+            error!("Could not write training history: {}", e);
+        });
+    }
+    let class_counts = class_counts(training_data, run.nn.output_size());
+    save_run(learn_cfg, run, training_data.len(), class_counts, test_data);
+}
+
+// Split `data` into the samples outside of `range` for training and those in it for testing
+fn split_at_range<N: Float>(data: &[Data<N>],
+                            range: &Range<usize>)
+                            -> (Vec<Data<N>>, Vec<Data<N>>) {
+    let training_data = data[..range.start].iter().chain(&data[range.end..]).cloned().collect();
+    (training_data, data[range.clone()].to_vec())
+}
+
+// Train a network with `learn_cfg` for every range of validation samples of `data` on the other
+// samples and validate it on the range. Returns the mean accuracy of the networks, `None` for
+// regression, their mean cost per validation sample and the run of the first range.
+fn validate<N: Float>(learn_cfg: &config::LearningConfig,
+                      data: &[Data<N>],
+                      validation: &[Range<usize>])
+                      -> Result<(Option<f64>, f64, Run<N>), String> {
+    let (mut accuracy, mut cost) = (Some(0.0), 0.0);
+    let mut first = None;
+    for range in validation {
+        let (training_data, test_data) = split_at_range(data, range);
+        let (nn, topology, mut trainer) = build_network(learn_cfg, &training_data)?;
+        let (nn, history) = trainer.train(nn, training_data, &test_data);
+        {
+            let last = history.epochs.last().ok_or_else(|| "No epochs were trained".to_string())?;
+            accuracy = accuracy.and_then(|a| last.accuracy.map(|b| a + b));
+            cost += last.validation_cost.unwrap_or(f64::NAN);
+        }
+        if first.is_none() {
+            first = Some(Run {
+                nn: nn,
+                topology: topology,
+                history: history,
+            });
+        }
+    }
+    let n = validation.len() as f64;
+    let first = first.ok_or_else(|| "No validation data".to_string())?;
+    Ok((accuracy.map(|a| a / n), cost / n, first))
+}
+
 /// Will load a neural network located at `save_file` and input `data` into the network.
-/// The number of correctly classified items in `data` will be printed on the info log.
+/// The number of correctly classified items in `data` will be printed on the info log, or the
+/// metrics of multi-label or regression data. Outputs above `threshold` count as predicted labels
+/// of multi-label data.
 ///
+/// The network is run in the precision it was saved with.
 /// If no network is located at `save_file` or there is an error on initialising it from file
 /// an error will be logged.
-pub fn classify(save_file: &str, data: &Vec<Data>) {
-    let nn = match nn::Network::from_file(save_file) {
+pub fn classify(save_file: &Path, data: &Vec<Data>, threshold: f64) {
+    match nn::precision_from_file(save_file) {
+        Err(msg) => {
+            error!("Error when trying to open network file at {}: {}",
+                   save_file.display(),
+                   msg);
+        }
+        Ok(Precision::Single) => classify_with::<f32>(save_file, data, threshold),
+        Ok(Precision::Double) => {
+            classify_with(save_file,
+                          &data.iter().map(Data::convert::<f64>).collect(),
+                          threshold)
+        }
+    }
+}
+
+fn classify_with<N: Float>(save_file: &Path, data: &Vec<Data<N>>, threshold: f64) {
+    let nn = match nn::Network::<N>::from_file(save_file) {
         Err(msg) => {
-            error!("Error when trying to open network file at given location: {}",
+            error!("Error when trying to open network file at {}: {}",
+                   save_file.display(),
                    msg);
             return;
         }
         Ok(nn) => nn,
     };
-    nn::learning::evaluate_with_output(&nn, &data);
+    if let Err(e) = nn.check_data(data) {
+        error!("The network does not fit the data: {}", e);
+        return;
+    }
+    learning::evaluate_with_output(&nn, &data, N::from_f64(threshold));
+}
+
+/// Draw charts of the network saved at `save_file` into the directory given in `report_cfg`:
+/// the learning curves of the training history saved with the network and, for single-label
+/// classification data, the confusion matrix of the network on `data`.
+///
+/// The network is run in the precision it was saved with. Errors are logged.
+pub fn report(save_file: &Path, data: &Vec<Data>, report_cfg: &config::ReportConfig) {
+    match nn::precision_from_file(save_file) {
+        Err(msg) => {
+            error!("Error when trying to open network file at {}: {}",
+                   save_file.display(),
+                   msg);
+        }
+        Ok(Precision::Single) => report_with::<f32>(save_file, data, report_cfg),
+        Ok(Precision::Double) => {
+            report_with(save_file,
+                        &data.iter().map(Data::convert::<f64>).collect(),
+                        report_cfg)
+        }
+    }
+}
+
+fn report_with<N: Float>(save_file: &Path,
+                         data: &Vec<Data<N>>,
+                         report_cfg: &config::ReportConfig) {
+    let (nn, history) = match (nn::Network::<N>::from_file(save_file),
+                               nn::history_from_file(save_file)) {
+        (Ok(nn), Ok(history)) => (nn, history),
+        (Err(msg), _) | (_, Err(msg)) => {
+            error!("Error when trying to open network file at {}: {}",
+                   save_file.display(),
+                   msg);
+            return;
+        }
+    };
+    if let Err(e) = nn.check_data(data) {
+        error!("The network does not fit the data: {}", e);
+        return;
+    }
+    if history.is_none() {
+        info!("No training history saved with the network, no learning curves are drawn.");
+    }
+
+    // the confusion matrix needs a single class per sample
+    let confusion = if !data.is_empty() && data.iter().all(|d| d.get_class().is_some()) {
+        Some(learning::confusion_matrix(&nn, data))
+    } else {
+        info!("The data is not single-label classification data, no confusion matrix is drawn.");
+        None
+    };
+
+    match report::write_charts(Path::new(&report_cfg.out_dir),
+                               history.as_ref(),
+                               confusion.as_ref()) {
+        Ok(charts) => {
+            for chart in charts {
+                info!("Wrote {}", chart.display());
+            }
+        }
+        Err(e) => error!("Could not write charts: {}", e),
+    }
+}
+
+/// Check the gradients calculated by backpropagation against numerical derivatives on a random
+/// network with the topology given in `gc_cfg`.
+///
+/// The result is printed on the info log, an error is logged if the check failed.
+pub fn gradcheck(gc_cfg: &config::GradCheckConfig) {
+    match gc_cfg.precision {
+        Precision::Single => gradcheck_with::<f32>(gc_cfg),
+        Precision::Double => gradcheck_with::<f64>(gc_cfg),
+    }
+}
+
+fn gradcheck_with<N: Float>(gc_cfg: &config::GradCheckConfig) {
+    info!("Checking gradients of a '{}' network on {} samples in {} precision...",
+          gc_cfg.topology,
+          gc_cfg.samples,
+          N::precision());
+    let result = match gradcheck::check_random_network::<N>(&gc_cfg.topology,
+                                                             gc_cfg.samples,
+                                                             gc_cfg.batch_norm,
+                                                             gc_cfg.output,
+                                                             gc_cfg.cost,
+                                                             N::from_f64(gc_cfg.epsilon)) {
+        Err(msg) => {
+            error!("Could not create network: {}", msg);
+            return;
+        }
+        Ok(result) => result,
+    };
+
+    info!("Checked {} parameters, largest relative error: {:e} ({})",
+          result.n_checked,
+          result.max_error,
+          result.worst_parameter);
+    if result.passed(gc_cfg.tolerance) {
+        info!("Gradient check passed!");
+    } else {
+        error!("Gradient check failed, the relative error exceeds {:e}!",
+               gc_cfg.tolerance);
+    }
 }
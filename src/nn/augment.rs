@@ -0,0 +1,245 @@
+//! Random transformations of training images.
+//!
+//! Each training image is transformed anew whenever it is part of a mini batch, so the network
+//! sees slightly different images in every epoch and generalizes better. The transformations are
+//! given as a list of tokens:
+//!
+//! * `shift:PIXELS` - shift by up to `PIXELS` pixels horizontally and vertically
+//! * `rotate:DEGREES` - rotate by up to `DEGREES` degrees around the center
+//! * `scale:FRACTION` - scale by a factor between `1 - FRACTION` and `1 + FRACTION`
+//! * `elastic:ALPHA[:SIGMA]` - elastic distortion: a random displacement field, smoothed by a
+//!   Gaussian with standard deviation `SIGMA` (default 4) and scaled by `ALPHA`
+//! * `noise:STDDEV` - add Gaussian noise to every pixel, the result is kept in `[0, 1]`
+//!
+//! For example `shift:2 rotate:10 elastic:34:4` works well for MNIST.
+
+use na::DVector;
+use nn::{Float, Shape};
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+use structs::Data;
+
+/// Random transformations that are applied to the training images of each mini batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Augmentation {
+    /// largest shift in pixels, in each direction
+    pub shift: f64,
+    /// largest rotation in degrees, in each direction
+    pub rotation: f64,
+    /// largest relative change of the size, e.g. 0.1 for scaling by 0.9 to 1.1
+    pub scale: f64,
+    /// strength (alpha) and smoothness (sigma) of elastic distortions, alpha 0 disables them
+    pub elastic: (f64, f64),
+    /// standard deviation of the Gaussian noise added to each pixel
+    pub noise: f64,
+    /// seed of the random number generator, so training runs can be repeated
+    pub seed: usize,
+}
+
+impl Default for Augmentation {
+    /// No transformation at all
+    fn default() -> Self {
+        Augmentation {
+            shift: 0.0,
+            rotation: 0.0,
+            scale: 0.0,
+            elastic: (0.0, 4.0),
+            noise: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl Augmentation {
+    /// Parse the transformations from tokens, see the module documentation
+    pub fn parse<'a, I>(tokens: I) -> Result<Augmentation, &'static str>
+        where I: IntoIterator<Item = &'a str>
+    {
+        let mut augmentation = Augmentation::default();
+        for token in tokens {
+            let parts: Vec<&str> = token.split(':').collect();
+            let values = parts[1..]
+                .iter()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| "augmentation values must be numbers")?;
+            if values.iter().any(|v| *v < 0.0) {
+                return Err("augmentation values must not be negative");
+            }
+            match (parts[0], values.len()) {
+                ("shift", 1) => augmentation.shift = values[0],
+                ("rotate", 1) => augmentation.rotation = values[0],
+                ("scale", 1) if values[0] < 1.0 => augmentation.scale = values[0],
+                ("scale", 1) => return Err("scaling must be smaller than 1"),
+                ("elastic", 1) => augmentation.elastic.0 = values[0],
+                ("elastic", 2) => augmentation.elastic = (values[0], values[1]),
+                ("noise", 1) => augmentation.noise = values[0],
+                _ => return Err("unknown augmentation, see 'learn --help'"),
+            }
+        }
+        Ok(augmentation)
+    }
+
+    /// Return a randomly transformed copy of an image
+    ///
+    /// All channels of the image are transformed the same way, the class vector is kept.
+    pub fn apply<N: Float, R: Rng>(&self, data: &Data<N>, rng: &mut R) -> Data<N> {
+        let shape = data.get_shape();
+        let image: Vec<f64> = data.get_input().at.iter().map(|&x| x.to_f64()).collect();
+
+        let angle = uniform(rng, self.rotation).to_radians();
+        let scale = 1.0 + uniform(rng, self.scale);
+        let shift = (uniform(rng, self.shift), uniform(rng, self.shift));
+        let displacement = if self.elastic.0 > 0.0 {
+            Some(elastic_field(shape, self.elastic.0, self.elastic.1, rng))
+        } else {
+            None
+        };
+        let mut warped = warp(&image, shape, angle, scale, shift, displacement.as_ref());
+
+        if self.noise > 0.0 {
+            for x in &mut warped {
+                let StandardNormal(n) = rng.gen();
+                *x = (*x + n * self.noise).max(0.0).min(1.0);
+            }
+        }
+        data.clone().with_input(DVector { at: warped.into_iter().map(N::from_f64).collect() })
+    }
+}
+
+// A uniformly distributed random number in `[-max, max]`
+fn uniform<R: Rng>(rng: &mut R, max: f64) -> f64 {
+    if max > 0.0 {
+        rng.gen_range(-max, max)
+    } else {
+        0.0
+    }
+}
+
+// Rotate the image by `angle` (radians) around its center, scale it by `scale` and shift it by
+// `shift` (vertical, horizontal). The optional displacement field moves the position each output
+// pixel is taken from, e.g. for elastic distortions. Pixels taken from outside the image are 0.
+fn warp(image: &[f64],
+        shape: Shape,
+        angle: f64,
+        scale: f64,
+        shift: (f64, f64),
+        displacement: Option<&(Vec<f64>, Vec<f64>)>)
+        -> Vec<f64> {
+    let (height, width) = (shape.height, shape.width);
+    let area = height * width;
+    let center = ((height as f64 - 1.0) / 2.0, (width as f64 - 1.0) / 2.0);
+    let (sin, cos) = angle.sin_cos();
+
+    let mut warped = vec![0.0; image.len()];
+    for y in 0..height {
+        for x in 0..width {
+            // map the output pixel back to the position in the input it comes from
+            let v = y as f64 - center.0 - shift.0;
+            let u = x as f64 - center.1 - shift.1;
+            let mut sy = (-sin * u + cos * v) / scale + center.0;
+            let mut sx = (cos * u + sin * v) / scale + center.1;
+            if let Some(&(ref dy, ref dx)) = displacement {
+                sy += dy[y * width + x];
+                sx += dx[y * width + x];
+            }
+            for (channel, out) in image.chunks(area).zip(warped.chunks_mut(area)) {
+                out[y * width + x] = bilinear(channel, height, width, sy, sx);
+            }
+        }
+    }
+    warped
+}
+
+// Interpolate the pixel value at a position between pixels, the image is 0 outside its borders
+fn bilinear(image: &[f64], height: usize, width: usize, y: f64, x: f64) -> f64 {
+    let (y0, x0) = (y.floor(), x.floor());
+    let (fy, fx) = (y - y0, x - x0);
+    let pixel = |y: f64, x: f64| if y < 0.0 || x < 0.0 || y >= height as f64 ||
+                                    x >= width as f64 {
+        0.0
+    } else {
+        image[y as usize * width + x as usize]
+    };
+    (1.0 - fy) * ((1.0 - fx) * pixel(y0, x0) + fx * pixel(y0, x0 + 1.0)) +
+    fy * ((1.0 - fx) * pixel(y0 + 1.0, x0) + fx * pixel(y0 + 1.0, x0 + 1.0))
+}
+
+// A random displacement field (vertical, horizontal) for elastic distortions: uniform noise in
+// `[-1, 1]`, smoothed by a Gaussian with standard deviation `sigma` and scaled by `alpha`
+fn elastic_field<R: Rng>(shape: Shape,
+                         alpha: f64,
+                         sigma: f64,
+                         rng: &mut R)
+                         -> (Vec<f64>, Vec<f64>) {
+    let area = shape.height * shape.width;
+    let mut field = || {
+        let noise: Vec<f64> = (0..area).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+        let mut smooth = gaussian_blur(&noise, shape.height, shape.width, sigma);
+        for d in &mut smooth {
+            *d *= alpha;
+        }
+        smooth
+    };
+    let dy = field();
+    let dx = field();
+    (dy, dx)
+}
+
+// Convolve the image with a Gaussian, first along the rows and then along the columns
+fn gaussian_blur(image: &[f64], height: usize, width: usize, sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return image.to_vec();
+    }
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..radius + 1)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let norm: f64 = kernel.iter().sum();
+
+    let blur = |image: &[f64], step: (isize, isize)| {
+        let mut blurred = vec![0.0; image.len()];
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut sum = 0.0;
+                for (k, w) in kernel.iter().enumerate() {
+                    let (yy, xx) = (y + (k as isize - radius) * step.0,
+                                    x + (k as isize - radius) * step.1);
+                    if yy >= 0 && xx >= 0 && yy < height as isize && xx < width as isize {
+                        sum += w * image[yy as usize * width + xx as usize];
+                    }
+                }
+                blurred[y as usize * width + x as usize] = sum / norm;
+            }
+        }
+        blurred
+    };
+    let rows = blur(image, (0, 1));
+    blur(&rows, (1, 0))
+}
+
+
+#[test]
+fn test_warp() {
+    // a single bright pixel in a 5x5 image
+    let mut image = vec![0.0; 25];
+    image[2 * 5 + 1] = 1.0;
+    let shape = Shape::new(5, 5, 1);
+    assert_eq!(warp(&image, shape, 0.0, 1.0, (0.0, 0.0), None), image);
+
+    // shifted one pixel down and two to the right
+    let shifted = warp(&image, shape, 0.0, 1.0, (1.0, 2.0), None);
+    assert_eq!(shifted[3 * 5 + 3], 1.0);
+    assert_eq!(shifted.iter().sum::<f64>(), 1.0);
+
+    // a quarter turn moves the pixel from left of the center to above or below it
+    let rotated = warp(&image, shape, 90f64.to_radians(), 1.0, (0.0, 0.0), None);
+    assert!((rotated[5 + 2] + rotated[3 * 5 + 2] - 1.0).abs() < 1e-9);
+
+    let tokens = "shift:2 rotate:10 elastic:34".split(' ');
+    let augmentation = Augmentation::parse(tokens).unwrap();
+    assert_eq!((augmentation.shift, augmentation.rotation), (2.0, 10.0));
+    assert_eq!(augmentation.elastic, (34.0, 4.0));
+    assert!(Augmentation::parse(vec!["flip"]).is_err());
+    assert!(Augmentation::parse(vec!["noise:-1"]).is_err());
+}
@@ -0,0 +1,193 @@
+use nn::{Activation, ActivationLayer, BatchNorm, Conv2D, Dense, Dropout, Float, Initializer,
+         Layer, LayerSpec, Network, Pool2D, Recurrent, Shape, Topology};
+use rand::{self, Rng, SeedableRng, StdRng};
+
+/// Builds a `Network` from a `Topology`
+///
+/// Hidden dense and convolutional layers are followed by the hidden `activation`, sigmoid by
+/// default, the output layer by the `output` activation, e.g. `Linear` for regression. The states
+/// of recurrent layers are already squashed by their cells.
+///
+/// # Example
+///
+/// ```
+/// use rustle_my_net::nn::{Initializer, Network, NetworkBuilder, Topology};
+///
+/// let nn: Network = NetworkBuilder::new(Topology::dense(&[4, 5, 3]))
+///     .keep_probs(&[1.0, 0.8])
+///     .batch_norm(true)
+///     .initializer(Initializer::Xavier)
+///     .seed(7)
+///     .build()
+///     .unwrap();
+/// assert_eq!(nn.output_size(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct NetworkBuilder<N: Float = f32> {
+    /// the input shape and the layers
+    topology: Topology,
+    /// the dropout keep probability of the input and each hidden layer, `None` disables dropout
+    keep_probs: Option<Vec<N>>,
+    /// whether the hidden layers are batch normalized
+    batch_norm: bool,
+    /// the activation of the hidden layers
+    activation: Activation,
+    /// the activation of the output layer
+    output: Activation,
+    /// how the weights and biases are initialised
+    initializer: Initializer,
+    /// seed of the initialisation, `None` draws a different network every time
+    seed: Option<usize>,
+}
+
+impl<N: Float> NetworkBuilder<N> {
+    /// Start building a network with the layers of `topology`
+    pub fn new(topology: Topology) -> NetworkBuilder<N> {
+        NetworkBuilder {
+            topology: topology,
+            keep_probs: None,
+            batch_norm: false,
+            activation: Activation::Sigmoid,
+            output: Activation::Sigmoid,
+            initializer: Initializer::Default,
+            seed: None,
+        }
+    }
+
+    /// Set one dropout keep probability for the input and each hidden layer, each in the range
+    /// `(0, 1]`
+    ///
+    /// During training each neuron of a layer is kept with the given probability and switched off
+    /// otherwise (inverted dropout), a probability of 1 disables dropout for that layer.
+    pub fn keep_probs(mut self, keep_probs: &[N]) -> NetworkBuilder<N> {
+        self.keep_probs = Some(keep_probs.to_vec());
+        self
+    }
+
+    /// Batch normalize the weighted inputs of all hidden dense and convolutional layers
+    pub fn batch_norm(mut self, batch_norm: bool) -> NetworkBuilder<N> {
+        self.batch_norm = batch_norm;
+        self
+    }
+
+    /// Set the activation of the hidden dense and convolutional layers
+    pub fn activation(mut self, activation: Activation) -> NetworkBuilder<N> {
+        self.activation = activation;
+        self
+    }
+
+    /// Set the activation of the output layer
+    pub fn output(mut self, output: Activation) -> NetworkBuilder<N> {
+        self.output = output;
+        self
+    }
+
+    /// Set how the weights and biases are initialised
+    pub fn initializer(mut self, initializer: Initializer) -> NetworkBuilder<N> {
+        self.initializer = initializer;
+        self
+    }
+
+    /// Initialise the network from `seed`, so the same network is built every time
+    pub fn seed(mut self, seed: usize) -> NetworkBuilder<N> {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Build the network
+    ///
+    /// Returns an error if the layers do not fit together or the number of keep probabilities
+    /// does not match the layers.
+    pub fn build(&self) -> Result<Network<N>, &'static str> {
+        let topology = &self.topology;
+        let keep_probs = self.keep_probs
+            .clone()
+            .unwrap_or_else(|| vec![N::one(); topology.layers.len()]);
+        if keep_probs.len() != topology.layers.len() {
+            return Err("one keep probability per input and hidden layer required");
+        }
+
+        let mut layers: Vec<Box<Layer<N>>> = Vec::new();
+        let mut shape = topology.input;
+        for (l, (spec, keep_prob)) in topology.layers.iter().zip(keep_probs.iter()).enumerate() {
+            // randomly switch off neurons of the layer that feeds into this one
+            if *keep_prob < N::one() {
+                layers.push(Box::new(Dropout::new(*keep_prob)));
+            }
+            let weighted = match *spec {
+                LayerSpec::Dense(size) => {
+                    layers.push(Box::new(Dense::new(shape.len(), size)));
+                    shape = Shape::flat(size);
+                    true
+                }
+                LayerSpec::Conv { channels, kernel, stride, padding } => {
+                    let conv = Conv2D::new(shape, channels, kernel, stride, padding)?;
+                    shape = conv.get_output_shape();
+                    layers.push(Box::new(conv));
+                    true
+                }
+                LayerSpec::Pool { mode, size, stride } => {
+                    let pool = Pool2D::new(mode, shape, size, stride)?;
+                    shape = pool.get_output_shape();
+                    layers.push(Box::new(pool));
+                    false
+                }
+                LayerSpec::Recurrent { cell, size, sequences, bptt_length } => {
+                    let rnn = Recurrent::new(cell, shape, size, sequences, bptt_length)?;
+                    shape = rnn.get_output_shape();
+                    layers.push(Box::new(rnn));
+                    false
+                }
+            };
+            if weighted {
+                // batch normalization is only used on the hidden layers
+                if l + 1 < topology.layers.len() {
+                    if self.batch_norm {
+                        layers.push(Box::new(BatchNorm::new(shape.len())));
+                    }
+                    layers.push(Box::new(ActivationLayer::new(self.activation)));
+                } else {
+                    layers.push(Box::new(ActivationLayer::new(self.output)));
+                }
+            }
+        }
+
+        // the layers initialise themselves, they are only drawn again if that needs to be
+        // repeatable or done differently
+        if self.seed.is_some() || self.initializer != Initializer::Default {
+            let mut rng: Box<Rng> = match self.seed {
+                Some(seed) => Box::new(StdRng::from_seed(&[seed][..])),
+                None => Box::new(rand::thread_rng()),
+            };
+            for layer in &mut layers {
+                layer.initialize(self.initializer, &mut *rng);
+            }
+        }
+        Network::from_layers(topology.input.len(), layers)
+    }
+}
+
+
+#[test]
+fn test_network_builder() {
+    use na::DVector;
+
+    // the same seed builds the same network
+    let builder = NetworkBuilder::<f64>::new(Topology::dense(&[4, 5, 3])).seed(3);
+    let input = DVector::from_slice(4, &[0.1, 0.2, 0.3, 0.4]);
+    let output = builder.build().unwrap().feedforward(&input);
+    assert_eq!(builder.build().unwrap().feedforward(&input), output);
+    assert!(builder.clone().seed(4).build().unwrap().feedforward(&input) != output);
+
+    // Xavier initialises the biases with zero and keeps the weights in its limits
+    let nn = builder.clone().initializer(Initializer::Xavier).build().unwrap();
+    let dense: Vec<_> = nn.get_layers().iter().filter(|l| l.kind() == "dense").collect();
+    assert!(dense[0].parameters()[0].iter().all(|w| w.abs() <= (6.0f64 / 9.0).sqrt()));
+    assert!(dense[1].parameters()[1].iter().all(|b| *b == 0.0));
+
+    let nn = builder.clone().keep_probs(&[0.5, 1.0]).batch_norm(true).build().unwrap();
+    let kinds: Vec<_> = nn.get_layers().iter().map(|l| l.kind()).collect();
+    assert_eq!(kinds,
+               vec!["dropout", "dense", "batch norm", "activation", "dense", "activation"]);
+    assert!(builder.keep_probs(&[0.5]).build().is_err());
+}
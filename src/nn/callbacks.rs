@@ -0,0 +1,297 @@
+//! Hooks into the training of a `Trainer`.
+//!
+//! A `Callback` is told when training and every epoch begin and end and after every mini batch.
+//! It sees the network and the metrics of the epoch, and can stop the training early. Besides
+//! custom metrics or notifications, the built-in callbacks save the best network
+//! (`Checkpoint`), stop once the network no longer improves (`EarlyStopping`) and write the
+//! history while training is still running (`HistoryLogger`).
+
+use nn::{Float, Network};
+use nn::history::{EpochRecord, TrainingHistory};
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+/// Whether the training goes on after a callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// keep on training
+    Continue,
+    /// stop the training, an epoch in progress ends after the current mini batch but is still
+    /// validated and recorded
+    Stop,
+}
+
+/// Custom logic that runs during the training of a `Trainer`
+///
+/// All methods do nothing by default, so only the needed ones have to be implemented.
+pub trait Callback<N: Float>: Debug {
+    /// Called before the first epoch
+    fn on_train_begin(&mut self, _nn: &Network<N>) {}
+
+    /// Called at the beginning of `epoch`, counted from 1
+    fn on_epoch_begin(&mut self, _epoch: u32, _nn: &Network<N>) {}
+
+    /// Called after every mini batch of `epoch` with the mean cost per sample of the batch,
+    /// before the parameters were updated
+    fn on_batch_end(&mut self, _epoch: u32, _batch: usize, _cost: f64, _nn: &Network<N>)
+                    -> Control {
+        Control::Continue
+    }
+
+    /// Called after each epoch was validated, with the record of the epoch
+    fn on_epoch_end(&mut self, _record: &EpochRecord, _nn: &Network<N>) -> Control {
+        Control::Continue
+    }
+
+    /// Called after the last epoch with the history of the whole training
+    fn on_train_end(&mut self, _history: &TrainingHistory, _nn: &Network<N>) {}
+}
+
+/// A metric of an epoch that callbacks watch for improvements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Monitor {
+    /// the mean training cost, lower is better
+    TrainingCost,
+    /// the mean validation cost, lower is better
+    ValidationCost,
+    /// the accuracy on the test data, higher is better
+    Accuracy,
+}
+
+impl Monitor {
+    /// The value of the metric in `record`, `None` if the epoch was not validated
+    pub fn value(&self, record: &EpochRecord) -> Option<f64> {
+        match *self {
+            Monitor::TrainingCost => Some(record.training_cost),
+            Monitor::ValidationCost => record.validation_cost,
+            Monitor::Accuracy => record.accuracy,
+        }
+    }
+
+    /// Whether `value` is better than `best` by more than `min_delta`. Every value improves on no
+    /// value or NaN, NaN never improves.
+    pub fn improves(&self, value: f64, best: Option<f64>, min_delta: f64) -> bool {
+        match best {
+            _ if value.is_nan() => false,
+            None => true,
+            Some(best) if best.is_nan() => true,
+            Some(best) => {
+                match *self {
+                    Monitor::Accuracy => value > best + min_delta,
+                    _ => value < best - min_delta,
+                }
+            }
+        }
+    }
+}
+
+/// Saves the network to a file whenever the monitored metric reaches a new best value
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// the file the network is saved to
+    path: PathBuf,
+    /// the metric that decides which network is the best
+    monitor: Monitor,
+    /// the best value so far
+    best: Option<f64>,
+}
+
+impl Checkpoint {
+    /// Save the network with the lowest validation cost to `path`
+    pub fn new<P: Into<PathBuf>>(path: P) -> Checkpoint {
+        Checkpoint {
+            path: path.into(),
+            monitor: Monitor::ValidationCost,
+            best: None,
+        }
+    }
+
+    /// Save the best network by `monitor` instead
+    pub fn monitor(mut self, monitor: Monitor) -> Checkpoint {
+        self.monitor = monitor;
+        self
+    }
+
+    /// The best value of the monitored metric so far
+    pub fn best(&self) -> Option<f64> {
+        self.best
+    }
+}
+
+impl<N: Float> Callback<N> for Checkpoint {
+    fn on_train_begin(&mut self, _nn: &Network<N>) {
+        self.best = None;
+    }
+
+    fn on_epoch_end(&mut self, record: &EpochRecord, nn: &Network<N>) -> Control {
+        if let Some(value) = self.monitor.value(record) {
+            if self.monitor.improves(value, self.best, 0.0) {
+                self.best = Some(value);
+                debug!("Epoch {}: saving checkpoint to {}", record.epoch, self.path.display());
+                nn.clone().save_to_file(&self.path).unwrap_or_else(|e| {
+                    error!("Could not save checkpoint: {}", e);
+                });
+            }
+        }
+        Control::Continue
+    }
+}
+
+/// Stops the training once the monitored metric has not improved for a number of epochs
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+    /// the metric that has to improve
+    monitor: Monitor,
+    /// the number of epochs without improvement before the training is stopped
+    patience: u32,
+    /// the smallest change that counts as an improvement
+    min_delta: f64,
+    /// the best value so far
+    best: Option<f64>,
+    /// the number of epochs since the last improvement
+    waited: u32,
+}
+
+impl EarlyStopping {
+    /// Stop once the validation cost has not improved for `patience` epochs
+    pub fn new(patience: u32) -> EarlyStopping {
+        EarlyStopping {
+            monitor: Monitor::ValidationCost,
+            patience: patience,
+            min_delta: 0.0,
+            best: None,
+            waited: 0,
+        }
+    }
+
+    /// Watch `monitor` instead
+    pub fn monitor(mut self, monitor: Monitor) -> EarlyStopping {
+        self.monitor = monitor;
+        self
+    }
+
+    /// Only count changes larger than `min_delta` as improvements
+    pub fn min_delta(mut self, min_delta: f64) -> EarlyStopping {
+        self.min_delta = min_delta;
+        self
+    }
+}
+
+impl<N: Float> Callback<N> for EarlyStopping {
+    fn on_train_begin(&mut self, _nn: &Network<N>) {
+        self.best = None;
+        self.waited = 0;
+    }
+
+    fn on_epoch_end(&mut self, record: &EpochRecord, _nn: &Network<N>) -> Control {
+        let value = match self.monitor.value(record) {
+            Some(value) => value,
+            None => return Control::Continue,
+        };
+        if self.monitor.improves(value, self.best, self.min_delta) {
+            self.best = Some(value);
+            self.waited = 0;
+            return Control::Continue;
+        }
+        self.waited += 1;
+        if self.waited < self.patience {
+            return Control::Continue;
+        }
+        info!("Stopping early after epoch {}, no improvement in {} epochs",
+              record.epoch,
+              self.waited);
+        Control::Stop
+    }
+}
+
+/// Writes the history of the training to a file after every epoch, so it is also available while
+/// the training is running or if it is interrupted
+///
+/// The file is written as JSON if it ends in `.json` and as CSV otherwise.
+#[derive(Debug, Clone)]
+pub struct HistoryLogger {
+    /// the file the history is written to
+    path: PathBuf,
+    /// the history so far
+    history: TrainingHistory,
+}
+
+impl HistoryLogger {
+    /// Write the history to `path`
+    pub fn new<P: Into<PathBuf>>(path: P) -> HistoryLogger {
+        HistoryLogger {
+            path: path.into(),
+            history: TrainingHistory::new(),
+        }
+    }
+}
+
+impl<N: Float> Callback<N> for HistoryLogger {
+    fn on_train_begin(&mut self, _nn: &Network<N>) {
+        self.history = TrainingHistory::new();
+    }
+
+    fn on_epoch_end(&mut self, record: &EpochRecord, _nn: &Network<N>) -> Control {
+        self.history.push(record.clone());
+        self.history.save(&self.path).unwrap_or_else(|e| {
+            error!("Could not write training history: {}", e);
+        });
+        Control::Continue
+    }
+}
+
+
+#[test]
+fn test_early_stopping() {
+    let record = |epoch, validation_cost| {
+        EpochRecord {
+            epoch: epoch,
+            training_cost: 1.0,
+            validation_cost: Some(validation_cost),
+            accuracy: None,
+            training_accuracy: None,
+            learning_rate: 0.1,
+            gradient_norm: 1.0,
+            max_gradient_norm: 1.0,
+            seconds: 0.0,
+        }
+    };
+    let nn: Network = Network::new(&[2, 2]).unwrap();
+    let mut stopping = EarlyStopping::new(2).min_delta(0.01);
+    Callback::<f32>::on_train_begin(&mut stopping, &nn);
+    let controls: Vec<Control> = [0.5, 0.4, 0.395, 0.3, 0.31, 0.305]
+        .iter()
+        .enumerate()
+        .map(|(i, &cost)| stopping.on_epoch_end(&record(i as u32 + 1, cost), &nn))
+        .collect();
+    assert_eq!(controls,
+               vec![Control::Continue, Control::Continue, Control::Continue, Control::Continue,
+                    Control::Continue, Control::Stop]);
+
+    // accuracy has to rise, NaN never improves
+    assert!(Monitor::Accuracy.improves(0.8, Some(0.7), 0.0));
+    assert!(!Monitor::ValidationCost.improves(0.8, Some(0.7), 0.0));
+    assert!(!Monitor::ValidationCost.improves(::std::f64::NAN, None, 0.0));
+}
+
+#[test]
+fn test_checkpoint_error() {
+    let record = EpochRecord {
+        epoch: 1,
+        training_cost: 1.0,
+        validation_cost: Some(0.5),
+        accuracy: None,
+        training_accuracy: None,
+        learning_rate: 0.1,
+        gradient_norm: 1.0,
+        max_gradient_norm: 1.0,
+        seconds: 0.0,
+    };
+    let nn: Network = Network::new(&[2, 2]).unwrap();
+    // a checkpoint that cannot be written is logged, the training goes on
+    let path = ::std::env::temp_dir().join("rustle-my-net-missing").join("checkpoint.json");
+    let mut checkpoint = Checkpoint::new(path);
+    Callback::<f32>::on_train_begin(&mut checkpoint, &nn);
+    assert_eq!(checkpoint.on_epoch_end(&record, &nn), Control::Continue);
+    assert_eq!(checkpoint.best(), Some(0.5));
+}
@@ -0,0 +1,183 @@
+use na::DMatrix;
+use nn::Float;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// Cost functions that measure the error of the outputs of a network
+///
+/// The cost of a batch is the sum of the costs of all outputs of all samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cost {
+    /// The quadratic cost `0.5 * (a - y)^2`, i.e. half the squared error (MSE)
+    Quadratic,
+    /// The absolute error `|a - y|` (MAE), which is less sensitive to outliers
+    Absolute,
+    /// The Huber loss with threshold `delta`: quadratic for errors up to `delta` and linear for
+    /// larger errors
+    Huber(f64),
+    /// The binary cross-entropy `-(y * ln(a) + (1 - y) * ln(1 - a))` of outputs in `(0, 1)`,
+    /// e.g. of independent sigmoid outputs for multi-label classification
+    CrossEntropy,
+}
+
+// Outputs are clamped to `[CLIP, 1 - CLIP]` for the cross-entropy, so saturated outputs do not
+// produce infinite costs
+const CLIP: f64 = 1e-6;
+
+impl Default for Cost {
+    fn default() -> Self {
+        Cost::Quadratic
+    }
+}
+
+impl Cost {
+    /// The cost of the `outputs` of a batch, summed over all samples (columns)
+    pub fn value<N: Float>(&self, outputs: &DMatrix<N>, desired_outputs: &DMatrix<N>) -> N {
+        self.sample_values(outputs, desired_outputs).into_iter().fold(N::zero(), |sum, c| sum + c)
+    }
+
+    /// The cost of each sample (column) of a batch
+    pub fn sample_values<N: Float>(&self,
+                                   outputs: &DMatrix<N>,
+                                   desired_outputs: &DMatrix<N>)
+                                   -> Vec<N> {
+        let n_outputs = outputs.nrows();
+        let samples = outputs.as_vector()
+            .chunks(n_outputs)
+            .zip(desired_outputs.as_vector().chunks(n_outputs));
+        samples.map(|(a, y)| {
+                let mut cost = N::zero();
+                for (a, y) in a.iter().zip(y.iter()) {
+                    cost += self.element_value(*a, *y);
+                }
+                cost
+            })
+            .collect()
+    }
+
+    // The cost of a single output `a` with desired value `y`
+    fn element_value<N: Float>(&self, a: N, y: N) -> N {
+        let r = a - y;
+        match *self {
+            Cost::Quadratic => r * r / N::from_f64(2.0),
+            Cost::Absolute => r.abs(),
+            Cost::Huber(delta) => {
+                let delta = N::from_f64(delta);
+                if r.abs() <= delta {
+                    r * r / N::from_f64(2.0)
+                } else {
+                    delta * (r.abs() - delta / N::from_f64(2.0))
+                }
+            }
+            Cost::CrossEntropy => {
+                let a = clip(a);
+                -(y * a.ln() + (N::one() - y) * (N::one() - a).ln())
+            }
+        }
+    }
+
+    /// The derivative of the cost with respect to each output
+    pub fn derivative<N: Float>(&self,
+                                outputs: &DMatrix<N>,
+                                desired_outputs: &DMatrix<N>)
+                                -> DMatrix<N> {
+        let mut delta = outputs.clone() - desired_outputs;
+        let outputs = outputs.as_vector().iter();
+        for (r, a) in delta.as_mut_vector().iter_mut().zip(outputs) {
+            *r = match *self {
+                Cost::Quadratic => *r,
+                // the derivative at 0 is taken to be 0
+                Cost::Absolute => {
+                    if *r == N::zero() {
+                        N::zero()
+                    } else {
+                        r.signum()
+                    }
+                }
+                Cost::Huber(delta) => {
+                    let delta = N::from_f64(delta);
+                    r.max(-delta).min(delta)
+                }
+                // (a - y) / (a * (1 - a)), the cost is flat where the output is clipped.
+                // Backpropagation does not use it after a sigmoid, but the fused `a - y`.
+                Cost::CrossEntropy => {
+                    if clip(*a) != *a {
+                        N::zero()
+                    } else {
+                        *r / (*a * (N::one() - *a))
+                    }
+                }
+            };
+        }
+        delta
+    }
+}
+
+impl FromStr for Cost {
+    type Err = io::Error;
+
+    /// Parse a cost function: `mse`, `mae`, `huber[:DELTA]` or `bce`, the default threshold of
+    /// the Huber loss is 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match (parts[0], parts.len()) {
+            ("mse", 1) => Ok(Cost::Quadratic),
+            ("mae", 1) => Ok(Cost::Absolute),
+            ("huber", 1) => Ok(Cost::Huber(1.0)),
+            ("bce", 1) => Ok(Cost::CrossEntropy),
+            ("huber", 2) => {
+                match parts[1].parse() {
+                    Ok(delta) if delta > 0.0 => Ok(Cost::Huber(delta)),
+                    _ => {
+                        Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "The threshold of the Huber loss must be positive."))
+                    }
+                }
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown cost function.")),
+        }
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cost::Quadratic => write!(f, "mse"),
+            Cost::Absolute => write!(f, "mae"),
+            Cost::Huber(delta) => write!(f, "huber:{}", delta),
+            Cost::CrossEntropy => write!(f, "bce"),
+        }
+    }
+}
+
+// Clamp an output into the range the cross-entropy is evaluated in
+fn clip<N: Float>(a: N) -> N {
+    let clip = N::from_f64(CLIP);
+    a.max(clip).min(N::one() - clip)
+}
+
+
+#[test]
+fn test_costs() {
+    let outputs = DMatrix::from_column_vector(3, 1, &[0.5, 3.0, -1.0]);
+    let desired = DMatrix::from_column_vector(3, 1, &[0.0, 1.0, -1.0]);
+    assert_eq!(Cost::Quadratic.value(&outputs, &desired), 0.125 + 2.0);
+    assert_eq!(Cost::Absolute.value(&outputs, &desired), 2.5);
+    assert_eq!(Cost::Absolute.derivative(&outputs, &desired).as_vector(),
+               &[1.0, 1.0, 0.0]);
+    // the error of 2 is in the linear part of the Huber loss
+    let huber: Cost = "huber:1".parse().unwrap();
+    assert_eq!(huber.value(&outputs, &desired), 0.125 + 1.5);
+    assert_eq!(huber.derivative(&outputs, &desired).as_vector(), &[0.5, 1.0, 0.0]);
+    assert!("huber:0".parse::<Cost>().is_err());
+
+    let outputs = DMatrix::from_column_vector(2, 1, &[0.8, 1.0]);
+    let labels = DMatrix::from_column_vector(2, 1, &[1.0, 0.0]);
+    let bce = Cost::CrossEntropy.value(&outputs, &labels);
+    // a saturated wrong output gives a large, but finite cost
+    assert!((bce - (-(0.8f64.ln()) - (1e-6f64).ln())).abs() < 1e-6);
+    let derivative = Cost::CrossEntropy.derivative(&outputs, &labels);
+    assert!((derivative[(0, 0)] + 1.0 / 0.8).abs() < 1e-12);
+    assert_eq!(derivative[(1, 0)], 0.0);
+}
@@ -0,0 +1,91 @@
+use na::BaseFloat;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::str::FromStr;
+
+/// The floating point precision a `Network` is computed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precision {
+    /// 32 bit floats (`f32`), fast and good enough for training
+    Single,
+    /// 64 bit floats (`f64`), slower but useful for debugging and numerical checks
+    Double,
+}
+
+impl Default for Precision {
+    /// Networks saved before the precision was recorded were always computed in `f32`
+    fn default() -> Self {
+        Precision::Single
+    }
+}
+
+impl FromStr for Precision {
+    type Err = io::Error;
+
+    /// Parse a precision from its rust type name (`f32` or `f64`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(Precision::Single),
+            "f64" => Ok(Precision::Double),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown precision.")),
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Precision::Single => write!(f, "f32"),
+            Precision::Double => write!(f, "f64"),
+        }
+    }
+}
+
+/// Floating point type that networks, data and learning are generic over
+///
+/// It is implemented for `f32` and `f64`.
+pub trait Float
+    : BaseFloat + Debug + Default + Display + Serialize + Deserialize + Send + Sync + 'static {
+    /// The `Precision` that corresponds to this type
+    fn precision() -> Precision;
+
+    /// Convert a `f64` into this type, possibly losing precision
+    fn from_f64(x: f64) -> Self;
+
+    /// Convert this value into a `f64`
+    fn to_f64(self) -> f64;
+
+    /// Convert a value of another float type into this type
+    fn from_float<M: Float>(x: M) -> Self {
+        Self::from_f64(x.to_f64())
+    }
+}
+
+impl Float for f32 {
+    fn precision() -> Precision {
+        Precision::Single
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Float for f64 {
+    fn precision() -> Precision {
+        Precision::Double
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
@@ -0,0 +1,258 @@
+//! Numerical verification of the gradients calculated by `learning::backprop`.
+//!
+//! Every parameter of every layer of a network is nudged by `+epsilon` and `-epsilon` and the
+//! resulting change of the cost is compared to the analytic gradient (central finite
+//! differences). Use `f64` networks for this, in `f32` the rounding errors of the finite
+//! differences are too large to tell a correct gradient from a slightly wrong one.
+
+use na::DMatrix;
+use nn::learning;
+use nn::{Activation, Cost, Float, Network, Topology};
+use rand::{self, Rng};
+use rand::distributions::normal::StandardNormal;
+
+/// The outcome of comparing analytic and numerical gradients
+#[derive(Debug, Clone)]
+pub struct GradCheckResult {
+    /// Number of parameters that were checked
+    pub n_checked: usize,
+    /// The largest relative error between an analytic and a numerical derivative
+    pub max_error: f64,
+    /// Description of the parameter with the largest error,
+    /// e.g. "element 3 of parameter 0 of layer 1 (dense)"
+    pub worst_parameter: String,
+}
+
+impl GradCheckResult {
+    fn new() -> GradCheckResult {
+        GradCheckResult {
+            n_checked: 0,
+            max_error: 0.0,
+            worst_parameter: String::new(),
+        }
+    }
+
+    /// Returns whether no relative error was larger than `tolerance`
+    pub fn passed(&self, tolerance: f64) -> bool {
+        self.max_error <= tolerance
+    }
+
+    // Record the comparison of the analytic and numerical derivative of one parameter
+    fn record<F>(&mut self, analytic: f64, numerical: f64, describe: F)
+        where F: FnOnce() -> String
+    {
+        self.n_checked += 1;
+        let error = relative_error(analytic, numerical);
+        if error > self.max_error || self.worst_parameter.is_empty() {
+            self.max_error = error;
+            self.worst_parameter = describe();
+        }
+    }
+}
+
+/// Compare the gradients of `cost` `backprop` calculates for a batch with central finite
+/// differences of the cost, for every parameter of every layer of `nn`.
+///
+/// Each column of `inputs` is one sample. Dropout must be disabled, otherwise the cost is not
+/// deterministic. The network is modified during the check, but all parameters are restored
+/// afterwards (only the running statistics of batch normalization change).
+pub fn check_gradients<N: Float>(nn: &mut Network<N>,
+                                 inputs: &DMatrix<N>,
+                                 desired_outputs: &DMatrix<N>,
+                                 cost: Cost,
+                                 epsilon: N)
+                                 -> GradCheckResult {
+    learning::backprop(nn, inputs, desired_outputs, cost, None);
+    // the gradients are copied, the numerical derivatives overwrite the ones stored in the layers
+    let gradients: Vec<Vec<Vec<N>>> = nn.get_layers()
+        .iter()
+        .map(|layer| layer.gradients().iter().map(|g| g.to_vec()).collect())
+        .collect();
+
+    let mut result = GradCheckResult::new();
+    for (l, layer_gradients) in gradients.iter().enumerate() {
+        let kind = nn.get_layers()[l].kind();
+        for (p, nablas) in layer_gradients.iter().enumerate() {
+            if is_normalized_bias(nn, l, p) {
+                continue;
+            }
+            for (i, &nabla) in nablas.iter().enumerate() {
+                let numerical = numerical_derivative(nn,
+                                                     inputs,
+                                                     desired_outputs,
+                                                     cost,
+                                                     epsilon,
+                                                     |nn| {
+                    &mut nn.get_layers_mut()[l].parameters_mut().swap_remove(p)[i]
+                });
+                result.record(nabla.to_f64(), numerical.to_f64(), || {
+                    format!("element {} of parameter {} of layer {} ({})", i, p, l + 1, kind)
+                });
+            }
+        }
+    }
+    result
+}
+
+/// Run `check_gradients` on a new random network with the given topology for a batch of
+/// `n_samples` random inputs and one-hot desired outputs.
+///
+/// If `batch_norm` is set, all hidden layers of the network use batch normalization, `output` is
+/// the activation of the output layer.
+pub fn check_random_network<N: Float>(topology: &Topology,
+                                      n_samples: usize,
+                                      batch_norm: bool,
+                                      output: Activation,
+                                      cost: Cost,
+                                      epsilon: N)
+                                      -> Result<GradCheckResult, &'static str> {
+    let keep_probs = vec![N::one(); topology.layers.len()];
+    let mut nn = Network::<N>::from_topology(topology, &keep_probs, batch_norm, output)?;
+    let (inputs, desired_outputs) = random_batch(&nn, n_samples, &mut rand::thread_rng());
+    Ok(check_gradients(&mut nn, &inputs, &desired_outputs, cost, epsilon))
+}
+
+// A batch of `n_samples` random inputs of `nn` and one-hot desired outputs
+fn random_batch<N: Float, R: Rng>(nn: &Network<N>,
+                                  n_samples: usize,
+                                  rng: &mut R)
+                                  -> (DMatrix<N>, DMatrix<N>) {
+    let n_outputs = nn.output_size();
+    let inputs = DMatrix::from_fn(nn.input_size(), n_samples, |_, _| {
+        let StandardNormal(x) = rng.gen();
+        N::from_f64(x)
+    });
+    let mut desired_outputs = DMatrix::new_zeros(n_outputs, n_samples);
+    for k in 0..n_samples {
+        desired_outputs[(rng.gen_range(0, n_outputs), k)] = N::one();
+    }
+    (inputs, desired_outputs)
+}
+
+// Whether parameter `p` of layer `l` is the bias of a layer that feeds into batch normalization.
+// Batch normalization subtracts the mean of each neuron over the batch, so such a bias has no
+// effect on the cost: its analytic gradient is zero and the numerical one only rounding noise.
+fn is_normalized_bias<N: Float>(nn: &Network<N>, l: usize, p: usize) -> bool {
+    let layers = nn.get_layers();
+    let weighted = ["dense", "convolution"].contains(&layers[l].kind());
+    weighted && p == 1 && layers.get(l + 1).map_or(false, |next| next.kind() == "batch norm")
+}
+
+// Central difference quotient of the cost with respect to the parameter `param` points to
+fn numerical_derivative<N, F>(nn: &mut Network<N>,
+                              inputs: &DMatrix<N>,
+                              desired_outputs: &DMatrix<N>,
+                              cost: Cost,
+                              epsilon: N,
+                              mut param: F)
+                              -> N
+    where N: Float,
+          F: FnMut(&mut Network<N>) -> &mut N
+{
+    let original = *param(nn);
+
+    *param(nn) = original + epsilon;
+    let cost_plus = learning::batch_cost(nn, inputs, desired_outputs, cost, None);
+    *param(nn) = original - epsilon;
+    let cost_minus = learning::batch_cost(nn, inputs, desired_outputs, cost, None);
+    *param(nn) = original;
+
+    (cost_plus - cost_minus) / (epsilon + epsilon)
+}
+
+// Relative error of two derivatives. Very small derivatives are compared absolutely, because
+// the rounding error of the finite differences would dominate their relative error.
+fn relative_error(analytic: f64, numerical: f64) -> f64 {
+    (analytic - numerical).abs() / (analytic.abs() + numerical.abs()).max(1e-4)
+}
+
+
+// Check the gradients of a network and a batch drawn from `seed`, so failures can be reproduced
+#[cfg(test)]
+fn check_seeded(topology: &Topology,
+                batch_norm: bool,
+                output: Activation,
+                cost: Cost,
+                seed: usize)
+                -> GradCheckResult {
+    use nn::NetworkBuilder;
+    use rand::{SeedableRng, StdRng};
+
+    let mut nn = NetworkBuilder::<f64>::new(topology.clone())
+        .batch_norm(batch_norm)
+        .output(output)
+        .seed(seed)
+        .build()
+        .unwrap();
+    let mut rng: StdRng = SeedableRng::from_seed(&[seed][..]);
+    let (inputs, desired_outputs) = random_batch(&nn, 5, &mut rng);
+    check_gradients(&mut nn, &inputs, &desired_outputs, cost, 1e-5)
+}
+
+#[test]
+fn test_backprop_gradients() {
+    let topologies = ["2 3 2",
+                      "4 5 3",
+                      "3 6 4 2",
+                      "5x5x2 conv:3:3:1:1 maxpool:2 3",
+                      "6x6x1 conv:2:3:2 avgpool:2:1 conv:2:1 2",
+                      "4x2x1 rnn:3 2",
+                      "4x2x1 lstm:3:seq 2",
+                      "4x2x1 gru:3:seq gru:2 2"];
+    for topology in &topologies {
+        let topology = Topology::parse(topology.split(' ')).unwrap();
+        for batch_norm in &[false, true] {
+            let result =
+                check_seeded(&topology, *batch_norm, Activation::Sigmoid, Cost::Quadratic, 1);
+            assert!(result.passed(1e-6),
+                    "gradient check failed for {} (batch norm: {}): error {} at {}",
+                    topology,
+                    batch_norm,
+                    result.max_error,
+                    result.worst_parameter);
+        }
+    }
+
+    // the biases of the first layer are normalized away, but the batch norm parameters are checked
+    let topology = Topology::dense(&[2, 3, 2]);
+    let result = check_seeded(&topology, true, Activation::Sigmoid, Cost::Quadratic, 1);
+    assert_eq!(result.n_checked, 6 + 2 * 3 + 6 + 2);
+}
+
+#[test]
+fn test_cost_gradients() {
+    let topology = Topology::dense(&[3, 5, 2]);
+    let costs = [(Activation::Linear, Cost::Quadratic),
+                 (Activation::Linear, Cost::Absolute),
+                 (Activation::Linear, Cost::Huber(0.5)),
+                 (Activation::Sigmoid, Cost::CrossEntropy)];
+    for &(output, cost) in &costs {
+        let result = check_seeded(&topology, false, output, cost, 1);
+        assert!(result.passed(1e-6),
+                "gradient check failed for {} cost: error {} at {}",
+                cost,
+                result.max_error,
+                result.worst_parameter);
+    }
+}
+
+#[test]
+fn test_gradcheck_detects_wrong_gradients() {
+    let mut nn = Network::<f64>::new(&[2, 3, 2]).unwrap();
+    let inputs = DMatrix::from_column_vector(2, 1, &[0.5, -0.3]);
+    let desired_outputs = DMatrix::from_column_vector(2, 1, &[1.0, 0.0]);
+    learning::backprop(&mut nn, &inputs, &desired_outputs, Cost::Quadratic, None);
+    let analytic = nn.get_layers()[0].gradients()[0][0];
+    // a wrong analytic gradient must not pass the check
+    let mut result = GradCheckResult::new();
+    let numerical = numerical_derivative(&mut nn,
+                                         &inputs,
+                                         &desired_outputs,
+                                         Cost::Quadratic,
+                                         1e-5,
+                                         |nn| {
+        &mut nn.get_layers_mut()[0].parameters_mut().swap_remove(0)[0]
+    });
+    result.record(analytic * 1.1 + 1e-3, numerical, || String::new());
+    assert!(!result.passed(1e-6));
+}
@@ -0,0 +1,116 @@
+extern crate serde_json;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The progress of the network in a single training epoch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochRecord {
+    /// number of the epoch, starting at 1
+    pub epoch: u32,
+    /// mean cost per training sample, calculated like during training, i.e. with dropout and
+    /// class weights
+    pub training_cost: f64,
+    /// mean cost per test sample, `None` without test data
+    pub validation_cost: Option<f64>,
+    /// fraction of correctly classified test samples (all labels correct for multi-label data),
+    /// `None` without test data and for regression
+    pub accuracy: Option<f64>,
+    /// fraction of correctly classified training samples, evaluated without dropout after the
+    /// epoch, `None` for regression and in histories of older versions
+    #[serde(default)]
+    pub training_accuracy: Option<f64>,
+    /// learning rate used in the epoch
+    pub learning_rate: f64,
+    /// mean global L2 norm of the gradients of the mini batches before they were clipped, 0 in
+    /// histories of older versions
+    #[serde(default)]
+    pub gradient_norm: f64,
+    /// largest global L2 norm of the gradients of a mini batch before they were clipped, spikes
+    /// show where the training was close to diverging
+    #[serde(default)]
+    pub max_gradient_norm: f64,
+    /// wall time of the epoch in seconds, including the evaluation
+    pub seconds: f64,
+}
+
+/// The record of every epoch of a training run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainingHistory {
+    /// the epochs in order
+    pub epochs: Vec<EpochRecord>,
+}
+
+impl TrainingHistory {
+    /// Create an empty history
+    pub fn new() -> Self {
+        TrainingHistory::default()
+    }
+
+    /// Append the record of the next epoch
+    pub fn push(&mut self, record: EpochRecord) {
+        self.epochs.push(record);
+    }
+
+    /// Write the history as CSV with a header line, missing values are left empty
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer,
+                 "epoch,training_cost,validation_cost,accuracy,training_accuracy,learning_rate,\
+                  gradient_norm,max_gradient_norm,seconds")?;
+        let optional = |x: Option<f64>| x.map_or(String::new(), |x| x.to_string());
+        for record in &self.epochs {
+            writeln!(writer,
+                     "{},{},{},{},{},{},{},{},{}",
+                     record.epoch,
+                     record.training_cost,
+                     optional(record.validation_cost),
+                     optional(record.accuracy),
+                     optional(record.training_accuracy),
+                     record.learning_rate,
+                     record.gradient_norm,
+                     record.max_gradient_norm,
+                     record.seconds)?;
+        }
+        Ok(())
+    }
+
+    /// Write the history to the file at `path`, as JSON if it ends in `.json` and as CSV
+    /// otherwise
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        if path.extension().map_or(false, |e| e == "json") {
+            serde_json::to_writer_pretty(&mut writer, self)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        } else {
+            self.write_csv(writer)
+        }
+    }
+}
+
+
+#[test]
+fn test_history_csv() {
+    let mut history = TrainingHistory::new();
+    history.push(EpochRecord {
+        epoch: 1,
+        training_cost: 0.5,
+        validation_cost: None,
+        accuracy: Some(0.75),
+        training_accuracy: Some(1.0),
+        learning_rate: 0.1,
+        gradient_norm: 1.5,
+        max_gradient_norm: 3.0,
+        seconds: 2.0,
+    });
+    let mut csv = Vec::new();
+    history.write_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(),
+               "epoch,training_cost,validation_cost,accuracy,training_accuracy,learning_rate,\
+                gradient_norm,max_gradient_norm,seconds\n\
+                1,0.5,,0.75,1,0.1,1.5,3,2\n");
+
+    let json = serde_json::to_string(&history).unwrap();
+    assert_eq!(serde_json::from_str::<TrainingHistory>(&json).unwrap(), history);
+}
@@ -0,0 +1,153 @@
+use na::DMatrix;
+use nn::Float;
+use nn::layers::{Layer, hadamard, map_matrix};
+use std::io;
+use std::str::FromStr;
+use structs::serialnet::SerializableLayer;
+
+/// Activation functions that can be applied elementwise by an `ActivationLayer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Activation {
+    /// The sigmoid function `1 / (1 + exp(-z))`
+    Sigmoid,
+    /// The identity, e.g. for the outputs of a regression
+    Linear,
+}
+
+impl Activation {
+    /// Apply the activation function to a single value
+    pub fn apply<N: Float>(&self, z: N) -> N {
+        match *self {
+            Activation::Sigmoid => sigmoid(z),
+            Activation::Linear => z,
+        }
+    }
+
+    /// The derivative of the activation function at `z`
+    pub fn derivative<N: Float>(&self, z: N) -> N {
+        self.derivative_from_output(self.apply(z))
+    }
+
+    /// The derivative of the activation function at the input that was mapped to `a`
+    ///
+    /// Both activations have derivatives that only depend on their output, so backpropagation
+    /// can reuse the outputs of the forward pass instead of evaluating the function again.
+    pub fn derivative_from_output<N: Float>(&self, a: N) -> N {
+        match *self {
+            // Derivative of sigmoid function, ask wolfram alpha if you don't believe me
+            Activation::Sigmoid => a * (N::one() - a),
+            Activation::Linear => N::one(),
+        }
+    }
+}
+
+/// The sigmoid function `1 / (1 + exp(-z))`
+///
+/// `exp` is only evaluated for non-positive arguments, so it cannot overflow: for negative `z`
+/// the equivalent `exp(z) / (1 + exp(z))` is used. Tiny outputs of large negative inputs thereby
+/// keep their precision instead of being rounded to 0 via `1 / inf`.
+pub fn sigmoid<N: Float>(z: N) -> N {
+    if z >= N::zero() {
+        N::one() / (N::one() + (-z).exp())
+    } else {
+        let e = z.exp();
+        e / (N::one() + e)
+    }
+}
+
+impl FromStr for Activation {
+    type Err = io::Error;
+
+    /// Parse an activation function: `sigmoid` or `linear`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sigmoid" => Ok(Activation::Sigmoid),
+            "linear" => Ok(Activation::Linear),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown activation function.")),
+        }
+    }
+}
+
+/// A layer that applies an activation function to each of its inputs
+#[derive(Debug, Clone)]
+pub struct ActivationLayer<N: Float> {
+    /// the applied function
+    function: Activation,
+    /// output of the last training forward pass, the derivatives are calculated from it
+    output: Option<DMatrix<N>>,
+}
+
+impl<N: Float> ActivationLayer<N> {
+    /// Create a layer applying `function`
+    pub fn new(function: Activation) -> ActivationLayer<N> {
+        ActivationLayer {
+            function: function,
+            output: None,
+        }
+    }
+}
+
+impl<N: Float> Layer<N> for ActivationLayer<N> {
+    fn kind(&self) -> &'static str {
+        "activation"
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        Ok(input_size)
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        for (o, z) in output.as_mut_vector().iter_mut().zip(input.as_vector().iter()) {
+            *o = self.function.apply(*z);
+        }
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let output = map_matrix(input, |z| self.function.apply(z));
+        self.output = Some(output.clone());
+        output
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        let output = self.output.as_ref().expect("backward called without forward_train");
+        let mut grad_input = map_matrix(output, |a| self.function.derivative_from_output(a));
+        hadamard(&mut grad_input, grad_output);
+        grad_input
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::Activation { function: self.function }
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
+
+
+#[test]
+fn test_sigmoid_extreme_inputs() {
+    // no overflow, and tiny outputs are not rounded to 0
+    assert_eq!(sigmoid(1000.0f32), 1.0);
+    assert_eq!(sigmoid(-1000.0f64), 0.0);
+    assert!(sigmoid(-100.0f32) > 0.0 && sigmoid(-700.0f64) > 0.0);
+    assert!((sigmoid(-100.0f64) / (-100.0f64).exp() - 1.0).abs() < 1e-12);
+    assert_eq!(sigmoid(0.0f32), 0.5);
+    assert!(sigmoid(::std::f64::NAN).is_nan());
+    for &z in &[-1e6f64, -40.0, -3.0, 0.5, 40.0, 1e6] {
+        assert!((sigmoid(z) + sigmoid(-z) - 1.0).abs() < 1e-15);
+        let derivative = Activation::Sigmoid.derivative(z);
+        assert!(derivative.is_finite() && derivative >= 0.0 && derivative <= 0.25);
+    }
+
+    // backpropagation with the cached outputs matches the derivative at the inputs
+    let mut layer = ActivationLayer::new(Activation::Sigmoid);
+    let input =
+        DMatrix::from_column_vector(4, 2, &[-1e4f64, -20.0, 0.3, 50.0, 1e4, 2.0, -0.7, 0.0]);
+    layer.forward_train(&input);
+    let grad_input = layer.backward(&DMatrix::from_element(4, 2, 1.0));
+    for (g, z) in grad_input.as_vector().iter().zip(input.as_vector().iter()) {
+        assert!(g.is_finite());
+        assert!((g - Activation::Sigmoid.derivative(*z)).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,287 @@
+use na::{DMatrix, DVector};
+use nn::Float;
+use nn::layers::Layer;
+use structs::serialnet::SerializableLayer;
+
+/// Batch normalization of the weighted inputs of a layer
+///
+/// During training the weighted input `z` of each neuron is normalized with the mean and variance
+/// over the mini batch, then scaled by `gamma` and shifted by `beta`, which are learned like
+/// weights and biases. Exponential running averages of the batch statistics are kept and used to
+/// normalize inputs during inference.
+///
+/// [Source](https://arxiv.org/abs/1502.03167)
+#[derive(Debug, Clone)]
+pub struct BatchNorm<N: Float> {
+    /// learnable scale of each neuron
+    gamma: DVector<N>,
+    /// learnable shift of each neuron
+    beta: DVector<N>,
+    /// running average of the batch means, used for inference
+    running_mean: DVector<N>,
+    /// running average of the batch variances, used for inference
+    running_var: DVector<N>,
+    /// weight of the old value when the running averages are updated
+    momentum: N,
+    /// small constant added to the variance to avoid divisions by zero
+    epsilon: N,
+    /// values of the last training forward pass
+    cache: Option<BatchNormCache<N>>,
+    /// gradient of the scales from the last backward pass
+    nabla_gamma: DVector<N>,
+    /// gradient of the shifts from the last backward pass
+    nabla_beta: DVector<N>,
+}
+
+// Values of a training forward pass that are needed to calculate the gradients afterwards
+#[derive(Debug, Clone)]
+struct BatchNormCache<N: Float> {
+    /// the normalized inputs, one column per sample
+    x_hat: DMatrix<N>,
+    /// `1 / sqrt(var + epsilon)` of each neuron
+    inv_std: DVector<N>,
+}
+
+impl<N: Float> BatchNorm<N> {
+    /// Create a batch normalization for a layer with `size` neurons
+    ///
+    /// It starts as the identity: scale 1, shift 0, running mean 0 and running variance 1.
+    pub fn new(size: usize) -> BatchNorm<N> {
+        BatchNorm::from_parts(DVector::from_element(size, N::one()),
+                              DVector::from_element(size, N::zero()),
+                              DVector::from_element(size, N::zero()),
+                              DVector::from_element(size, N::one()),
+                              N::from_f64(0.9),
+                              N::from_f64(1e-5))
+    }
+
+    /// Create a batch normalization from its stored parameters and statistics
+    pub fn from_parts(gamma: DVector<N>,
+                      beta: DVector<N>,
+                      running_mean: DVector<N>,
+                      running_var: DVector<N>,
+                      momentum: N,
+                      epsilon: N)
+                      -> BatchNorm<N> {
+        let size = gamma.len();
+        BatchNorm {
+            gamma: gamma,
+            beta: beta,
+            running_mean: running_mean,
+            running_var: running_var,
+            momentum: momentum,
+            epsilon: epsilon,
+            cache: None,
+            nabla_gamma: DVector::new_zeros(size),
+            nabla_beta: DVector::new_zeros(size),
+        }
+    }
+
+    // Normalize each row of `z` (one column per sample) with the statistics of the batch.
+    //
+    // The running statistics are updated. Returns the normalized, scaled and shifted values and
+    // the cache `backward_batch` needs.
+    fn forward_batch(&mut self, z: &DMatrix<N>) -> (DMatrix<N>, BatchNormCache<N>) {
+        let (size, m) = (z.nrows(), z.ncols());
+        let n_samples = N::from_f64(m as f64);
+
+        let mut x_hat = z.clone();
+        let mut inv_std = DVector::from_element(size, N::zero());
+        for i in 0..size {
+            let mut mean = N::zero();
+            for k in 0..m {
+                mean += z[(i, k)];
+            }
+            mean /= n_samples;
+            let mut var = N::zero();
+            for k in 0..m {
+                var += (z[(i, k)] - mean) * (z[(i, k)] - mean);
+            }
+            var /= n_samples;
+
+            inv_std[i] = N::one() / (var + self.epsilon).sqrt();
+            for k in 0..m {
+                x_hat[(i, k)] = (z[(i, k)] - mean) * inv_std[i];
+            }
+
+            self.running_mean[i] = self.momentum * self.running_mean[i] +
+                                   (N::one() - self.momentum) * mean;
+            self.running_var[i] = self.momentum * self.running_var[i] +
+                                  (N::one() - self.momentum) * var;
+        }
+
+        let mut y = x_hat.clone();
+        for i in 0..size {
+            for k in 0..m {
+                y[(i, k)] = self.gamma[i] * x_hat[(i, k)] + self.beta[i];
+            }
+        }
+        (y,
+         BatchNormCache {
+             x_hat: x_hat,
+             inv_std: inv_std,
+         })
+    }
+
+    // Backpropagate the gradient `grad_y` of the cost with respect to the outputs of
+    // `forward_batch` through the normalization.
+    //
+    // Returns the gradients with respect to the inputs `z`, `gamma` and `beta`.
+    fn backward_batch(&self,
+                      cache: &BatchNormCache<N>,
+                      grad_y: &DMatrix<N>)
+                      -> (DMatrix<N>, DVector<N>, DVector<N>) {
+        let (size, m) = (grad_y.nrows(), grad_y.ncols());
+        let n_samples = N::from_f64(m as f64);
+
+        let mut grad_z = grad_y.clone();
+        let mut grad_gamma = DVector::from_element(size, N::zero());
+        let mut grad_beta = DVector::from_element(size, N::zero());
+        for i in 0..size {
+            // sums over the batch of the gradient with respect to x_hat, and of it times x_hat
+            let mut sum_dx_hat = N::zero();
+            let mut sum_dx_hat_x_hat = N::zero();
+            for k in 0..m {
+                let dx_hat = grad_y[(i, k)] * self.gamma[i];
+                sum_dx_hat += dx_hat;
+                sum_dx_hat_x_hat += dx_hat * cache.x_hat[(i, k)];
+                grad_gamma[i] += grad_y[(i, k)] * cache.x_hat[(i, k)];
+                grad_beta[i] += grad_y[(i, k)];
+            }
+            for k in 0..m {
+                let dx_hat = grad_y[(i, k)] * self.gamma[i];
+                grad_z[(i, k)] = cache.inv_std[i] / n_samples *
+                                 (n_samples * dx_hat - sum_dx_hat -
+                                  cache.x_hat[(i, k)] * sum_dx_hat_x_hat);
+            }
+        }
+        (grad_z, grad_gamma, grad_beta)
+    }
+
+    /// return the scale of each neuron
+    pub fn get_gamma(&self) -> &DVector<N> {
+        &self.gamma
+    }
+
+    /// return the shift of each neuron
+    pub fn get_beta(&self) -> &DVector<N> {
+        &self.beta
+    }
+
+    /// return the running average of the batch means
+    pub fn get_running_mean(&self) -> &DVector<N> {
+        &self.running_mean
+    }
+
+    /// return the running average of the batch variances
+    pub fn get_running_var(&self) -> &DVector<N> {
+        &self.running_var
+    }
+
+    /// return the momentum of the running averages
+    pub fn get_momentum(&self) -> N {
+        self.momentum
+    }
+
+    /// return the constant that is added to the variance
+    pub fn get_epsilon(&self) -> N {
+        self.epsilon
+    }
+}
+
+impl<N: Float> Layer<N> for BatchNorm<N> {
+    fn kind(&self) -> &'static str {
+        "batch norm"
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        if input_size != self.gamma.len() {
+            return Err("input size does not match the size of a batch normalization");
+        }
+        Ok(input_size)
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        let size = self.gamma.len();
+        let x = input.as_vector();
+        // normalize every sample with the running statistics
+        for (x_k, out_k) in x.chunks(size).zip(output.as_mut_vector().chunks_mut(size)) {
+            for (i, (o, z)) in out_k.iter_mut().zip(x_k.iter()).enumerate() {
+                let x_hat = (*z - self.running_mean[i]) /
+                            (self.running_var[i] + self.epsilon).sqrt();
+                *o = self.gamma[i] * x_hat + self.beta[i];
+            }
+        }
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let (y, cache) = self.forward_batch(input);
+        self.cache = Some(cache);
+        y
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        let (grad_z, nabla_gamma, nabla_beta) = {
+            let cache = self.cache.as_ref().expect("backward called without forward_train");
+            self.backward_batch(cache, grad_output)
+        };
+        self.nabla_gamma = nabla_gamma;
+        self.nabla_beta = nabla_beta;
+        grad_z
+    }
+
+    fn parameters(&self) -> Vec<&[N]> {
+        vec![&self.gamma.at, &self.beta.at]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [N]> {
+        vec![&mut self.gamma.at, &mut self.beta.at]
+    }
+
+    fn gradients(&self) -> Vec<&[N]> {
+        vec![&self.nabla_gamma.at, &self.nabla_beta.at]
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::BatchNorm(self.into())
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
+
+
+#[test]
+fn test_batch_norm_statistics() {
+    // two neurons, the first has batch mean 2.5 and variance 1.25, the second mean 0, variance 4
+    let input = DMatrix::from_row_vector(2, 4, &[1.0f64, 2.0, 3.0, 4.0, -2.0, 2.0, -2.0, 2.0]);
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+    // training normalizes with the batch statistics and moves the running averages towards them
+    let mut bn = BatchNorm::<f64>::new(2);
+    let output = bn.forward_train(&input);
+    assert!(close(output[(0, 0)], -1.5 / (1.25f64 + 1e-5).sqrt()));
+    assert!(close(output[(1, 1)], 2.0 / (4.0f64 + 1e-5).sqrt()));
+    let expected_mean = [0.9 * 0.0 + 0.1 * 2.5, 0.9 * 0.0 + 0.1 * 0.0];
+    let expected_var = [0.9 * 1.0 + 0.1 * 1.25, 0.9 * 1.0 + 0.1 * 4.0];
+    for i in 0..2 {
+        assert!(close(bn.get_running_mean()[i], expected_mean[i]));
+        assert!(close(bn.get_running_var()[i], expected_var[i]));
+    }
+
+    // inference normalizes with the running statistics instead, whatever the batch
+    let bn = BatchNorm::from_parts(DVector::from_slice(2, &[2.0, 0.5]),
+                                   DVector::from_slice(2, &[1.0, -1.0]),
+                                   DVector::from_slice(2, &[1.0, 3.0]),
+                                   DVector::from_slice(2, &[4.0, 0.25]),
+                                   0.9,
+                                   0.0);
+    let mut output = DMatrix::new_zeros(2, 4);
+    bn.forward_into(&input, &mut output);
+    for k in 0..4 {
+        assert!(close(output[(0, k)], 2.0 * (input[(0, k)] - 1.0) / 2.0 + 1.0));
+        assert!(close(output[(1, k)], 0.5 * (input[(1, k)] - 3.0) / 0.5 - 1.0));
+    }
+    assert_eq!(bn.get_running_mean().at, vec![1.0, 3.0]);
+}
@@ -0,0 +1,274 @@
+use na::{DMatrix, DVector};
+use nn::Float;
+use nn::layers::{Initializer, Layer};
+use nn::topology::Shape;
+use rand::{self, Rng};
+use structs::serialnet::SerializableLayer;
+
+/// A 2D convolution of an image with a number of learned kernels
+///
+/// Every output channel is the sum of the correlations of all input channels with a
+/// `kernel`x`kernel` filter plus a bias. The kernel is moved over the input in steps of `stride`,
+/// the input is padded with `padding` zeros at each border. Like `Dense`, the layer is linear.
+#[derive(Debug, Clone)]
+pub struct Conv2D<N: Float> {
+    /// shape of the input images
+    input_shape: Shape,
+    /// shape of the output images
+    output_shape: Shape,
+    /// height and width of the kernels
+    kernel: usize,
+    /// step between two applications of the kernels
+    stride: usize,
+    /// number of zeros added at each border of the input
+    padding: usize,
+    /// the kernels, one row per output channel and one column per input channel and kernel
+    /// position (channel by channel, row by row)
+    weights: DMatrix<N>,
+    /// bias of each output channel
+    biases: DVector<N>,
+    /// every multiplication of the convolution as (output position, column of the weights,
+    /// input index), positions in the zero padding are left out
+    taps: Vec<(usize, usize, usize)>,
+    /// input of the last training forward pass
+    input: Option<DMatrix<N>>,
+    /// gradient of the weights from the last backward pass
+    nabla_w: DMatrix<N>,
+    /// gradient of the biases from the last backward pass
+    nabla_b: DVector<N>,
+}
+
+impl<N: Float> Conv2D<N> {
+    /// Create a convolution of images of `input_shape` with `channels` output channels
+    ///
+    /// The weights are initialised from a normal distribution with standard deviation
+    /// `1 / sqrt(n)` where `n` is the number of inputs of each output, so the sigmoid neurons
+    /// after the convolution do not get saturated. The biases are initialised like the ones of
+    /// `Dense`. Returns an error if the kernel does not fit into the padded input.
+    pub fn new(input_shape: Shape,
+               channels: usize,
+               kernel: usize,
+               stride: usize,
+               padding: usize)
+               -> Result<Conv2D<N>, &'static str> {
+        let weights = DMatrix::new_zeros(channels, input_shape.channels * kernel * kernel);
+        let biases = DVector::new_zeros(channels);
+        let mut conv = Conv2D::from_parts(input_shape, kernel, stride, padding, weights, biases)?;
+        conv.initialize(Initializer::Default, &mut rand::thread_rng());
+        Ok(conv)
+    }
+
+    /// Create a convolution from given kernels and biases
+    ///
+    /// `weights` needs one row per output channel and `input_shape.channels * kernel * kernel`
+    /// columns.
+    pub fn from_parts(input_shape: Shape,
+                      kernel: usize,
+                      stride: usize,
+                      padding: usize,
+                      weights: DMatrix<N>,
+                      biases: DVector<N>)
+                      -> Result<Conv2D<N>, &'static str> {
+        if kernel == 0 || stride == 0 {
+            return Err("kernel size and stride of a convolution must be positive");
+        }
+        if kernel > input_shape.height + 2 * padding || kernel > input_shape.width + 2 * padding {
+            return Err("the kernel of a convolution is larger than its padded input");
+        }
+        if weights.ncols() != input_shape.channels * kernel * kernel ||
+           weights.nrows() != biases.len() {
+            return Err("the weights of a convolution do not match its input and kernel size");
+        }
+        let output_shape = Shape::new((input_shape.height + 2 * padding - kernel) / stride + 1,
+                                      (input_shape.width + 2 * padding - kernel) / stride + 1,
+                                      weights.nrows());
+
+        // the positions of the kernel never change, so the index arithmetic is done only once
+        let mut taps = Vec::new();
+        for oy in 0..output_shape.height {
+            for ox in 0..output_shape.width {
+                for c in 0..input_shape.channels {
+                    for ky in 0..kernel {
+                        for kx in 0..kernel {
+                            // position in the unpadded input, skip the zeros of the padding
+                            let iy = oy * stride + ky;
+                            let ix = ox * stride + kx;
+                            if iy < padding || ix < padding || iy - padding >= input_shape.height ||
+                               ix - padding >= input_shape.width {
+                                continue;
+                            }
+                            taps.push((oy * output_shape.width + ox,
+                                       (c * kernel + ky) * kernel + kx,
+                                       (c * input_shape.height + iy - padding) *
+                                       input_shape.width + ix - padding));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Conv2D {
+            input_shape: input_shape,
+            output_shape: output_shape,
+            kernel: kernel,
+            stride: stride,
+            padding: padding,
+            nabla_w: DMatrix::new_zeros(weights.nrows(), weights.ncols()),
+            nabla_b: DVector::new_zeros(biases.len()),
+            weights: weights,
+            biases: biases,
+            taps: taps,
+            input: None,
+        })
+    }
+
+    /// return the shape of the output images
+    pub fn get_output_shape(&self) -> Shape {
+        self.output_shape
+    }
+
+    // Convolve a single sample `x` into `out`
+    fn convolve(&self, x: &[N], out: &mut [N]) {
+        let n_channels = self.weights.nrows();
+        let area = self.output_shape.height * self.output_shape.width;
+        for (out_c, b) in out.chunks_mut(area).zip(self.biases.at.iter()) {
+            for o in out_c.iter_mut() {
+                *o = *b;
+            }
+        }
+        // the weights are stored column-major, so all output channels of a tap are contiguous
+        let w = self.weights.as_vector();
+        for &(p, j, i) in &self.taps {
+            for (c, w_cj) in w[j * n_channels..(j + 1) * n_channels].iter().enumerate() {
+                out[c * area + p] += *w_cj * x[i];
+            }
+        }
+    }
+}
+
+impl<N: Float> Layer<N> for Conv2D<N> {
+    fn kind(&self) -> &'static str {
+        "convolution"
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        if input_size != self.input_shape.len() {
+            return Err("input size does not match the input shape of a convolution");
+        }
+        Ok(self.output_shape.len())
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        let x = input.as_vector().chunks(self.input_shape.len());
+        let out = output.as_mut_vector().chunks_mut(self.output_shape.len());
+        for (x_k, out_k) in x.zip(out) {
+            self.convolve(x_k, out_k);
+        }
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let mut output = DMatrix::new_zeros(self.output_shape.len(), input.ncols());
+        self.forward_into(input, &mut output);
+        self.input = Some(input.clone());
+        output
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        let input = self.input.as_ref().expect("backward called without forward_train");
+        let n_channels = self.weights.nrows();
+        let area = self.output_shape.height * self.output_shape.width;
+
+        let mut nabla_w = DMatrix::new_zeros(self.weights.nrows(), self.weights.ncols());
+        let mut nabla_b = DVector::new_zeros(n_channels);
+        let mut grad_input = DMatrix::new_zeros(input.nrows(), input.ncols());
+        {
+            let w = self.weights.as_vector();
+            let nw = nabla_w.as_mut_vector();
+            let samples = input.as_vector()
+                .chunks(self.input_shape.len())
+                .zip(grad_output.as_vector().chunks(self.output_shape.len()))
+                .zip(grad_input.as_mut_vector().chunks_mut(self.input_shape.len()));
+            for ((x, g), g_in) in samples {
+                for (c, g_c) in g.chunks(area).enumerate() {
+                    for g_cp in g_c {
+                        nabla_b[c] += *g_cp;
+                    }
+                }
+                for &(p, j, i) in &self.taps {
+                    for c in 0..n_channels {
+                        let g_cp = g[c * area + p];
+                        nw[j * n_channels + c] += g_cp * x[i];
+                        g_in[i] += w[j * n_channels + c] * g_cp;
+                    }
+                }
+            }
+        }
+        self.nabla_w = nabla_w;
+        self.nabla_b = nabla_b;
+        grad_input
+    }
+
+    fn parameters(&self) -> Vec<&[N]> {
+        vec![self.weights.as_vector(), &self.biases.at]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [N]> {
+        vec![self.weights.as_mut_vector(), &mut self.biases.at]
+    }
+
+    fn gradients(&self) -> Vec<&[N]> {
+        vec![self.nabla_w.as_vector(), &self.nabla_b.at]
+    }
+
+    fn initialize(&mut self, initializer: Initializer, rng: &mut Rng) {
+        // by default the weights are scaled, but the biases are drawn like the ones of `Dense`
+        let weight_init = match initializer {
+            Initializer::Default => Initializer::Scaled,
+            initializer => initializer,
+        };
+        let n_inputs = self.weights.ncols();
+        let n_outputs = self.weights.nrows() * self.kernel * self.kernel;
+        for w in self.weights.as_mut_vector() {
+            *w = weight_init.weight(n_inputs, n_outputs, rng);
+        }
+        for b in &mut self.biases.at {
+            *b = initializer.bias(rng);
+        }
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::Conv2D {
+            input_shape: self.input_shape,
+            kernel: self.kernel,
+            stride: self.stride,
+            padding: self.padding,
+            weights: (self.weights.nrows(),
+                      self.weights.ncols(),
+                      self.weights.as_vector().to_vec()),
+            biases: self.biases.at.clone(),
+        }
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
+
+
+#[test]
+fn test_convolution() {
+    // a single 3x3 kernel on a 4x4 image with padding 1 and stride 2
+    let kernel = [0.0, 1.0, 0.0, 1.0, 2.0, 1.0, 0.0, 1.0, 0.0];
+    let mut conv = Conv2D::from_parts(Shape::new(4, 4, 1),
+                                      3,
+                                      2,
+                                      1,
+                                      DMatrix::from_column_vector(1, 9, &kernel),
+                                      DVector::from_element(1, 0.5))
+        .unwrap();
+    assert_eq!(conv.get_output_shape(), Shape::new(2, 2, 1));
+    let pixels: Vec<f64> = (0..16).map(|x| x as f64).collect();
+    let output = conv.forward_train(&DMatrix::from_column_vector(16, 1, &pixels));
+    // e.g. the top left output sees pixel 0 with weight 2 and pixels 1 and 4 with weight 1
+    assert_eq!(output.as_vector(), &[5.5, 14.5, 41.5, 60.5]);
+}
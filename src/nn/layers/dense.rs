@@ -0,0 +1,136 @@
+use na::{DMatrix, DVector, Transpose};
+use nn::Float;
+use nn::layers::{Initializer, Layer, add_to_columns, row_sums};
+use rand::{self, Rng};
+use structs::serialnet::SerializableLayer;
+
+/// A fully connected layer, calculating `weights * input + biases`
+///
+/// The layer is linear, combine it with an `ActivationLayer` to get sigmoid neurons.
+#[derive(Debug, Clone)]
+pub struct Dense<N: Float> {
+    /// weight matrix, one row per output and one column per input
+    weights: DMatrix<N>,
+    /// bias of each output
+    biases: DVector<N>,
+    /// input of the last training forward pass
+    input: Option<DMatrix<N>>,
+    /// gradient of the weights from the last backward pass
+    nabla_w: DMatrix<N>,
+    /// gradient of the biases from the last backward pass
+    nabla_b: DVector<N>,
+}
+
+impl<N: Float> Dense<N> {
+    /// Create a layer with `n_inputs` inputs and `n_outputs` outputs
+    ///
+    /// Biases and weights will be initialised randomly from a standard normal destribution.
+    /// Choosing them so that they are around 0 and very likely between -4 and 4 will speed up
+    /// learning because the sigmoid neurons will not as easily get saturated and saturated
+    /// neurons cause a slower learning progress.
+    pub fn new(n_inputs: usize, n_outputs: usize) -> Dense<N> {
+        let mut dense = Dense::from_parts(DMatrix::new_zeros(n_outputs, n_inputs),
+                                          DVector::new_zeros(n_outputs));
+        dense.initialize(Initializer::Default, &mut rand::thread_rng());
+        dense
+    }
+
+    /// Create a layer from given weights and biases
+    pub fn from_parts(weights: DMatrix<N>, biases: DVector<N>) -> Dense<N> {
+        Dense {
+            nabla_w: DMatrix::new_zeros(weights.nrows(), weights.ncols()),
+            nabla_b: DVector::new_zeros(biases.len()),
+            weights: weights,
+            biases: biases,
+            input: None,
+        }
+    }
+
+    /// return the weight matrix
+    pub fn get_weights(&self) -> &DMatrix<N> {
+        &self.weights
+    }
+
+    /// return the biases
+    pub fn get_biases(&self) -> &DVector<N> {
+        &self.biases
+    }
+}
+
+impl<N: Float> Layer<N> for Dense<N> {
+    fn kind(&self) -> &'static str {
+        "dense"
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        if input_size != self.weights.ncols() {
+            return Err("input size does not match the weights of a dense layer");
+        }
+        Ok(self.weights.nrows())
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        let (nrows, ncols) = (self.weights.nrows(), self.weights.ncols());
+        let w = self.weights.as_vector();
+        let x = input.as_vector();
+        let out = output.as_mut_vector();
+        // all matrices are stored column-major, so every sample is a contiguous slice
+        for (x_k, out_k) in x.chunks(ncols).zip(out.chunks_mut(nrows)) {
+            out_k.copy_from_slice(&self.biases.at);
+            for (w_j, x_jk) in w.chunks(nrows).zip(x_k.iter()) {
+                for (o, w_ij) in out_k.iter_mut().zip(w_j.iter()) {
+                    *o += *w_ij * *x_jk;
+                }
+            }
+        }
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let mut z = &self.weights * input;
+        add_to_columns(&mut z, &self.biases);
+        self.input = Some(input.clone());
+        z
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        let input = self.input.as_ref().expect("backward called without forward_train");
+        self.nabla_b = row_sums(grad_output);
+        self.nabla_w = grad_output * input.transpose();
+        &self.weights.transpose() * grad_output
+    }
+
+    fn parameters(&self) -> Vec<&[N]> {
+        vec![self.weights.as_vector(), &self.biases.at]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [N]> {
+        vec![self.weights.as_mut_vector(), &mut self.biases.at]
+    }
+
+    fn gradients(&self) -> Vec<&[N]> {
+        vec![self.nabla_w.as_vector(), &self.nabla_b.at]
+    }
+
+    fn initialize(&mut self, initializer: Initializer, rng: &mut Rng) {
+        let (n_outputs, n_inputs) = (self.weights.nrows(), self.weights.ncols());
+        for w in self.weights.as_mut_vector() {
+            *w = initializer.weight(n_inputs, n_outputs, rng);
+        }
+        for b in &mut self.biases.at {
+            *b = initializer.bias(rng);
+        }
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::Dense {
+            weights: (self.weights.nrows(),
+                      self.weights.ncols(),
+                      self.weights.as_vector().to_vec()),
+            biases: self.biases.at.clone(),
+        }
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
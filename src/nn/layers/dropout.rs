@@ -0,0 +1,80 @@
+use na::DMatrix;
+use nn::Float;
+use nn::layers::{Layer, hadamard};
+use rand;
+use rand::Rng;
+use structs::serialnet::SerializableLayer;
+
+/// Inverted dropout of the layer before
+///
+/// During training each input is kept with probability `keep_prob` and switched off otherwise,
+/// the kept ones are scaled by `1 / keep_prob`. This way the layer is simply the identity during
+/// inference and the network does not need to be rescaled.
+#[derive(Debug, Clone)]
+pub struct Dropout<N: Float> {
+    /// probability with which each input is kept
+    keep_prob: N,
+    /// the mask of the last training forward pass
+    mask: Option<DMatrix<N>>,
+}
+
+impl<N: Float> Dropout<N> {
+    /// Create a dropout layer keeping each input with probability `keep_prob`
+    pub fn new(keep_prob: N) -> Dropout<N> {
+        Dropout {
+            keep_prob: keep_prob,
+            mask: None,
+        }
+    }
+}
+
+impl<N: Float> Layer<N> for Dropout<N> {
+    fn kind(&self) -> &'static str {
+        "dropout"
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        if self.keep_prob <= N::zero() || self.keep_prob > N::one() {
+            return Err("keep probabilities must be in the range (0, 1]");
+        }
+        Ok(input_size)
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        // dropout is never applied outside of training
+        output.as_mut_vector().copy_from_slice(input.as_vector());
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let mut rng = rand::thread_rng();
+        let scale = N::one() / self.keep_prob;
+        let keep_prob = self.keep_prob;
+        let mask = DMatrix::from_fn(input.nrows(), input.ncols(), |_, _| {
+            if N::from_f64(rng.gen::<f64>()) < keep_prob {
+                scale
+            } else {
+                N::zero()
+            }
+        });
+        let mut output = input.clone();
+        hadamard(&mut output, &mask);
+        self.mask = Some(mask);
+        output
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        // inputs that were switched off did not contribute to the error
+        let mask = self.mask.as_ref().expect("backward called without forward_train");
+        let mut grad_input = grad_output.clone();
+        hadamard(&mut grad_input, mask);
+        grad_input
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::Dropout { keep_prob: self.keep_prob }
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,253 @@
+pub mod activation;
+pub mod batchnorm;
+pub mod conv;
+pub mod dense;
+pub mod dropout;
+pub mod pool;
+pub mod recurrent;
+
+pub use self::activation::{Activation, ActivationLayer};
+pub use self::batchnorm::BatchNorm;
+pub use self::conv::Conv2D;
+pub use self::dense::Dense;
+pub use self::dropout::Dropout;
+pub use self::pool::Pool2D;
+pub use self::recurrent::Recurrent;
+
+use na::{DMatrix, DVector, Iterable, IterableMut};
+use nn::Float;
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+use std::fmt::Debug;
+use std::io;
+use std::str::FromStr;
+use structs::serialnet::SerializableLayer;
+
+/// A layer of a `Network`
+///
+/// A network feeds its input through a sequence of layers, each layer transforming a batch of
+/// samples into the input of the next layer. All matrices hold one sample per column.
+///
+/// To add a new kind of layer, implement this trait and add a variant for it to
+/// `SerializableLayer` and `from_serializable`. Backpropagation only uses the methods of this
+/// trait, so it does not need to be changed.
+pub trait Layer<N: Float>: Debug {
+    /// A short name of the kind of layer, e.g. "dense"
+    fn kind(&self) -> &'static str;
+
+    /// The number of outputs per sample for `input_size` inputs per sample, or an error if the
+    /// layer cannot take inputs of that size
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str>;
+
+    /// Feed a batch through the layer in inference mode, writing the result into `output`.
+    ///
+    /// `output` already has `output_size` rows and one column per sample, layers should write
+    /// into it directly instead of allocating.
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>);
+
+    /// Feed a batch through the layer in training mode.
+    ///
+    /// The layer keeps whatever it needs to calculate the gradients in the following `backward`.
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N>;
+
+    /// Backpropagate the gradient of the cost with respect to the outputs of the last
+    /// `forward_train` through the layer.
+    ///
+    /// The gradients of the parameters, summed over the batch, are stored in the layer (see
+    /// `gradients`). Returns the gradient of the cost with respect to the inputs.
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N>;
+
+    /// The learnable parameters of the layer, e.g. the weights and the biases
+    fn parameters(&self) -> Vec<&[N]> {
+        Vec::new()
+    }
+
+    /// The mutable learnable parameters of the layer, in the same order as `parameters`
+    fn parameters_mut(&mut self) -> Vec<&mut [N]> {
+        Vec::new()
+    }
+
+    /// The gradients calculated by the last `backward`, in the same order and with the same
+    /// lengths as `parameters`
+    fn gradients(&self) -> Vec<&[N]> {
+        Vec::new()
+    }
+
+    /// Draw new random weights and biases with `initializer` from `rng`
+    ///
+    /// Layers without weights ignore it.
+    fn initialize(&mut self, _initializer: Initializer, _rng: &mut Rng) {}
+
+    /// Convert the layer into its serializable form
+    fn serialize(&self) -> SerializableLayer<N>;
+
+    /// Clone the layer into a new box
+    fn box_clone(&self) -> Box<Layer<N>>;
+}
+
+/// How the weights and biases of a layer are initialised, see `Layer::initialize`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Initializer {
+    /// The initialisation a layer gets when it is created, see e.g. `Dense::new`
+    Default,
+    /// Weights and biases from a standard normal distribution
+    StandardNormal,
+    /// Weights from a normal distribution with standard deviation `1 / sqrt(n)`, where `n` is the
+    /// number of inputs of a neuron, and zero biases
+    Scaled,
+    /// Weights from a uniform distribution between `-sqrt(6 / (n + m))` and `sqrt(6 / (n + m))`,
+    /// where `n` is the number of inputs and `m` the number of outputs of a neuron (Glorot), and
+    /// zero biases
+    Xavier,
+}
+
+impl Initializer {
+    /// Draw a weight of a neuron with `n_inputs` inputs and `n_outputs` outputs, `Default` draws
+    /// like `StandardNormal`
+    pub fn weight<N: Float>(&self, n_inputs: usize, n_outputs: usize, rng: &mut Rng) -> N {
+        // `Rng` is only implemented for sized generators, like a reference to a trait object
+        let mut rng = rng;
+        let x = match *self {
+            Initializer::Default |
+            Initializer::StandardNormal => {
+                let StandardNormal(x) = (&mut rng).gen();
+                x
+            }
+            Initializer::Scaled => {
+                let StandardNormal(x) = (&mut rng).gen();
+                x / (n_inputs as f64).sqrt()
+            }
+            Initializer::Xavier => {
+                let limit = (6.0 / (n_inputs + n_outputs) as f64).sqrt();
+                (&mut rng).gen_range(-limit, limit)
+            }
+        };
+        N::from_f64(x)
+    }
+
+    /// Draw a bias, from a standard normal distribution for `Default` and `StandardNormal` and
+    /// zero otherwise
+    pub fn bias<N: Float>(&self, rng: &mut Rng) -> N {
+        let mut rng = rng;
+        match *self {
+            Initializer::Default |
+            Initializer::StandardNormal => {
+                let StandardNormal(x) = (&mut rng).gen();
+                N::from_f64(x)
+            }
+            Initializer::Scaled |
+            Initializer::Xavier => N::zero(),
+        }
+    }
+}
+
+impl FromStr for Initializer {
+    type Err = io::Error;
+
+    /// Parse an initializer: `default`, `normal`, `scaled` or `xavier`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Initializer::Default),
+            "normal" => Ok(Initializer::StandardNormal),
+            "scaled" => Ok(Initializer::Scaled),
+            "xavier" => Ok(Initializer::Xavier),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown initializer.")),
+        }
+    }
+}
+
+impl<N: Float> Clone for Box<Layer<N>> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Create a layer from its serializable form
+///
+/// Returns an error if the stored parameters do not fit together.
+pub fn from_serializable<N: Float>(layer: SerializableLayer<N>)
+                                   -> Result<Box<Layer<N>>, &'static str> {
+    Ok(match layer {
+        SerializableLayer::Dense { weights, biases } => {
+            let (nrows, ncols, weights) = weights;
+            if weights.len() != nrows * ncols || biases.len() != nrows {
+                return Err("the weights of a dense layer do not match its biases");
+            }
+            Box::new(Dense::from_parts(DMatrix::from_column_vector(nrows, ncols, &weights),
+                                       DVector { at: biases }))
+        }
+        SerializableLayer::Conv2D { input_shape, kernel, stride, padding, weights, biases } => {
+            let (nrows, ncols, weights) = weights;
+            if weights.len() != nrows * ncols {
+                return Err("the weights of a convolution do not match their size");
+            }
+            Box::new(Conv2D::from_parts(input_shape,
+                                        kernel,
+                                        stride,
+                                        padding,
+                                        DMatrix::from_column_vector(nrows, ncols, &weights),
+                                        DVector { at: biases })?)
+        }
+        SerializableLayer::Pool2D { mode, input_shape, size, stride } => {
+            Box::new(Pool2D::new(mode, input_shape, size, stride)?)
+        }
+        SerializableLayer::Recurrent { cell,
+                                       input_shape,
+                                       sequences,
+                                       bptt_length,
+                                       weights,
+                                       recurrent_weights,
+                                       biases } => {
+            let (nrows, ncols, weights) = weights;
+            let (u_nrows, u_ncols, recurrent_weights) = recurrent_weights;
+            if weights.len() != nrows * ncols || recurrent_weights.len() != u_nrows * u_ncols {
+                return Err("the weights of a recurrent layer do not match their size");
+            }
+            Box::new(Recurrent::from_parts(cell,
+                                           input_shape,
+                                           sequences,
+                                           bptt_length,
+                                           DMatrix::from_column_vector(nrows, ncols, &weights),
+                                           DMatrix::from_column_vector(u_nrows,
+                                                                       u_ncols,
+                                                                       &recurrent_weights),
+                                           DVector { at: biases })?)
+        }
+        SerializableLayer::Activation { function } => Box::new(ActivationLayer::new(function)),
+        SerializableLayer::Dropout { keep_prob } => Box::new(Dropout::new(keep_prob)),
+        SerializableLayer::BatchNorm(bn) => Box::new(BatchNorm::from(bn)),
+    })
+}
+
+// Apply `f` to every element of the matrix
+fn map_matrix<N: Float, F: Fn(N) -> N>(m: &DMatrix<N>, f: F) -> DMatrix<N> {
+    DMatrix::from_column_iter(m.nrows(), m.ncols(), m.as_vector().iter().map(|x| f(*x)))
+}
+
+// Elementwise multiplication of `a` with `b`, the result is stored in `a`
+fn hadamard<N: Float>(a: &mut DMatrix<N>, b: &DMatrix<N>) {
+    for (a_ij, b_ij) in a.as_mut_vector().iter_mut().zip(b.as_vector().iter()) {
+        *a_ij *= *b_ij;
+    }
+}
+
+// Add `v` to every column of `m`
+fn add_to_columns<N: Float>(m: &mut DMatrix<N>, v: &DVector<N>) {
+    let nrows = m.nrows();
+    for column in m.as_mut_vector().chunks_mut(nrows) {
+        for (m_ij, v_i) in column.iter_mut().zip(v.iter()) {
+            *m_ij += *v_i;
+        }
+    }
+}
+
+// Sum over the columns of `m`
+fn row_sums<N: Float>(m: &DMatrix<N>) -> DVector<N> {
+    let mut sums = DVector::new_zeros(m.nrows());
+    for column in m.as_vector().chunks(m.nrows()) {
+        for (s_i, m_ij) in sums.iter_mut().zip(column.iter()) {
+            *s_i += *m_ij;
+        }
+    }
+    sums
+}
@@ -0,0 +1,213 @@
+use na::DMatrix;
+use nn::Float;
+use nn::layers::Layer;
+use nn::topology::{PoolMode, Shape};
+use structs::serialnet::SerializableLayer;
+
+/// Max or average pooling over square windows of each channel of an image
+///
+/// The windows are `size`x`size` and moved in steps of `stride`, windows that do not completely
+/// fit into the image are left out. The layer has no parameters.
+#[derive(Debug, Clone)]
+pub struct Pool2D<N: Float> {
+    /// whether the maximum or the mean of each window is taken
+    mode: PoolMode,
+    /// shape of the input images
+    input_shape: Shape,
+    /// shape of the output images
+    output_shape: Shape,
+    /// height and width of the windows
+    size: usize,
+    /// step between two windows
+    stride: usize,
+    /// the input indices of each window, `size * size` per output
+    windows: Vec<usize>,
+    /// index of the maximum of each window in the input of the last training forward pass
+    argmax: Vec<usize>,
+    /// number of inputs of the last training forward pass
+    input_len: usize,
+    /// weight of each input in the mean of a window, `1 / (size * size)`
+    scale: N,
+}
+
+impl<N: Float> Pool2D<N> {
+    /// Create a pooling layer for images of `input_shape`
+    ///
+    /// Returns an error if the windows do not fit into the input.
+    pub fn new(mode: PoolMode,
+               input_shape: Shape,
+               size: usize,
+               stride: usize)
+               -> Result<Pool2D<N>, &'static str> {
+        if size == 0 || stride == 0 {
+            return Err("size and stride of a pooling layer must be positive");
+        }
+        if size > input_shape.height || size > input_shape.width {
+            return Err("the windows of a pooling layer are larger than its input");
+        }
+        let output_shape = Shape::new((input_shape.height - size) / stride + 1,
+                                      (input_shape.width - size) / stride + 1,
+                                      input_shape.channels);
+
+        let mut windows = Vec::with_capacity(output_shape.len() * size * size);
+        for c in 0..output_shape.channels {
+            for oy in 0..output_shape.height {
+                for ox in 0..output_shape.width {
+                    for wy in 0..size {
+                        for wx in 0..size {
+                            windows.push((c * input_shape.height + oy * stride + wy) *
+                                         input_shape.width + ox * stride + wx);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Pool2D {
+            mode: mode,
+            input_shape: input_shape,
+            output_shape: output_shape,
+            size: size,
+            stride: stride,
+            windows: windows,
+            argmax: Vec::new(),
+            input_len: 0,
+            scale: N::one() / N::from_f64((size * size) as f64),
+        })
+    }
+
+    /// return the shape of the output images
+    pub fn get_output_shape(&self) -> Shape {
+        self.output_shape
+    }
+
+    // Pool a single sample `x` into `out`, the index of the maximum of each window is passed to
+    // `argmax` for max pooling
+    fn pool<F: FnMut(usize)>(&self, x: &[N], out: &mut [N], mut argmax: F) {
+        let window_len = self.size * self.size;
+        for (o, window) in out.iter_mut().zip(self.windows.chunks(window_len)) {
+            match self.mode {
+                PoolMode::Max => {
+                    let mut max = window[0];
+                    for i in &window[1..] {
+                        if x[*i] > x[max] {
+                            max = *i;
+                        }
+                    }
+                    *o = x[max];
+                    argmax(max);
+                }
+                PoolMode::Average => {
+                    let mut sum = N::zero();
+                    for i in window {
+                        sum += x[*i];
+                    }
+                    *o = sum * self.scale;
+                }
+            }
+        }
+    }
+}
+
+impl<N: Float> Layer<N> for Pool2D<N> {
+    fn kind(&self) -> &'static str {
+        match self.mode {
+            PoolMode::Max => "max pooling",
+            PoolMode::Average => "average pooling",
+        }
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        if input_size != self.input_shape.len() {
+            return Err("input size does not match the input shape of a pooling layer");
+        }
+        Ok(self.output_shape.len())
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        let x = input.as_vector().chunks(self.input_shape.len());
+        let out = output.as_mut_vector().chunks_mut(self.output_shape.len());
+        for (x_k, out_k) in x.zip(out) {
+            self.pool(x_k, out_k, |_| {});
+        }
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let mut output = DMatrix::new_zeros(self.output_shape.len(), input.ncols());
+        let mut argmax = Vec::new();
+        {
+            let x = input.as_vector().chunks(self.input_shape.len());
+            let out = output.as_mut_vector().chunks_mut(self.output_shape.len());
+            for (k, (x_k, out_k)) in x.zip(out).enumerate() {
+                // indices are stored relative to the whole batch
+                let offset = k * self.input_shape.len();
+                self.pool(x_k, out_k, |i| argmax.push(offset + i));
+            }
+        }
+        self.argmax = argmax;
+        self.input_len = input.ncols();
+        output
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        let mut grad_input = DMatrix::new_zeros(self.input_shape.len(), self.input_len);
+        match self.mode {
+            // only the maximum of each window contributed to the output
+            PoolMode::Max => {
+                let g_in = grad_input.as_mut_vector();
+                for (i, g) in self.argmax.iter().zip(grad_output.as_vector().iter()) {
+                    g_in[*i] += *g;
+                }
+            }
+            // every input of a window contributed equally
+            PoolMode::Average => {
+                let window_len = self.size * self.size;
+                let samples = grad_output.as_vector()
+                    .chunks(self.output_shape.len())
+                    .zip(grad_input.as_mut_vector().chunks_mut(self.input_shape.len()));
+                for (g, g_in) in samples {
+                    for (g_p, window) in g.iter().zip(self.windows.chunks(window_len)) {
+                        for i in window {
+                            g_in[*i] += *g_p * self.scale;
+                        }
+                    }
+                }
+            }
+        }
+        grad_input
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::Pool2D {
+            mode: self.mode,
+            input_shape: self.input_shape,
+            size: self.size,
+            stride: self.stride,
+        }
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
+
+
+#[test]
+fn test_pooling() {
+    let pixels: Vec<f64> = vec![1.0, 5.0, 2.0, 0.0, 3.0, 4.0, 8.0, 6.0, 0.0, 1.0, 7.0, 2.0, 9.0,
+                                1.0, 3.0, 3.0];
+    let input = DMatrix::from_column_vector(16, 1, &pixels);
+    let mut max_pool = Pool2D::new(PoolMode::Max, Shape::new(4, 4, 1), 2, 2).unwrap();
+    assert_eq!(max_pool.forward_train(&input).as_vector(), &[5.0, 8.0, 9.0, 7.0]);
+    // the gradient only flows back to the maxima
+    let grad = max_pool.backward(&DMatrix::from_column_vector(4, 1, &[1.0, 2.0, 3.0, 4.0]));
+    assert_eq!(grad.as_vector(),
+               &[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 4.0, 0.0, 3.0, 0.0, 0.0, 0.0]);
+
+    let mut avg_pool = Pool2D::new(PoolMode::Average, Shape::new(4, 4, 1), 3, 1).unwrap();
+    assert_eq!(avg_pool.get_output_shape(), Shape::new(2, 2, 1));
+    let output = avg_pool.forward_train(&input);
+    for (o, sum) in output.as_vector().iter().zip([31.0, 35.0, 36.0, 35.0].iter()) {
+        assert!((o - sum / 9.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,535 @@
+use na::{DMatrix, DVector};
+use nn::Float;
+use nn::layers::{Initializer, Layer, add_to_columns, map_matrix, row_sums};
+use nn::layers::activation::sigmoid;
+use nn::topology::{Cell, Shape};
+use rand::{self, Rng};
+use std::ops::Range;
+use structs::serialnet::SerializableLayer;
+
+/// A recurrent layer that runs a cell over the steps of a sequence
+///
+/// The input of each sample is a sequence of `steps` feature vectors stored one after another,
+/// i.e. of shape `steps x features x 1`. The state of the cell starts at zero and is updated with
+/// every step. Depending on `sequences`, the layer passes on the state after the last step or the
+/// states after all steps (shape `steps x size x 1`), so recurrent layers can be stacked.
+///
+/// The gradients are calculated with backpropagation through time. If `bptt_length` is not 0 the
+/// sequence is split into chunks of that many steps, counted from the end, and the error is not
+/// propagated from one chunk into the chunk before (truncated BPTT).
+#[derive(Debug, Clone)]
+pub struct Recurrent<N: Float> {
+    /// the kind of cell
+    cell: Cell,
+    /// number of steps of each sequence
+    steps: usize,
+    /// number of features of each step
+    n_inputs: usize,
+    /// number of units, i.e. the size of the state
+    size: usize,
+    /// whether the states after all steps are passed on, otherwise only the last one
+    sequences: bool,
+    /// number of steps the error is propagated back, 0 for all steps
+    bptt_length: usize,
+    /// weights of the inputs, one block of `size` rows per gate
+    weights: DMatrix<N>,
+    /// weights of the previous state, one block of `size` rows per gate
+    recurrent_weights: DMatrix<N>,
+    /// biases of each gate
+    biases: DVector<N>,
+    /// values of the last training forward pass
+    trace: Option<Trace<N>>,
+    /// gradient of the input weights from the last backward pass
+    nabla_w: DMatrix<N>,
+    /// gradient of the recurrent weights from the last backward pass
+    nabla_u: DMatrix<N>,
+    /// gradient of the biases from the last backward pass
+    nabla_b: DVector<N>,
+}
+
+// Values of a training forward pass that are needed to calculate the gradients afterwards, all
+// matrices have one column per sample
+#[derive(Debug, Clone)]
+struct Trace<N: Float> {
+    // the input of each step
+    xs: Vec<DMatrix<N>>,
+    // the states before the first and after each step
+    hs: Vec<DMatrix<N>>,
+    // the memory cells of an LSTM before the first and after each step
+    cs: Vec<DMatrix<N>>,
+    // the activated gates of each step
+    gates: Vec<DMatrix<N>>,
+}
+
+impl<N: Float> Recurrent<N> {
+    /// Create a recurrent layer with `size` units for sequences of `input_shape`
+    ///
+    /// The weights are initialised from a normal distribution with standard deviation
+    /// `1 / sqrt(n)` where `n` is the number of inputs respectively units, the biases with zero.
+    /// The forget gates of an LSTM start with a bias of 1, so the cells remember by default.
+    pub fn new(cell: Cell,
+               input_shape: Shape,
+               size: usize,
+               sequences: bool,
+               bptt_length: usize)
+               -> Result<Recurrent<N>, &'static str> {
+        let n_gates = gate_count(cell);
+        let mut rnn = Recurrent::from_parts(cell,
+                                            input_shape,
+                                            sequences,
+                                            bptt_length,
+                                            DMatrix::new_zeros(n_gates * size, input_shape.width),
+                                            DMatrix::new_zeros(n_gates * size, size),
+                                            DVector::new_zeros(n_gates * size))?;
+        rnn.initialize(Initializer::Default, &mut rand::thread_rng());
+        Ok(rnn)
+    }
+
+    /// Create a recurrent layer from given weights and biases
+    ///
+    /// The rows of the weights and biases hold the gates one after another: nothing for an Elman
+    /// cell; input, forget, candidate and output for an LSTM; update, reset and candidate for a
+    /// GRU.
+    pub fn from_parts(cell: Cell,
+                      input_shape: Shape,
+                      sequences: bool,
+                      bptt_length: usize,
+                      weights: DMatrix<N>,
+                      recurrent_weights: DMatrix<N>,
+                      biases: DVector<N>)
+                      -> Result<Recurrent<N>, &'static str> {
+        if input_shape.channels != 1 {
+            return Err("recurrent layers need sequences of shape STEPSxFEATURESx1 as input");
+        }
+        let size = recurrent_weights.ncols();
+        if size == 0 || weights.nrows() != gate_count(cell) * size ||
+           weights.ncols() != input_shape.width ||
+           recurrent_weights.nrows() != weights.nrows() || biases.len() != weights.nrows() {
+            return Err("the weights of a recurrent layer do not match its input and size");
+        }
+        Ok(Recurrent {
+            cell: cell,
+            steps: input_shape.height,
+            n_inputs: input_shape.width,
+            size: size,
+            sequences: sequences,
+            bptt_length: bptt_length,
+            nabla_w: DMatrix::new_zeros(weights.nrows(), weights.ncols()),
+            nabla_u: DMatrix::new_zeros(recurrent_weights.nrows(), recurrent_weights.ncols()),
+            nabla_b: DVector::new_zeros(biases.len()),
+            weights: weights,
+            recurrent_weights: recurrent_weights,
+            biases: biases,
+            trace: None,
+        })
+    }
+
+    /// return the shape of the output, `steps x size x 1` if the states of all steps are passed
+    /// on and `size` otherwise
+    pub fn get_output_shape(&self) -> Shape {
+        if self.sequences {
+            Shape::new(self.steps, self.size, 1)
+        } else {
+            Shape::flat(self.size)
+        }
+    }
+
+    // The rows of the gate `g`
+    fn gate(&self, g: usize) -> Range<usize> {
+        g * self.size..(g + 1) * self.size
+    }
+
+    // One step of the cell for a batch: returns the activated gates, the new state and the new
+    // memory cell (which is only changed by an LSTM)
+    fn step(&self,
+            x: &DMatrix<N>,
+            h: &DMatrix<N>,
+            c: &DMatrix<N>)
+            -> (DMatrix<N>, DMatrix<N>, DMatrix<N>) {
+        let n = self.size;
+        let mut pre = &self.weights * x;
+        add_to_columns(&mut pre, &self.biases);
+        match self.cell {
+            Cell::Elman => {
+                add_assign(&mut pre, &(&self.recurrent_weights * h));
+                let gates = map_matrix(&pre, |z| z.tanh());
+                let h_new = gates.clone();
+                (gates, h_new, c.clone())
+            }
+            Cell::Lstm => {
+                add_assign(&mut pre, &(&self.recurrent_weights * h));
+                let gates = DMatrix::from_fn(pre.nrows(), pre.ncols(), |i, k| {
+                    // the candidate uses tanh, the gates sigmoid
+                    if i / n == 2 {
+                        pre[(i, k)].tanh()
+                    } else {
+                        sigmoid(pre[(i, k)])
+                    }
+                });
+                let c_new = DMatrix::from_fn(n, h.ncols(), |i, k| {
+                    gates[(n + i, k)] * c[(i, k)] + gates[(i, k)] * gates[(2 * n + i, k)]
+                });
+                let h_new = DMatrix::from_fn(n, h.ncols(), |i, k| {
+                    gates[(3 * n + i, k)] * c_new[(i, k)].tanh()
+                });
+                (gates, h_new, c_new)
+            }
+            Cell::Gru => {
+                // update and reset gate see the previous state, the candidate the reset state
+                let pre_zr = block_mul(&self.recurrent_weights, 0..2 * n, h);
+                let mut gates = DMatrix::new_zeros(3 * n, h.ncols());
+                for i in 0..2 * n {
+                    for k in 0..h.ncols() {
+                        gates[(i, k)] = sigmoid(pre[(i, k)] + pre_zr[(i, k)]);
+                    }
+                }
+                let rh = DMatrix::from_fn(n, h.ncols(), |i, k| gates[(n + i, k)] * h[(i, k)]);
+                let pre_n = block_mul(&self.recurrent_weights, self.gate(2), &rh);
+                for i in 0..n {
+                    for k in 0..h.ncols() {
+                        gates[(2 * n + i, k)] = (pre[(2 * n + i, k)] + pre_n[(i, k)]).tanh();
+                    }
+                }
+                let h_new = DMatrix::from_fn(n, h.ncols(), |i, k| {
+                    let z = gates[(i, k)];
+                    (N::one() - z) * gates[(2 * n + i, k)] + z * h[(i, k)]
+                });
+                (gates, h_new, c.clone())
+            }
+        }
+    }
+
+    // Run the cell over all steps of a batch of sequences, returns the trace of the pass
+    fn run(&self, input: &DMatrix<N>) -> Trace<N> {
+        let n_samples = input.ncols();
+        let mut trace = Trace {
+            xs: Vec::with_capacity(self.steps),
+            hs: vec![DMatrix::new_zeros(self.size, n_samples)],
+            cs: vec![DMatrix::new_zeros(self.size, n_samples)],
+            gates: Vec::with_capacity(self.steps),
+        };
+        for t in 0..self.steps {
+            let x = get_rows(input, t * self.n_inputs..(t + 1) * self.n_inputs);
+            let (gates, h, c) = self.step(&x, &trace.hs[t], &trace.cs[t]);
+            trace.xs.push(x);
+            trace.gates.push(gates);
+            trace.hs.push(h);
+            trace.cs.push(c);
+        }
+        trace
+    }
+
+    // Write the states of the trace that are passed on into `output`
+    fn write_output(&self, trace: &Trace<N>, output: &mut DMatrix<N>) {
+        if self.sequences {
+            for t in 0..self.steps {
+                set_rows(output, t * self.size, &trace.hs[t + 1]);
+            }
+        } else {
+            set_rows(output, 0, &trace.hs[self.steps]);
+        }
+    }
+
+    // Backpropagate the error `dh` of the state after step `t` through the cell, `dc` is the
+    // error of the memory cell of an LSTM. Returns the error with respect to the pre-activations
+    // of the gates and the errors of the state and memory cell before the step. The gradients
+    // of the recurrent weights are added to `nabla_u`.
+    fn backward_step(&self,
+                     trace: &Trace<N>,
+                     t: usize,
+                     dh: &DMatrix<N>,
+                     dc: &DMatrix<N>,
+                     nabla_u: &mut DMatrix<N>)
+                     -> (DMatrix<N>, DMatrix<N>, DMatrix<N>) {
+        let n = self.size;
+        let gates = &trace.gates[t];
+        let h_prev = &trace.hs[t];
+        let n_samples = dh.ncols();
+        let mut dpre = DMatrix::new_zeros(gates.nrows(), n_samples);
+        match self.cell {
+            Cell::Elman => {
+                for i in 0..n {
+                    for k in 0..n_samples {
+                        let h = gates[(i, k)];
+                        dpre[(i, k)] = dh[(i, k)] * (N::one() - h * h);
+                    }
+                }
+                add_block_outer(nabla_u, 0..n, &dpre, h_prev);
+                let dh_prev = block_tr_mul(&self.recurrent_weights, 0..n, &dpre);
+                (dpre, dh_prev, dc.clone())
+            }
+            Cell::Lstm => {
+                let (c, c_prev) = (&trace.cs[t + 1], &trace.cs[t]);
+                let mut dc_prev = DMatrix::new_zeros(n, n_samples);
+                for i in 0..n {
+                    for k in 0..n_samples {
+                        let (ig, fg) = (gates[(i, k)], gates[(n + i, k)]);
+                        let (g, og) = (gates[(2 * n + i, k)], gates[(3 * n + i, k)]);
+                        let tc = c[(i, k)].tanh();
+                        let dc = dc[(i, k)] + dh[(i, k)] * og * (N::one() - tc * tc);
+                        dpre[(i, k)] = dc * g * ig * (N::one() - ig);
+                        dpre[(n + i, k)] = dc * c_prev[(i, k)] * fg * (N::one() - fg);
+                        dpre[(2 * n + i, k)] = dc * ig * (N::one() - g * g);
+                        dpre[(3 * n + i, k)] = dh[(i, k)] * tc * og * (N::one() - og);
+                        dc_prev[(i, k)] = dc * fg;
+                    }
+                }
+                add_block_outer(nabla_u, 0..4 * n, &dpre, h_prev);
+                let dh_prev = block_tr_mul(&self.recurrent_weights, 0..4 * n, &dpre);
+                (dpre, dh_prev, dc_prev)
+            }
+            Cell::Gru => {
+                let mut dh_prev = DMatrix::new_zeros(n, n_samples);
+                for i in 0..n {
+                    for k in 0..n_samples {
+                        let (z, cand) = (gates[(i, k)], gates[(2 * n + i, k)]);
+                        dpre[(i, k)] = dh[(i, k)] * (h_prev[(i, k)] - cand) * z * (N::one() - z);
+                        dpre[(2 * n + i, k)] = dh[(i, k)] * (N::one() - z) *
+                                               (N::one() - cand * cand);
+                        dh_prev[(i, k)] = dh[(i, k)] * z;
+                    }
+                }
+                // the candidate saw the reset state
+                let rh = DMatrix::from_fn(n, n_samples, |i, k| gates[(n + i, k)] * h_prev[(i, k)]);
+                let dpre_n = get_rows(&dpre, self.gate(2));
+                add_block_outer(nabla_u, self.gate(2), &dpre_n, &rh);
+                let drh = block_tr_mul(&self.recurrent_weights, self.gate(2), &dpre_n);
+                for i in 0..n {
+                    for k in 0..n_samples {
+                        let r = gates[(n + i, k)];
+                        dpre[(n + i, k)] = drh[(i, k)] * h_prev[(i, k)] * r * (N::one() - r);
+                        dh_prev[(i, k)] += drh[(i, k)] * r;
+                    }
+                }
+                // update and reset gate saw the previous state
+                let dpre_zr = get_rows(&dpre, 0..2 * n);
+                add_block_outer(nabla_u, 0..2 * n, &dpre_zr, h_prev);
+                add_assign(&mut dh_prev,
+                           &block_tr_mul(&self.recurrent_weights, 0..2 * n, &dpre_zr));
+                (dpre, dh_prev, dc.clone())
+            }
+        }
+    }
+}
+
+impl<N: Float> Layer<N> for Recurrent<N> {
+    fn kind(&self) -> &'static str {
+        match self.cell {
+            Cell::Elman => "rnn",
+            Cell::Lstm => "lstm",
+            Cell::Gru => "gru",
+        }
+    }
+
+    fn output_size(&self, input_size: usize) -> Result<usize, &'static str> {
+        if input_size != self.steps * self.n_inputs {
+            return Err("input size does not match the input shape of a recurrent layer");
+        }
+        Ok(self.get_output_shape().len())
+    }
+
+    fn forward_into(&self, input: &DMatrix<N>, output: &mut DMatrix<N>) {
+        let trace = self.run(input);
+        self.write_output(&trace, output);
+    }
+
+    fn forward_train(&mut self, input: &DMatrix<N>) -> DMatrix<N> {
+        let trace = self.run(input);
+        let mut output = DMatrix::new_zeros(self.get_output_shape().len(), input.ncols());
+        self.write_output(&trace, &mut output);
+        self.trace = Some(trace);
+        output
+    }
+
+    fn backward(&mut self, grad_output: &DMatrix<N>) -> DMatrix<N> {
+        let trace = self.trace.take().expect("backward called without forward_train");
+        let n_samples = grad_output.ncols();
+        let mut nabla_w = DMatrix::new_zeros(self.weights.nrows(), self.weights.ncols());
+        let mut nabla_u = DMatrix::new_zeros(self.recurrent_weights.nrows(), self.size);
+        let mut nabla_b = DVector::new_zeros(self.biases.len());
+        let mut grad_input = DMatrix::new_zeros(self.steps * self.n_inputs, n_samples);
+
+        // errors of the state and memory cell, propagated back from the later steps
+        let mut dh_next = DMatrix::new_zeros(self.size, n_samples);
+        let mut dc_next = DMatrix::new_zeros(self.size, n_samples);
+        for t in (0..self.steps).rev() {
+            // the error of the output of this step
+            if self.sequences {
+                let rows = t * self.size..(t + 1) * self.size;
+                add_assign(&mut dh_next, &get_rows(grad_output, rows));
+            } else if t == self.steps - 1 {
+                add_assign(&mut dh_next, grad_output);
+            }
+
+            let (dpre, dh_prev, dc_prev) =
+                self.backward_step(&trace, t, &dh_next, &dc_next, &mut nabla_u);
+            add_block_outer(&mut nabla_w, 0..dpre.nrows(), &dpre, &trace.xs[t]);
+            add_assign_vector(&mut nabla_b, &row_sums(&dpre));
+            set_rows(&mut grad_input,
+                     t * self.n_inputs,
+                     &block_tr_mul(&self.weights, 0..dpre.nrows(), &dpre));
+
+            // truncated BPTT: the error does not flow into the previous chunk of steps
+            if self.bptt_length > 0 && (self.steps - t) % self.bptt_length == 0 {
+                dh_next = DMatrix::new_zeros(self.size, n_samples);
+                dc_next = DMatrix::new_zeros(self.size, n_samples);
+            } else {
+                dh_next = dh_prev;
+                dc_next = dc_prev;
+            }
+        }
+
+        self.trace = Some(trace);
+        self.nabla_w = nabla_w;
+        self.nabla_u = nabla_u;
+        self.nabla_b = nabla_b;
+        grad_input
+    }
+
+    fn parameters(&self) -> Vec<&[N]> {
+        vec![self.weights.as_vector(), self.recurrent_weights.as_vector(), &self.biases.at]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut [N]> {
+        vec![self.weights.as_mut_vector(),
+             self.recurrent_weights.as_mut_vector(),
+             &mut self.biases.at]
+    }
+
+    fn gradients(&self) -> Vec<&[N]> {
+        vec![self.nabla_w.as_vector(), self.nabla_u.as_vector(), &self.nabla_b.at]
+    }
+
+    fn initialize(&mut self, initializer: Initializer, rng: &mut Rng) {
+        // by default the weights are scaled and the biases are zero
+        let initializer = match initializer {
+            Initializer::Default => Initializer::Scaled,
+            initializer => initializer,
+        };
+        let size = self.size;
+        for w in self.weights.as_mut_vector() {
+            *w = initializer.weight(self.n_inputs, size, rng);
+        }
+        for u in self.recurrent_weights.as_mut_vector() {
+            *u = initializer.weight(size, size, rng);
+        }
+        for b in &mut self.biases.at {
+            *b = initializer.bias(rng);
+        }
+        if self.cell == Cell::Lstm {
+            for b in &mut self.biases.at[size..2 * size] {
+                *b = N::one();
+            }
+        }
+    }
+
+    fn serialize(&self) -> SerializableLayer<N> {
+        SerializableLayer::Recurrent {
+            cell: self.cell,
+            input_shape: Shape::new(self.steps, self.n_inputs, 1),
+            sequences: self.sequences,
+            bptt_length: self.bptt_length,
+            weights: (self.weights.nrows(),
+                      self.weights.ncols(),
+                      self.weights.as_vector().to_vec()),
+            recurrent_weights: (self.recurrent_weights.nrows(),
+                                self.recurrent_weights.ncols(),
+                                self.recurrent_weights.as_vector().to_vec()),
+            biases: self.biases.at.clone(),
+        }
+    }
+
+    fn box_clone(&self) -> Box<Layer<N>> {
+        Box::new(self.clone())
+    }
+}
+
+// Number of blocks of weights a cell needs
+fn gate_count(cell: Cell) -> usize {
+    match cell {
+        Cell::Elman => 1,
+        Cell::Lstm => 4,
+        Cell::Gru => 3,
+    }
+}
+
+// Add `b` to `a`
+fn add_assign<N: Float>(a: &mut DMatrix<N>, b: &DMatrix<N>) {
+    for (a_ij, b_ij) in a.as_mut_vector().iter_mut().zip(b.as_vector().iter()) {
+        *a_ij += *b_ij;
+    }
+}
+
+// Add `b` to `a`
+fn add_assign_vector<N: Float>(a: &mut DVector<N>, b: &DVector<N>) {
+    for (a_i, b_i) in a.at.iter_mut().zip(b.at.iter()) {
+        *a_i += *b_i;
+    }
+}
+
+// A copy of the given rows of `m`
+fn get_rows<N: Float>(m: &DMatrix<N>, rows: Range<usize>) -> DMatrix<N> {
+    DMatrix::from_fn(rows.end - rows.start, m.ncols(), |i, j| m[(rows.start + i, j)])
+}
+
+// Overwrite the rows of `m` starting at `start` with `rows`
+fn set_rows<N: Float>(m: &mut DMatrix<N>, start: usize, rows: &DMatrix<N>) {
+    for i in 0..rows.nrows() {
+        for j in 0..rows.ncols() {
+            m[(start + i, j)] = rows[(i, j)];
+        }
+    }
+}
+
+// The product of the given rows of `m` with `v`
+fn block_mul<N: Float>(m: &DMatrix<N>, rows: Range<usize>, v: &DMatrix<N>) -> DMatrix<N> {
+    DMatrix::from_fn(rows.end - rows.start, v.ncols(), |i, k| {
+        let mut sum = N::zero();
+        for j in 0..m.ncols() {
+            sum += m[(rows.start + i, j)] * v[(j, k)];
+        }
+        sum
+    })
+}
+
+// The product of the transpose of the given rows of `m` with `d`
+fn block_tr_mul<N: Float>(m: &DMatrix<N>, rows: Range<usize>, d: &DMatrix<N>) -> DMatrix<N> {
+    DMatrix::from_fn(m.ncols(), d.ncols(), |j, k| {
+        let mut sum = N::zero();
+        for (i, r) in rows.clone().enumerate() {
+            sum += m[(r, j)] * d[(i, k)];
+        }
+        sum
+    })
+}
+
+// Add the product of `d` with the transpose of `v` to the given rows of `dst`
+fn add_block_outer<N: Float>(dst: &mut DMatrix<N>,
+                             rows: Range<usize>,
+                             d: &DMatrix<N>,
+                             v: &DMatrix<N>) {
+    for (i, r) in rows.enumerate() {
+        for j in 0..v.nrows() {
+            let mut sum = N::zero();
+            for k in 0..d.ncols() {
+                sum += d[(i, k)] * v[(j, k)];
+            }
+            dst[(r, j)] += sum;
+        }
+    }
+}
+
+
+#[test]
+fn test_truncated_bptt() {
+    // 4 steps of 2 features, the error is only propagated back through the last step
+    let mut lstm = Recurrent::<f64>::new(Cell::Lstm, Shape::new(4, 2, 1), 3, false, 1).unwrap();
+    assert_eq!(lstm.output_size(8), Ok(3));
+    let input = DMatrix::from_fn(8, 2, |i, k| (i + k) as f64 / 8.0);
+    lstm.forward_train(&input);
+    let grad_input = lstm.backward(&DMatrix::from_element(3, 2, 1.0));
+    for i in 0..6 {
+        assert_eq!((grad_input[(i, 0)], grad_input[(i, 1)]), (0.0, 0.0));
+    }
+    assert!(grad_input[(6, 0)] != 0.0);
+}
@@ -1,204 +1,1510 @@
-use structs::Data;
-use nn::Network;
-use na::{DVector, DMatrix, Iterable, Transpose};
+use structs::{Data, Task};
+use nn::{Activation, Augmentation, Cost, Float, Network, Workspace};
+use nn::callbacks::{Callback, Control};
+use nn::history::{EpochRecord, TrainingHistory};
+use na::DMatrix;
+use rand::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::Instant;
+use structs::serialnet::SerializableLayer;
+
+// Number of samples that are fed through the network at once during evaluation
+const EVAL_BATCH_SIZE: usize = 128;
 
 /// Execute Stochastic Gradient Descent on the `Network`.
 ///
 /// `training_data` is the data actually used for learning and should be disjoint from the
 /// `test_data`. Epochs is the  Number of learning cycles in each of which the whole `training_data`
 /// will be cycled through in mini batches of `mini_batch_size` size. `Eta` is the learning rate.
-/// `test_data` can be empty and if it is there will be no validation of the network.
+/// `test_data` can be empty and if it is there will be no validation of the network. Returns the
+/// costs, accuracy and duration of every epoch.
 ///
 /// The weights and biases of the network will be changed according to the gradient on the Error
 /// over the mini_batch. Note that this means that the SGD does not actually calculate the gradient
 /// over the whole training data set in each cycle, instead it calculates the gradient over the mini
 /// batches and then sums those up (hence Stochastic Gradient Descent).
-pub fn sgd(mut nn: &mut Network,
-           mut training_data: Vec<Data>,
-           epochs: u32,
-           mini_batch_size: u32,
-           eta: f32,
-           test_data: Vec<Data>) {
-    use rand::{self, Rng};
-
-    // Used to shuffle data
-    let mut rng = rand::thread_rng();
+#[deprecated(note = "use a `Trainer`, which also offers other cost functions, optimizers, \
+                     learning rate schedules, regularization and callbacks")]
+pub fn sgd<N: Float>(nn: &mut Network<N>,
+                     training_data: Vec<Data<N>>,
+                     epochs: u32,
+                     mini_batch_size: u32,
+                     eta: N,
+                     test_data: &Vec<Data<N>>)
+                     -> TrainingHistory {
+    Trainer::new()
+        .epochs(epochs)
+        .batch_size(mini_batch_size)
+        .learning_rate(eta.to_f64())
+        .run(nn, training_data, test_data)
+}
 
-    // In each learning epoche: Shuffle the training data so that the mini batches always contain
-    // different data sets from different flowers. Then update the mini batches using SGD.
-    for j in 0..epochs {
-        rng.shuffle(&mut training_data);
-        for mut mini_batch in training_data.chunks_mut(mini_batch_size as usize) {
-            // all the actual learning happens there:
-            update_mini_batch(&mut nn, &mut mini_batch, eta);
-        }
-        if test_data.len() > 0 {
-            debug!("Epoch {}: {}/{}",
-                   j + 1,
-                   evaluate(&nn, &test_data),
-                   test_data.len());
-        } else {
-            debug!("Epoch {} complete!", j + 1);
+/// How the gradients of a mini batch are turned into updates of the parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimizer {
+    /// plain gradient descent, every parameter is moved against its gradient
+    Sgd,
+    /// gradient descent with momentum: every update also moves on by the given fraction of the
+    /// last update of the parameter, which is usually around 0.9
+    Momentum(f64),
+}
+
+/// How the learning rate changes from epoch to epoch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// the same learning rate in every epoch
+    Constant,
+    /// the learning rate is multiplied with `factor` every `every` epochs
+    Step { every: u32, factor: f64 },
+    /// the learning rate is multiplied with the given factor after every epoch
+    Exponential(f64),
+}
+
+impl Schedule {
+    /// The learning rate of `epoch`, counted from 0, if the first epoch has the learning rate
+    /// `eta`
+    pub fn learning_rate(&self, eta: f64, epoch: u32) -> f64 {
+        match *self {
+            Schedule::Constant => eta,
+            Schedule::Step { every, factor } => eta * factor.powi((epoch / every.max(1)) as i32),
+            Schedule::Exponential(factor) => eta * factor.powi(epoch as i32),
+        }
+    }
+}
+
+/// Penalties on large parameters against overfitting, in addition to dropout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Regularization {
+    /// no penalty
+    None,
+    /// L2 regularization with the given `lambda`: `lambda / 2n` times the sum of the squared
+    /// parameters is added to the cost, where `n` is the number of training samples. All
+    /// parameters are penalized, including the biases.
+    L2(f64),
+}
+
+/// What the training does once costs, gradients or parameters stop being finite numbers, which
+/// usually means that the learning rate is too high
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Divergence {
+    /// stop the training and keep the network of the last complete epoch
+    Abort,
+    /// restore the network of the start of the epoch and repeat the epoch with the learning rate
+    /// multiplied with `factor`, at most `retries` times over the whole training, then abort
+    Rollback { factor: f64, retries: u32 },
+}
+
+impl FromStr for Divergence {
+    type Err = io::Error;
+
+    /// Parses `abort` or `rollback[:FACTOR]`, the factor is 0.5 by default
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("abort"), None) => Ok(Divergence::Abort),
+            (Some("rollback"), factor) => {
+                let factor = match factor.map(|f| f.parse::<f64>()) {
+                    None => 0.5,
+                    Some(Ok(factor)) if factor > 0.0 && factor < 1.0 => factor,
+                    Some(_) => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "The rollback factor has to be in (0, 1)."))
+                    }
+                };
+                Ok(Divergence::Rollback {
+                    factor: factor,
+                    retries: 5,
+                })
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown divergence handling.")),
+        }
+    }
+}
+
+/// Trains a `Network` with mini batch gradient descent
+///
+/// In each epoch the training data is shuffled and fed through the network in mini batches, the
+/// parameters are updated after every mini batch. Afterwards the network is validated on the test
+/// data, if there is any. Every setting has a default, e.g. 100 epochs of plain gradient descent
+/// with a learning rate of 0.05, see `sgd` for the meaning of the basic settings. Callbacks can be
+/// added to run custom logic during the training, see `callbacks`.
+///
+/// # Example
+///
+/// ```
+/// extern crate nalgebra;
+/// extern crate rustle_my_net;
+///
+/// use nalgebra::DVector;
+/// use rustle_my_net::nn::{Cost, Network, NetworkBuilder, Topology, Trainer};
+/// use rustle_my_net::nn::callbacks::EarlyStopping;
+/// use rustle_my_net::nn::learning::{Optimizer, Schedule};
+/// use rustle_my_net::structs::Data;
+///
+/// # fn main() {
+/// let data: Vec<Data> = (0..8)
+///     .map(|i| Data::new(DVector::from_slice(2, &[i as f32 / 8.0, 0.5]), (i % 2) as u8, 2))
+///     .collect();
+///
+/// let nn: Network = NetworkBuilder::new(Topology::dense(&[2, 4, 2])).seed(1).build().unwrap();
+/// let (nn, history) = Trainer::new()
+///     .epochs(5)
+///     .batch_size(4)
+///     .learning_rate(0.5)
+///     .optimizer(Optimizer::Momentum(0.9))
+///     .schedule(Schedule::Exponential(0.9))
+///     .cost(Cost::CrossEntropy)
+///     .callback(EarlyStopping::new(3))
+///     .train(nn, data.clone(), &data);
+/// assert!(history.epochs.len() <= 5);
+/// assert_eq!(nn.output_size(), 2);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Trainer<N: Float = f32> {
+    /// number of passes over the training data
+    epochs: u32,
+    /// number of samples per update
+    batch_size: u32,
+    /// learning rate of the first epoch
+    learning_rate: f64,
+    /// how the gradients are turned into updates
+    optimizer: Optimizer,
+    /// how the learning rate changes over the epochs
+    schedule: Schedule,
+    /// penalty on large parameters
+    regularization: Regularization,
+    /// the cost function whose gradient is descended
+    cost: Cost,
+    /// outputs above the threshold count as predicted labels of multi-label data
+    threshold: f64,
+    /// largest absolute value of each element of the gradients
+    clip_value: Option<f64>,
+    /// largest global L2 norm of all gradients
+    clip_norm: Option<f64>,
+    /// what happens when the training diverges
+    divergence: Divergence,
+    /// weights and resampling of imbalanced classes
+    balance: ClassBalance<N>,
+    /// random transformations of the training images
+    augmentation: Option<Augmentation>,
+    /// custom logic that runs during training, in order
+    callbacks: Vec<Box<Callback<N>>>,
+}
+
+impl<N: Float> Default for Trainer<N> {
+    fn default() -> Self {
+        Trainer {
+            epochs: 100,
+            batch_size: 32,
+            learning_rate: 0.05,
+            optimizer: Optimizer::Sgd,
+            schedule: Schedule::Constant,
+            regularization: Regularization::None,
+            cost: Cost::Quadratic,
+            threshold: 0.5,
+            clip_value: None,
+            clip_norm: None,
+            divergence: Divergence::Abort,
+            balance: ClassBalance::default(),
+            augmentation: None,
+            callbacks: Vec::new(),
+        }
+    }
+}
+
+impl<N: Float> Trainer<N> {
+    /// Create a trainer with the default settings
+    pub fn new() -> Trainer<N> {
+        Trainer::default()
+    }
+
+    /// Set the number of epochs
+    pub fn epochs(mut self, epochs: u32) -> Trainer<N> {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Set the number of samples of each mini batch
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn batch_size(mut self, batch_size: u32) -> Trainer<N> {
+        assert!(batch_size > 0, "The mini batch size has to be at least 1");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Set the learning rate of the first epoch
+    pub fn learning_rate(mut self, eta: f64) -> Trainer<N> {
+        self.learning_rate = eta;
+        self
+    }
+
+    /// Set the optimizer
+    pub fn optimizer(mut self, optimizer: Optimizer) -> Trainer<N> {
+        self.optimizer = optimizer;
+        self
+    }
+
+    /// Set how the learning rate changes over the epochs
+    pub fn schedule(mut self, schedule: Schedule) -> Trainer<N> {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Set the penalty on large parameters
+    pub fn regularization(mut self, regularization: Regularization) -> Trainer<N> {
+        self.regularization = regularization;
+        self
+    }
+
+    /// Set the cost function
+    pub fn cost(mut self, cost: Cost) -> Trainer<N> {
+        self.cost = cost;
+        self
+    }
+
+    /// Set the threshold above which outputs count as predicted labels of multi-label data
+    pub fn threshold(mut self, threshold: f64) -> Trainer<N> {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Clip every element of the mean gradients of a mini batch to `[-max, max]`, `max` has to
+    /// be positive
+    pub fn clip_value(mut self, max: f64) -> Trainer<N> {
+        self.clip_value = Some(max);
+        self
+    }
+
+    /// Scale the mean gradients of a mini batch down so that their global L2 norm, taken over the
+    /// gradients of all layers, is at most `max_norm`, which has to be positive
+    ///
+    /// Unlike clipping by value this keeps the direction of the update. If both are set, the
+    /// values are clipped first.
+    pub fn clip_norm(mut self, max_norm: f64) -> Trainer<N> {
+        self.clip_norm = Some(max_norm);
+        self
+    }
+
+    /// Set what happens when costs, gradients or parameters stop being finite numbers
+    pub fn divergence(mut self, divergence: Divergence) -> Trainer<N> {
+        self.divergence = divergence;
+        self
+    }
+
+    /// Weight and resample the classes of the training data, see `ClassBalance`
+    pub fn class_balance(mut self, balance: ClassBalance<N>) -> Trainer<N> {
+        self.balance = balance;
+        self
+    }
+
+    /// Train on randomly transformed copies of the training images
+    pub fn augmentation(mut self, augmentation: Augmentation) -> Trainer<N> {
+        self.augmentation = Some(augmentation);
+        self
+    }
+
+    /// Run `callback` during the training, after the callbacks added before
+    pub fn callback<C: Callback<N> + 'static>(mut self, callback: C) -> Trainer<N> {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Train `nn` on `training_data`, validating it on `test_data` after every epoch
+    ///
+    /// `test_data` can be empty, then the network is not validated. The training ends early if a
+    /// callback stops it. Returns the trained network and the costs, accuracy, learning rate and
+    /// duration of every epoch. Without training data the network is returned unchanged with an
+    /// empty history.
+    pub fn train(&mut self,
+                 nn: Network<N>,
+                 training_data: Vec<Data<N>>,
+                 test_data: &Vec<Data<N>>)
+                 -> (Network<N>, TrainingHistory) {
+        let mut nn = nn;
+        let history = self.run(&mut nn, training_data, test_data);
+        (nn, history)
+    }
+
+    // Train the network in place, see `train`
+    fn run(&mut self,
+           nn: &mut Network<N>,
+           mut training_data: Vec<Data<N>>,
+           test_data: &Vec<Data<N>>)
+           -> TrainingHistory {
+        use rand::{self, SeedableRng, StdRng};
+
+        // the costs are means over the trained samples, there are none to learn from
+        if training_data.is_empty() {
+            error!("No training data, the network is not trained");
+            return TrainingHistory::new();
+        }
+
+        // Used to shuffle data
+        let mut rng = rand::thread_rng();
+        // augmentation uses its own seeded generator, so the transformations can be repeated
+        let seed = self.augmentation.as_ref().map_or(0, |a| a.seed);
+        let mut augment_rng: StdRng = SeedableRng::from_seed(&[seed][..]);
+        let mut history = TrainingHistory::new();
+        // momentum remembers the last update of every parameter
+        let mut velocities = match self.optimizer {
+            Optimizer::Sgd => None,
+            Optimizer::Momentum(_) => {
+                let zeros = |p: &&[N]| vec![N::zero(); p.len()];
+                Some(nn.get_layers()
+                    .iter()
+                    .map(|layer| layer.parameters().iter().map(zeros).collect())
+                    .collect::<Vec<Vec<Vec<N>>>>())
+            }
+        };
+        let n_training = training_data.len();
+        let threshold = N::from_f64(self.threshold);
+        // the learning rate of the schedule is reduced after each rollback
+        let (mut rate_factor, mut rollbacks) = (1.0, 0);
+        for callback in &mut self.callbacks {
+            callback.on_train_begin(nn);
+        }
+
+        // In each learning epoche: Shuffle the training data so that the mini batches always
+        // contain different data sets from different flowers. Then update the mini batches using
+        // SGD.
+        let mut j = 0;
+        while j < self.epochs {
+            let start = Instant::now();
+            for callback in &mut self.callbacks {
+                callback.on_epoch_begin(j + 1, nn);
+            }
+            // the last good state, in case the training diverges during the epoch
+            let snapshot = (nn.clone(), velocities.clone());
+            let eta = self.schedule.learning_rate(self.learning_rate, j) * rate_factor;
+            // the parameters shrink by this fraction with every update
+            let decay = match self.regularization {
+                Regularization::None => N::zero(),
+                Regularization::L2(lambda) => N::from_f64(eta * lambda / n_training as f64),
+            };
+            let update = Update {
+                eta: N::from_f64(eta),
+                decay: decay,
+                optimizer: self.optimizer,
+                clip_value: self.clip_value.map(N::from_f64),
+                clip_norm: self.clip_norm.map(N::from_f64),
+            };
+            // resampling draws different samples in each epoch
+            let mut epoch_data = match self.balance.resampling {
+                Resampling::None => None,
+                resampling => Some(resample(&training_data, resampling, &mut rng)),
+            };
+            let epoch_data = epoch_data.as_mut().unwrap_or(&mut training_data);
+            rng.shuffle(epoch_data);
+            let mut training_cost = N::zero();
+            let mut n_trained = 0;
+            // the global norms of the gradients of the mini batches
+            let mut norms = Vec::new();
+            let mut control = Control::Continue;
+            let mut diverged = None;
+            for (b, mut mini_batch) in epoch_data.chunks_mut(self.batch_size as usize)
+                .enumerate() {
+                let weights = self.balance.weights.as_ref();
+                let velocities = velocities.as_mut().map(|v| &mut v[..]);
+                // all the actual learning happens there:
+                let result = match self.augmentation {
+                    Some(ref augmentation) => {
+                        let mut augmented: Vec<Data<N>> = mini_batch.iter()
+                            .map(|d| augmentation.apply(d, &mut augment_rng))
+                            .collect();
+                        update_mini_batch(nn,
+                                          &mut augmented,
+                                          &update,
+                                          self.cost,
+                                          weights,
+                                          velocities)
+                    }
+                    None => {
+                        update_mini_batch(nn,
+                                          &mut mini_batch,
+                                          &update,
+                                          self.cost,
+                                          weights,
+                                          velocities)
+                    }
+                };
+                let (batch_cost, norm) = match result {
+                    Ok(result) => result,
+                    Err(non_finite) => {
+                        diverged = Some(format!("{} in mini batch {} of epoch {}",
+                                                non_finite,
+                                                b + 1,
+                                                j + 1));
+                        break;
+                    }
+                };
+                training_cost += batch_cost;
+                n_trained += mini_batch.len();
+                norms.push(norm.to_f64());
+
+                let cost = batch_cost.to_f64() / mini_batch.len() as f64;
+                for callback in &mut self.callbacks {
+                    if callback.on_batch_end(j + 1, b, cost, nn) == Control::Stop {
+                        control = Control::Stop;
+                    }
+                }
+                if control == Control::Stop {
+                    break;
+                }
+            }
+
+            // continue from the state before the epoch, either with a lower learning rate or not
+            // at all
+            if let Some(msg) = diverged {
+                *nn = snapshot.0;
+                velocities = snapshot.1;
+                match self.divergence {
+                    Divergence::Rollback { factor, retries } if rollbacks < retries => {
+                        rollbacks += 1;
+                        rate_factor *= factor;
+                        error!("Training diverged: {}. Rolling back to the start of the epoch \
+                                with a learning rate of {}",
+                               msg,
+                               eta * factor);
+                        continue;
+                    }
+                    _ => {
+                        error!("Training diverged: {}. Stopping with the network of the last \
+                                complete epoch",
+                               msg);
+                        break;
+                    }
+                }
+            }
+            let training_cost = training_cost.to_f64() / n_trained as f64;
+
+            let (mut validation_cost, mut accuracy) = (None, None);
+            if test_data.len() > 0 {
+                let evaluation = evaluate_metrics(&nn, test_data, threshold);
+                debug!("Epoch {}: {}", j + 1, evaluation);
+                validation_cost = Some(mean_cost(&nn, test_data, self.cost).to_f64());
+                accuracy = evaluation.accuracy();
+            } else {
+                debug!("Epoch {} complete!", j + 1);
+            }
+
+            let training_accuracy = evaluate_metrics(&nn, &training_data, threshold).accuracy();
+            let gradient_norm = norms.iter().sum::<f64>() / norms.len() as f64;
+
+            let elapsed = start.elapsed();
+            let record = EpochRecord {
+                epoch: j + 1,
+                training_cost: training_cost,
+                validation_cost: validation_cost,
+                accuracy: accuracy,
+                training_accuracy: training_accuracy,
+                learning_rate: eta,
+                gradient_norm: gradient_norm,
+                max_gradient_norm: norms.iter().cloned().fold(0.0, f64::max),
+                seconds: elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9,
+            };
+            // every callback sees the epoch, even if an earlier one stops the training
+            for callback in &mut self.callbacks {
+                if callback.on_epoch_end(&record, nn) == Control::Stop {
+                    control = Control::Stop;
+                }
+            }
+            history.push(record);
+            if control == Control::Stop {
+                break;
+            }
+            j += 1;
+        }
+        for callback in &mut self.callbacks {
+            callback.on_train_end(&history, nn);
+        }
+        history
+    }
+}
+
+// How the parameters are updated in an epoch
+struct Update<N> {
+    // the learning rate
+    eta: N,
+    // the fraction every parameter shrinks by, for regularization
+    decay: N,
+    // how the gradients are turned into updates
+    optimizer: Optimizer,
+    // the largest absolute value of each gradient element
+    clip_value: Option<N>,
+    // the largest global norm of the gradients
+    clip_norm: Option<N>,
+}
+
+// Values that are not finite numbers any more, found while training on a mini batch
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NonFinite {
+    // the cost of the mini batch
+    Cost,
+    // the gradients of the layer with the given number, counted from 1, and kind
+    Gradients(usize, &'static str),
+    // the parameters of the layer with the given number, counted from 1, and kind after the
+    // update
+    Parameters(usize, &'static str),
+}
+
+impl fmt::Display for NonFinite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NonFinite::Cost => write!(f, "non-finite cost"),
+            NonFinite::Gradients(l, kind) => {
+                write!(f, "non-finite gradients in layer {} ({})", l, kind)
+            }
+            NonFinite::Parameters(l, kind) => {
+                write!(f, "non-finite parameters in layer {} ({})", l, kind)
+            }
         }
     }
 }
 
+// Whether all values are finite numbers, i.e. neither infinite nor NaN
+fn all_finite<N: Float>(values: &[N]) -> bool {
+    values.iter().all(|x| x.is_finite())
+}
+
+// The L2 norm of all gradients of all layers. The values are scaled by the largest one first, so
+// large gradients do not overflow when they are squared.
+fn global_norm<N: Float>(gradients: &[Vec<Vec<N>>]) -> N {
+    let values = || gradients.iter().flat_map(|l| l.iter()).flat_map(|g| g.iter());
+    let max = values().fold(N::zero(), |max, x| max.max(x.abs()));
+    if max == N::zero() {
+        return max;
+    }
+    max * values().fold(N::zero(), |sum, x| sum + (*x / max) * (*x / max)).sqrt()
+}
 
-// Applies Stochastic Gradient Descent over the mini batch.
-fn update_mini_batch(mut nn: &mut Network, mini_batch: &mut [Data], eta: f32) {
-    // nabla_b holds changes for biases in the network. Initialise with zeros because
-    // the changes will later on be summed up in this vector
-    let mut nabla_b: Vec<DVector<f32>> = Vec::with_capacity(nn.get_biases().len());
-    for biases in nn.get_biases() {
-        nabla_b.push(DVector::new_zeros(biases.len()));
+// Clips the gradients of all layers to `[-clip_value, clip_value]`, then scales them down so
+// their global norm is at most `clip_norm`. Returns the global norm before clipping.
+fn clip_gradients<N: Float>(gradients: &mut [Vec<Vec<N>>],
+                            clip_value: Option<N>,
+                            clip_norm: Option<N>)
+                            -> N {
+    let norm = global_norm(gradients);
+    if let Some(max) = clip_value {
+        for x in gradients.iter_mut().flat_map(|l| l.iter_mut()).flat_map(|g| g.iter_mut()) {
+            *x = x.max(-max).min(max);
+        }
     }
-    // holds changes for weights in the network, similar to biases
-    let mut nabla_w: Vec<DMatrix<f32>> = Vec::with_capacity(nn.get_weights().len());
-    for weights in nn.get_weights() {
-        // verify: rows, columns
-        nabla_w.push(DMatrix::new_zeros(weights.nrows(), weights.ncols()));
+    if let Some(max_norm) = clip_norm {
+        let clipped_norm = if clip_value.is_some() { global_norm(gradients) } else { norm };
+        if clipped_norm > max_norm {
+            let scale = max_norm / clipped_norm;
+            for x in gradients.iter_mut().flat_map(|l| l.iter_mut()).flat_map(|g| g.iter_mut()) {
+                *x = *x * scale;
+            }
+        }
     }
+    norm
+}
 
+// Applies gradient descent over the mini batch. Returns the cost of the mini batch before the
+// update and the global norm of its mean gradients before they were clipped. `velocities` holds
+// the last update of every parameter of every layer if the optimizer uses momentum. Returns where
+// values first stopped being finite numbers, the parameters might be partially updated then.
+fn update_mini_batch<N: Float>(mut nn: &mut Network<N>,
+                               mini_batch: &mut [Data<N>],
+                               update: &Update<N>,
+                               cost: Cost,
+                               class_weights: Option<&Vec<N>>,
+                               mut velocities: Option<&mut [Vec<Vec<N>>]>)
+                               -> Result<(N, N), NonFinite> {
     // necessary because we can't access mini_batch_len later on
-    let mini_batch_len = mini_batch.len();
+    let mini_batch_len = N::from_f64(mini_batch.len() as f64);
 
-    // for each dataset in mini_batch: calculate gradients, add to nablas
-    for data in mini_batch {
-        let (delta_nabla_b, delta_nabla_w) =
-            backprop(&mut nn, data.get_input(), data.get_class_vector());
-        for (mut nb, dnb) in nabla_b.iter_mut().zip(delta_nabla_b.iter()) {
-            // TODO: Remove clone (seriously)
-            *nb += dnb.clone();
+    // the whole mini batch is fed through the network at once, one sample per column
+    let (inputs, desired_outputs) = batch_to_matrices(mini_batch);
+    let sample_weights = class_weights.map(|weights| {
+        mini_batch.iter()
+            .map(|data| data.get_class().map_or(N::one(), |c| weights[c]))
+            .collect::<Vec<N>>()
+    });
+    // calculate the gradients summed over the mini batch, they are stored in the layers
+    let batch_cost = backprop(&mut nn,
+                              &inputs,
+                              &desired_outputs,
+                              cost,
+                              sample_weights.as_ref().map(|w| &w[..]));
+    if !batch_cost.is_finite() {
+        return Err(NonFinite::Cost);
+    }
+
+    // the mean gradients of all layers, they are clipped together before any layer is updated
+    let mut gradients = Vec::with_capacity(nn.get_layers().len());
+    for (l, layer) in nn.get_layers().iter().enumerate() {
+        let mean = |g: &&[N]| g.iter().map(|x| *x / mini_batch_len).collect::<Vec<N>>();
+        let layer_gradients: Vec<Vec<N>> = layer.gradients().iter().map(mean).collect();
+        if !layer_gradients.iter().all(|g| all_finite(g)) {
+            return Err(NonFinite::Gradients(l + 1, layer.kind()));
         }
-        for (mut nw, dnw) in nabla_w.iter_mut().zip(delta_nabla_w.iter()) {
-            // TODO: Remove clone
-            *nw += dnw.clone();
+        gradients.push(layer_gradients);
+    }
+    let norm = clip_gradients(&mut gradients, update.clip_value, update.clip_norm);
+
+    // Update the parameters of every layer, e.g. weights and biases
+    for (l, (layer, gradients)) in nn.get_layers_mut().iter_mut().zip(gradients).enumerate() {
+        for (i, (params, nablas)) in
+            layer.parameters_mut().into_iter().zip(gradients.iter()).enumerate() {
+            match (update.optimizer, velocities.as_mut()) {
+                (Optimizer::Momentum(mu), Some(velocities)) => {
+                    let mu = N::from_f64(mu);
+                    for ((p, n), v) in
+                        params.iter_mut().zip(nablas.iter()).zip(velocities[l][i].iter_mut()) {
+                        *v = *v * mu - (*n * update.eta + *p * update.decay);
+                        *p += *v;
+                    }
+                }
+                _ => {
+                    for (p, n) in params.iter_mut().zip(nablas.iter()) {
+                        let delta = *n * update.eta + *p * update.decay;
+                        *p -= delta;
+                    }
+                }
+            }
         }
+        if !layer.parameters().iter().all(|p| all_finite(p)) {
+            return Err(NonFinite::Parameters(l + 1, layer.kind()));
+        }
+    }
+    Ok((batch_cost, norm))
+}
+
+// Feed a batch through the network like during training: apply dropout and normalize with the
+// statistics of the batch, updating the running statistics. Returns the output of the last layer.
+fn feedforward_train<N: Float>(nn: &mut Network<N>, inputs: &DMatrix<N>) -> DMatrix<N> {
+    let mut activation = inputs.clone();
+    for layer in nn.get_layers_mut() {
+        activation = layer.forward_train(&activation);
     }
+    activation
+}
+
+/// Calculates the gradients of the cost for a batch of training examples
+///
+/// Each column of `inputs` is one sample, the corresponding column of `desired_outputs` its
+/// desired output. The gradients of `cost` summed over the batch with respect to the
+/// parameters of each layer are stored in the layer, see `Layer::gradients`. If
+/// `sample_weights` are given, the cost of each sample is multiplied with its weight. Dropout and
+/// batch normalization are applied like during training, so the running statistics of batch
+/// normalized layers are updated. Returns the cost of the batch.
+pub fn backprop<N: Float>(nn: &mut Network<N>,
+                          inputs: &DMatrix<N>,
+                          desired_outputs: &DMatrix<N>,
+                          cost: Cost,
+                          sample_weights: Option<&[N]>)
+                          -> N {
+    let outputs = feedforward_train(nn, inputs);
+
+    // the cross-entropy of a sigmoid output has the derivative `a - y` with respect to the input
+    // of the sigmoid, so the two are fused: the gradient of a saturated wrong output stays near 1
+    // instead of vanishing in `a * (1 - a)`
+    let fused = cost == Cost::CrossEntropy && nn.get_layers().last().map_or(false, |layer| {
+        // only activation layers are serialized, the others would copy their parameters
+        layer.kind() == "activation" &&
+        match layer.serialize() {
+            SerializableLayer::Activation { function: Activation::Sigmoid } => true,
+            _ => false,
+        }
+    });
+    let n_backward = nn.get_layers().len() - fused as usize;
 
-    // Update the actual weights and biases
-    for (mut w, nw) in nn.get_weights_mut().iter_mut().zip(nabla_w.iter()) {
-        *w -= (eta / (mini_batch_len as f32)) * nw.clone();
+    // delta is a measurement for the error of the last layer's output
+    // compared to the desired output, we will derive the gradients from this
+    let mut delta = if fused {
+        outputs.clone() - desired_outputs
+    } else {
+        cost.derivative(&outputs, desired_outputs)
+    };
+    if let Some(weights) = sample_weights {
+        let n_outputs = delta.nrows();
+        for (column, w) in delta.as_mut_vector().chunks_mut(n_outputs).zip(weights.iter()) {
+            for d in column {
+                *d *= *w;
+            }
+        }
     }
 
-    for (mut b, nb) in nn.get_biases_mut().iter_mut().zip(nabla_b.iter()) {
-        *b -= eta / (mini_batch_len as f32) * nb.clone();
+    // now propagate the error through all layers going from last to first layer
+    for layer in nn.get_layers_mut()[..n_backward].iter_mut().rev() {
+        delta = layer.backward(&delta);
     }
+    weighted_cost(cost, &outputs, desired_outputs, sample_weights)
 }
 
+/// The `cost` of a batch in training mode, summed over all samples (columns).
+///
+/// This is the cost `backprop` calculates the gradients of. Note that it is only deterministic if
+/// dropout is disabled.
+pub fn batch_cost<N: Float>(nn: &mut Network<N>,
+                            inputs: &DMatrix<N>,
+                            desired_outputs: &DMatrix<N>,
+                            cost: Cost,
+                            sample_weights: Option<&[N]>)
+                            -> N {
+    let outputs = feedforward_train(nn, inputs);
+    weighted_cost(cost, &outputs, desired_outputs, sample_weights)
+}
 
-// Gets the desired changes in weights and biases for one training example
-fn backprop(nn: &mut Network,
-            data: &DVector<f32>,
-            desired_output: &DVector<f32>)
-            -> (Vec<DVector<f32>>, Vec<DMatrix<f32>>) {
-    use na::Outer;
-    use nn;
+// The cost of the outputs of a batch, the cost of each sample multiplied with its weight
+fn weighted_cost<N: Float>(cost: Cost,
+                           outputs: &DMatrix<N>,
+                           desired_outputs: &DMatrix<N>,
+                           sample_weights: Option<&[N]>)
+                           -> N {
+    match sample_weights {
+        None => cost.value(outputs, desired_outputs),
+        Some(weights) => {
+            let costs = cost.sample_values(outputs, desired_outputs);
+            costs.iter().zip(weights.iter()).fold(N::zero(), |sum, (c, w)| sum + *c * *w)
+        }
+    }
+}
 
-    // Hold the changes calculated for this training data
-    let mut nabla_b: Vec<DVector<f32>> = Vec::with_capacity(nn.get_biases().len());
-    for biases in nn.get_biases() {
-        nabla_b.push(DVector::new_zeros(biases.len()));
+/// How `sgd` counters imbalanced classes of single-label classification data
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassBalance<N> {
+    /// the weight of the cost of each class, `None` weights all samples equally
+    pub weights: Option<Vec<N>>,
+    /// how the training data is resampled in each epoch
+    pub resampling: Resampling,
+}
+
+impl<N> Default for ClassBalance<N> {
+    fn default() -> Self {
+        ClassBalance {
+            weights: None,
+            resampling: Resampling::None,
+        }
     }
-    let mut nabla_w: Vec<DMatrix<f32>> = Vec::with_capacity(nn.get_weights().len());
-    for weights in nn.get_weights() {
-        nabla_w.push(DMatrix::new_zeros(weights.nrows(), weights.ncols()));
+}
+
+/// Ways of resampling the training data so that all classes are equally frequent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resampling {
+    /// all samples are used once per epoch
+    None,
+    /// samples of the smaller classes are repeated until each class is as large as the largest
+    Over,
+    /// only a random part of the larger classes is used, so each class is as large as the
+    /// smallest
+    Under,
+}
+
+impl FromStr for Resampling {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Resampling::None),
+            "over" => Ok(Resampling::Over),
+            "under" => Ok(Resampling::Under),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown resampling.")),
+        }
     }
+}
 
-    // feedforward
+/// Class weights that are inversely proportional to the frequency of each class in `data`
+///
+/// The weight of class `c` is `n / (n_classes * n_c)`, so the weights of all samples add up to
+/// the number of samples `n`. Classes that do not occur get a weight of 1.
+pub fn inverse_frequency_weights<N: Float>(data: &[Data<N>], n_classes: usize) -> Vec<N> {
+    let mut counts = vec![0; n_classes];
+    for class in data.iter().filter_map(|d| d.get_class()) {
+        counts[class] += 1;
+    }
+    counts.iter()
+        .map(|&count| if count == 0 {
+            N::one()
+        } else {
+            N::from_f64(data.len() as f64 / (n_classes * count) as f64)
+        })
+        .collect()
+}
 
-    // holds activation levels all for layers (including in- and output)
-    let mut activations: Vec<DVector<f32>> = Vec::with_capacity(nn.get_layers().len());
-    // note that this pushes the input activations
-    activations.push(data.clone());
+// Draw the samples of one epoch so that all classes are equally frequent. Every sample of a class
+// is used once before any sample is repeated.
+fn resample<N: Float, R: Rng>(data: &[Data<N>],
+                              resampling: Resampling,
+                              rng: &mut R)
+                              -> Vec<Data<N>> {
+    let mut classes: Vec<Vec<&Data<N>>> = Vec::new();
+    for d in data {
+        let class = d.get_class().expect("resampling needs single-label classification data");
+        if classes.len() <= class {
+            classes.resize(class + 1, Vec::new());
+        }
+        classes[class].push(d);
+    }
+    classes.retain(|samples| !samples.is_empty());
+
+    let sizes = classes.iter().map(|samples| samples.len());
+    let size = match resampling {
+        Resampling::None => return data.to_vec(),
+        Resampling::Over => sizes.max().unwrap_or(0),
+        Resampling::Under => sizes.min().unwrap_or(0),
+    };
+    let mut resampled = Vec::with_capacity(size * classes.len());
+    for samples in &mut classes {
+        rng.shuffle(samples);
+        for i in 0..size {
+            resampled.push(samples[i % samples.len()].clone());
+        }
+    }
+    resampled
+}
 
-    // hold z for each layer where z is the input vector of the sigmoid function
-    let mut zs: Vec<DVector<f32>> = Vec::with_capacity(nn.get_layers().len());
+// Put the inputs and class vectors of the data into matrices, one sample per column
+fn batch_to_matrices<N: Float>(batch: &[Data<N>]) -> (DMatrix<N>, DMatrix<N>) {
+    let n_inputs = batch[0].get_input().len();
+    let n_outputs = batch[0].get_class_vector().len();
+    let mut inputs = DMatrix::new_zeros(n_inputs, batch.len());
+    let mut outputs = DMatrix::new_zeros(n_outputs, batch.len());
+    for (k, data) in batch.iter().enumerate() {
+        inputs.as_mut_vector()[k * n_inputs..(k + 1) * n_inputs]
+            .copy_from_slice(&data.get_input().at);
+        outputs.as_mut_vector()[k * n_outputs..(k + 1) * n_outputs]
+            .copy_from_slice(&data.get_class_vector().at);
+    }
+    (inputs, outputs)
+}
 
-    // execute feedforward
-    for (biases, weights) in nn.get_biases().iter().zip(nn.get_weights().iter()) {
-        // TODO: Remove Clone
-        zs.push(weights * &activations[activations.len() - 1] + biases.clone());
-        activations.push(nn::sigmoid(&zs[zs.len() - 1]))
+/// Print in info log how many samples of `test_data` were correctly classified by `nn`, or the
+/// metrics of multi-label or regression data
+pub fn evaluate_with_output<N: Float>(nn: &Network<N>, test_data: &Vec<Data<N>>, threshold: N) {
+    match evaluate_metrics(nn, test_data, threshold) {
+        Evaluation::Classification(correct, total) => {
+            info!("{}/{} correctly classified", correct, total)
+        }
+        evaluation => info!("{} on {} samples", evaluation, test_data.len()),
     }
+}
 
+/// The result of evaluating a network on test data, the metrics depend on the task of the data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evaluation {
+    /// the number of correctly classified samples and the number of samples
+    Classification(u32, usize),
+    /// the metrics of multi-label data
+    MultiLabel(MultiLabelMetrics),
+    /// the metrics of data with real-valued targets
+    Regression(RegressionMetrics),
+}
 
-    // backward pass
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Evaluation::Classification(correct, total) => write!(f, "{}/{}", correct, total),
+            Evaluation::MultiLabel(ref metrics) => write!(f, "{}", metrics),
+            Evaluation::Regression(ref metrics) => write!(f, "{}", metrics),
+        }
+    }
+}
 
-    // calculate values for output layer
-    // delta is a measurement for the error of the last layer's output
-    // compared to the desired output, we will derive the nabla values from this
-    let mut delta = cost_derivative(&activations[activations.len() - 1], desired_output) *
-                    sigmoid_prime(&zs[zs.len() - 1]);
-    // need to store these because ownership issues
-    let nabla_b_len = nabla_b.len();
-    let nabla_w_len = nabla_w.len();
-    // TODO: Remove clone
-    nabla_b[nabla_b_len - 1] = delta.clone();
-    nabla_w[nabla_w_len - 1] = (&delta).outer(&activations[activations.len() - 2]);
+impl Evaluation {
+    /// The fraction of correctly classified samples, for multi-label data the fraction of
+    /// samples with all labels correct. `None` for regression.
+    pub fn accuracy(&self) -> Option<f64> {
+        match *self {
+            Evaluation::Classification(correct, total) => Some(correct as f64 / total as f64),
+            Evaluation::MultiLabel(ref metrics) => Some(metrics.subset_accuracy),
+            Evaluation::Regression(_) => None,
+        }
+    }
+}
+
+/// The mean `cost` of the outputs of `nn` per sample of `test_data`, without dropout
+pub fn mean_cost<N: Float>(nn: &Network<N>, test_data: &[Data<N>], cost: Cost) -> N {
+    let mut sum = N::zero();
+    for_each_output(nn, test_data, |output, data| {
+        let output = DMatrix::from_column_vector(output.len(), 1, output);
+        let desired = DMatrix::from_column_vector(output.nrows(), 1, &data.get_class_vector().at);
+        sum += cost.value(&output, &desired);
+    });
+    sum / N::from_f64(test_data.len() as f64)
+}
 
-    // now calculate the values for all previous layers going from second to last to first layer
-    // note: get_weiths is used for measurement of number of layers with biases and weights to
-    // make sure the input layer is ignored
-    for l in 2..nn.get_weights().len() + 1 {
-        let z = &zs[zs.len() - l];
-        let sp = sigmoid_prime(&z);
-        delta = (&nn.get_weights()[nn.get_weights().len() - l + 1].transpose() * &delta) * sp;
-        nabla_b[nabla_b_len - l] = delta.clone();
-        nabla_w[nabla_w_len - l] = (&delta).outer(&activations[activations.len() - l - 1]);
+/// Evaluate `nn` on `test_data` with the metrics of the task of the data
+///
+/// For multi-label data, outputs above `threshold` count as predicted labels.
+pub fn evaluate_metrics<N: Float>(nn: &Network<N>,
+                                  test_data: &Vec<Data<N>>,
+                                  threshold: N)
+                                  -> Evaluation {
+    match test_data.first().map_or(Task::Classification, |d| d.get_task()) {
+        Task::Classification => {
+            Evaluation::Classification(evaluate(nn, test_data), test_data.len())
+        }
+        Task::MultiLabel => Evaluation::MultiLabel(multi_label_metrics(nn, test_data, threshold)),
+        Task::Regression => Evaluation::Regression(regression_metrics(nn, test_data)),
     }
-    (nabla_b, nabla_w)
 }
 
-// Derivative of the cost function
-fn cost_derivative(output_activations: &DVector<f32>,
-                   desired_output: &DVector<f32>)
-                   -> DVector<f32> {
-    // easy, derivative of quadratic cost function is:
-    // TODO: Get rid of clone
-    output_activations.clone() - desired_output.clone()
+/// The quality of the predictions of a network on multi-label data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MultiLabelMetrics {
+    /// fraction of all labels of all samples that were predicted wrongly
+    pub hamming_loss: f64,
+    /// fraction of samples whose labels were all predicted correctly
+    pub subset_accuracy: f64,
+    /// F1 score of the true and false positives and negatives of all labels together
+    pub micro_f1: f64,
+    /// mean of the F1 scores of the single labels. A label that is neither present nor
+    /// predicted in any sample has a score of 1.
+    pub macro_f1: f64,
+}
+
+impl fmt::Display for MultiLabelMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Hamming loss {:.4}, subset accuracy {:.4}, micro F1 {:.4}, macro F1 {:.4}",
+               self.hamming_loss,
+               self.subset_accuracy,
+               self.micro_f1,
+               self.macro_f1)
+    }
 }
 
+/// Calculate the multi-label metrics of `nn` on `test_data`, outputs above `threshold` count as
+/// predicted labels
+pub fn multi_label_metrics<N: Float>(nn: &Network<N>,
+                                     test_data: &[Data<N>],
+                                     threshold: N)
+                                     -> MultiLabelMetrics {
+    let n_outputs = nn.output_size();
 
-// Derivative of the sigmoid function
-fn sigmoid_prime(z: &DVector<f32>) -> DVector<f32> {
-    use nn;
-    // Derivative of sigmoid function, ask wolfram alpha if you don't believe me
-    nn::sigmoid(z) * (1.0f32 - nn::sigmoid(z))
+    // true positives, false positives and false negatives of each label
+    let mut counts = vec![(0, 0, 0); n_outputs];
+    let (mut wrong, mut all_correct) = (0, 0);
+    for_each_output(nn, test_data, |output, data| {
+        let labels = data.get_class_vector().at.iter();
+        let mut correct = true;
+        for ((&a, &y), count) in output.iter().zip(labels).zip(counts.iter_mut()) {
+            let (predicted, present) = (a > threshold, y > N::from_f64(0.5));
+            match (predicted, present) {
+                (true, true) => count.0 += 1,
+                (true, false) => count.1 += 1,
+                (false, true) => count.2 += 1,
+                (false, false) => {}
+            }
+            if predicted != present {
+                wrong += 1;
+                correct = false;
+            }
+        }
+        if correct {
+            all_correct += 1;
+        }
+    });
+
+    let f1 = |(tp, fp, fn_): (usize, usize, usize)| if tp + fp + fn_ == 0 {
+        1.0
+    } else {
+        2.0 * tp as f64 / (2 * tp + fp + fn_) as f64
+    };
+    let total = counts.iter().fold((0, 0, 0), |t, c| (t.0 + c.0, t.1 + c.1, t.2 + c.2));
+    MultiLabelMetrics {
+        hamming_loss: wrong as f64 / (test_data.len() * n_outputs) as f64,
+        subset_accuracy: all_correct as f64 / test_data.len() as f64,
+        micro_f1: f1(total),
+        macro_f1: counts.iter().map(|c| f1(*c)).sum::<f64>() / n_outputs as f64,
+    }
 }
 
-/// Print in info log how many samples of `test_data` were correctly classified by `nn`
-pub fn evaluate_with_output(nn: &Network, test_data: &Vec<Data>) {
-    info!("{}/{} correctly classified",
-          evaluate(&nn, &test_data),
-          test_data.len());
+/// The errors of a network on data with real-valued targets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionMetrics {
+    /// root mean squared error of all outputs
+    pub rmse: f64,
+    /// mean absolute error of all outputs
+    pub mae: f64,
+    /// coefficient of determination: 1 minus the squared error relative to the variance of the
+    /// targets around their mean (per output). 1 is a perfect fit, 0 is no better than
+    /// predicting the mean.
+    pub r2: f64,
 }
 
+impl fmt::Display for RegressionMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RMSE {:.4}, MAE {:.4}, R² {:.4}", self.rmse, self.mae, self.r2)
+    }
+}
+
+/// Calculate the regression metrics of `nn` on `test_data`
+pub fn regression_metrics<N: Float>(nn: &Network<N>,
+                                    test_data: &[Data<N>])
+                                    -> RegressionMetrics {
+    let n_outputs = nn.output_size();
+
+    // the mean of each target, for the variance in R²
+    let mut means = vec![0.0; n_outputs];
+    for data in test_data {
+        for (m, &y) in means.iter_mut().zip(data.get_class_vector().at.iter()) {
+            *m += y.to_f64() / test_data.len() as f64;
+        }
+    }
+
+    let (mut squared, mut absolute, mut variance) = (0.0, 0.0, 0.0);
+    for_each_output(nn, test_data, |output, data| {
+        let targets = data.get_class_vector().at.iter();
+        for ((&a, &y), m) in output.iter().zip(targets).zip(means.iter()) {
+            let r = a.to_f64() - y.to_f64();
+            squared += r * r;
+            absolute += r.abs();
+            variance += (y.to_f64() - m) * (y.to_f64() - m);
+        }
+    });
+
+    let n = (test_data.len() * n_outputs) as f64;
+    RegressionMetrics {
+        rmse: (squared / n).sqrt(),
+        mae: absolute / n,
+        r2: if variance > 0.0 {
+            1.0 - squared / variance
+        } else {
+            0.0
+        },
+    }
+}
+
+/// How often the samples of each class were classified as each class
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfusionMatrix {
+    /// `counts[actual][predicted]` is the number of samples of class `actual` that were
+    /// classified as `predicted`
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    /// The number of samples of class `c`
+    pub fn support(&self, c: usize) -> usize {
+        self.counts[c].iter().sum()
+    }
+
+    /// The fraction of samples classified as `c` that belong to class `c`, 0 if no sample was
+    /// classified as `c`
+    pub fn precision(&self, c: usize) -> f64 {
+        let predicted: usize = self.counts.iter().map(|row| row[c]).sum();
+        ratio(self.counts[c][c], predicted)
+    }
+
+    /// The fraction of samples of class `c` that were classified as `c`, 0 if there are none
+    pub fn recall(&self, c: usize) -> f64 {
+        ratio(self.counts[c][c], self.support(c))
+    }
+
+    /// The harmonic mean of precision and recall of class `c`
+    pub fn f1(&self, c: usize) -> f64 {
+        let (precision, recall) = (self.precision(c), self.recall(c));
+        if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        }
+    }
+}
+
+// `part / total`, 0 if `total` is 0
+fn ratio(part: usize, total: usize) -> f64 {
+    if total > 0 {
+        part as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+/// Calculate the confusion matrix of `nn` on single-label classification data, the predicted
+/// class is the output with the highest value
+pub fn confusion_matrix<N: Float>(nn: &Network<N>, test_data: &[Data<N>]) -> ConfusionMatrix {
+    let n_classes = nn.output_size();
+    let mut counts = vec![vec![0; n_classes]; n_classes];
+    for_each_output(nn, test_data, |output, data| {
+        counts[find_max(&data.get_class_vector().at)][find_max(output)] += 1;
+    });
+    ConfusionMatrix { counts: counts }
+}
+
+/// A sample of single-label classification data that was assigned the wrong class
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Misclassified {
+    /// position of the sample in the data
+    pub index: usize,
+    /// the class of the sample
+    pub actual: usize,
+    /// the class with the highest output
+    pub predicted: usize,
+    /// how much higher the output of the predicted class is than that of the actual class
+    pub margin: f64,
+}
+
+/// The (at most) `count` misclassified samples of `test_data` with the largest margin between
+/// the predicted and the actual class, largest margin first
+pub fn worst_misclassified<N: Float>(nn: &Network<N>,
+                                     test_data: &[Data<N>],
+                                     count: usize)
+                                     -> Vec<Misclassified> {
+    let mut misclassified = Vec::new();
+    let mut index = 0;
+    for_each_output(nn, test_data, |output, data| {
+        let (actual, predicted) = (find_max(&data.get_class_vector().at), find_max(output));
+        if actual != predicted {
+            misclassified.push(Misclassified {
+                index: index,
+                actual: actual,
+                predicted: predicted,
+                margin: (output[predicted] - output[actual]).to_f64(),
+            });
+        }
+        index += 1;
+    });
+    misclassified.sort_by(|a, b| b.margin.partial_cmp(&a.margin).unwrap_or(Ordering::Equal));
+    misclassified.truncate(count);
+    misclassified
+}
 
 // compares the output of the Network with the test_data
 // returns the number of correct results
-fn evaluate(nn: &Network, test_data: &Vec<Data>) -> u32 {
+fn evaluate<N: Float>(nn: &Network<N>, test_data: &Vec<Data<N>>) -> u32 {
     // corr holds number of correctly recognised training data sets
     let mut corr = 0;
-    // iterate over test data input vectors and test data class vectors
-    for (x, y) in test_data.iter()
-        .map(|x| x.get_input())
-        .zip(test_data.iter()
-            .map(|x| x.get_class_vector())) {
-        // TODO: Shitty performance yo
-        if find_max(&nn.feedforward(x)) == find_max(&y) {
+    for_each_output(nn, test_data, |output, data| {
+        if find_max(output) == find_max(&data.get_class_vector().at) {
             corr += 1;
         }
-    }
+    });
     corr
 }
 
+// Feed `test_data` through the network in batches and call `f` with the output of each sample
+fn for_each_output<N, F>(nn: &Network<N>, test_data: &[Data<N>], mut f: F)
+    where N: Float,
+          F: FnMut(&[N], &Data<N>)
+{
+    let n_inputs = nn.input_size();
+    let n_outputs = nn.output_size();
+
+    // the buffers are reused for all batches, so evaluation does not allocate per sample
+    let mut workspace = Workspace::new();
+    let mut inputs: DMatrix<N> = DMatrix::new_zeros(n_inputs, EVAL_BATCH_SIZE);
+
+    for batch in test_data.chunks(EVAL_BATCH_SIZE) {
+        // only the last batch might be smaller
+        if batch.len() != inputs.ncols() {
+            inputs = DMatrix::new_zeros(n_inputs, batch.len());
+        }
+        // each sample is a column of the input matrix
+        for (column, data) in inputs.as_mut_vector().chunks_mut(n_inputs).zip(batch.iter()) {
+            column.copy_from_slice(&data.get_input().at);
+        }
+
+        let outputs = nn.feedforward_batch_into(&inputs, &mut workspace);
+        for (output, data) in outputs.as_vector().chunks(n_outputs).zip(batch.iter()) {
+            f(output, data);
+        }
+    }
+}
+
+
+// returns the index of the highest value in the slice, NaN counts as lower than any number
+fn find_max<N: Float>(vec: &[N]) -> usize {
+    let mut max = 0;
+    for (i, x) in vec.iter().enumerate() {
+        if vec[max].is_nan() || *x >= vec[max] {
+            max = i;
+        }
+    }
+    max
+}
+
+
+#[test]
+fn test_multi_label_metrics() {
+    use na::{DMatrix, DVector};
+    use nn::Dense;
 
-// returns the index of the highest value in the vector
-fn find_max(vec: &DVector<f32>) -> usize {
-    vec.iter()
+    // a network that passes its input through, so the inputs are the predictions
+    let identity = Dense::from_parts(DMatrix::from_fn(3, 3, |i, j| if i == j { 1.0 } else { 0.0 }),
+                                     DVector::new_zeros(3));
+    let nn = Network::from_layers(3, vec![Box::new(identity)]).unwrap();
+    let sample = |output: [f64; 3], labels: [f64; 3]| {
+        Data::new_multi_label(DVector::from_slice(3, &output), DVector::from_slice(3, &labels))
+    };
+    let data = vec![sample([0.9, 0.2, 0.7], [1.0, 0.0, 1.0]),
+                    sample([0.6, 0.4, 0.1], [1.0, 1.0, 0.0]),
+                    sample([0.3, 0.1, 0.8], [0.0, 0.0, 0.0])];
+    let metrics = multi_label_metrics(&nn, &data, 0.5);
+    assert_eq!(metrics.hamming_loss, 2.0 / 9.0);
+    assert_eq!(metrics.subset_accuracy, 1.0 / 3.0);
+    // 3 true positives, 1 false positive and 1 false negative
+    assert_eq!(metrics.micro_f1, 6.0 / 8.0);
+    assert_eq!(metrics.macro_f1, (1.0 + 0.0 + 2.0 / 3.0) / 3.0);
+    // with a lower threshold the second label is predicted as well
+    assert_eq!(multi_label_metrics(&nn, &data, 0.35).subset_accuracy, 2.0 / 3.0);
+}
+
+#[test]
+fn test_saturated_cross_entropy() {
+    use na::{DMatrix, DVector};
+    use nn::{ActivationLayer, Dense};
+
+    // the sigmoid output is 1 in f32, although the label is 0
+    let dense = Dense::from_parts(DMatrix::from_element(1, 1, 100.0f32), DVector::new_zeros(1));
+    let sigmoid = ActivationLayer::new(Activation::Sigmoid);
+    let mut nn = Network::from_layers(1, vec![Box::new(dense), Box::new(sigmoid)]).unwrap();
+    let (input, label) = (DMatrix::from_element(1, 1, 1.0), DMatrix::from_element(1, 1, 0.0));
+    let cost = backprop(&mut nn, &input, &label, Cost::CrossEntropy, None);
+    assert!(cost.is_finite());
+    // the gradient of the bias is `a - y`
+    let gradient = nn.get_layers()[0].gradients()[1][0];
+    assert!((gradient - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_class_balance() {
+    use na::DVector;
+    use rand;
+
+    // 6 samples of class 0, 2 of class 1 and none of class 2
+    let data: Vec<Data<f64>> = [0, 0, 0, 1, 0, 0, 1, 0]
+        .iter()
         .enumerate()
-        .max_by(|tuple1, tuple2| tuple1.1.partial_cmp(tuple2.1).unwrap())
-        .unwrap()
-        .0
+        .map(|(i, &c)| Data::new(DVector::from_element(1, i as f64), c, 3))
+        .collect();
+    assert_eq!(inverse_frequency_weights(&data, 3), vec![8.0 / 18.0, 8.0 / 6.0, 1.0]);
+
+    let mut rng = rand::thread_rng();
+    let count = |data: &[Data<f64>], class| {
+        data.iter().filter(|d| d.get_class() == Some(class)).count()
+    };
+    let over = resample(&data, Resampling::Over, &mut rng);
+    assert_eq!((count(&over, 0), count(&over, 1)), (6, 6));
+    // every sample is used before any is repeated
+    for i in 0..8 {
+        assert!(over.iter().any(|d| d.get_input()[0] == i as f64));
+    }
+    let under = resample(&data, Resampling::Under, &mut rng);
+    assert_eq!((count(&under, 0), count(&under, 1)), (2, 2));
+}
+
+#[test]
+fn test_confusion_matrix() {
+    use na::{DMatrix, DVector};
+    use nn::Dense;
+
+    // a network that passes its input through, so the inputs are the outputs
+    let identity = Dense::from_parts(DMatrix::from_fn(2, 2, |i, j| if i == j { 1.0 } else { 0.0 }),
+                                     DVector::new_zeros(2));
+    let nn = Network::from_layers(2, vec![Box::new(identity)]).unwrap();
+    let sample = |output: [f64; 2], class| Data::new(DVector::from_slice(2, &output), class, 2);
+    let data = vec![sample([0.9, 0.1], 0),
+                    sample([0.4, 0.6], 0),
+                    sample([0.2, 0.8], 1),
+                    sample([0.3, 0.7], 0)];
+    let confusion = confusion_matrix(&nn, &data);
+    assert_eq!(confusion.counts, vec![vec![1, 2], vec![0, 1]]);
+    assert_eq!((confusion.support(0), confusion.recall(0)), (3, 1.0 / 3.0));
+    assert_eq!((confusion.precision(1), confusion.f1(1)), (1.0 / 3.0, 0.5));
+
+    let worst = worst_misclassified(&nn, &data, 1);
+    assert_eq!(worst.len(), 1);
+    assert_eq!((worst[0].index, worst[0].actual, worst[0].predicted), (3, 0, 1));
+}
+
+// Eight points of two alternating classes, small enough for a single mini batch
+#[cfg(test)]
+fn two_classes() -> Vec<Data<f64>> {
+    use na::DVector;
+
+    (0..8)
+        .map(|i| Data::new(DVector::from_slice(2, &[i as f64 / 8.0, 1.0]), (i % 2) as u8, 2))
+        .collect()
+}
+
+#[test]
+fn test_trainer() {
+    use nn::{NetworkBuilder, Topology};
+
+    assert_eq!(Schedule::Step { every: 2, factor: 0.5 }.learning_rate(1.0, 3), 0.5);
+    assert_eq!(Schedule::Exponential(0.5).learning_rate(1.0, 2), 0.25);
+
+    // a single mini batch per epoch, so the updates do not depend on the shuffling
+    let data = two_classes();
+    let nn = NetworkBuilder::new(Topology::dense(&[2, 3, 2])).seed(5).build().unwrap();
+    let trainer = || {
+        Trainer::new()
+            .epochs(4)
+            .batch_size(8)
+            .learning_rate(0.5)
+            .schedule(Schedule::Exponential(0.5))
+    };
+    let train = |mut trainer: Trainer<f64>| -> (Vec<f64>, TrainingHistory) {
+        let (nn, history) = trainer.train(nn.clone(), data.clone(), &Vec::new());
+        (nn.get_layers().iter().flat_map(|l| l.parameters().concat()).collect(), history)
+    };
+    let norm = |params: &[f64]| params.iter().map(|p| p * p).sum::<f64>();
+
+    let (plain, history) = train(trainer());
+    let rates: Vec<f64> = history.epochs.iter().map(|e| e.learning_rate).collect();
+    assert_eq!(rates, vec![0.5, 0.25, 0.125, 0.0625]);
+
+    // L2 regularization shrinks the parameters
+    let (regularized, _) = train(trainer().regularization(Regularization::L2(10.0)));
+    assert!(norm(&regularized) < norm(&plain));
+
+    // momentum only differs from plain gradient descent after the first update, up to the
+    // rounding of the gradients, which are summed in the order of the shuffled samples
+    let momentum = || trainer().optimizer(Optimizer::Momentum(0.9));
+    let first_update = train(trainer().epochs(1)).0;
+    assert!(train(momentum().epochs(1)).0.iter().zip(&first_update).all(|(m, p)| {
+        (m - p).abs() < 1e-12
+    }));
+    assert!(train(momentum()).0 != plain);
+
+    // without training data there are no epochs and no NaN costs
+    let (untrained, history) = trainer().train(nn.clone(), Vec::new(), &data);
+    assert!(history.epochs.is_empty());
+    assert_eq!(untrained.get_layers()[0].parameters(), nn.get_layers()[0].parameters());
+}
+
+#[test]
+#[should_panic(expected = "mini batch size")]
+fn test_zero_batch_size() {
+    Trainer::<f64>::new().batch_size(0);
+}
+
+#[test]
+fn test_trainer_callbacks() {
+    use na::DVector;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // records the hooks it is called with and stops in the second batch of the second epoch
+    #[derive(Debug)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+    impl Callback<f64> for Recorder {
+        fn on_train_begin(&mut self, _nn: &Network<f64>) {
+            self.0.borrow_mut().push("begin".to_string());
+        }
+        fn on_epoch_begin(&mut self, epoch: u32, _nn: &Network<f64>) {
+            self.0.borrow_mut().push(format!("epoch {}", epoch));
+        }
+        fn on_batch_end(&mut self, epoch: u32, batch: usize, _cost: f64, _nn: &Network<f64>)
+                        -> Control {
+            self.0.borrow_mut().push(format!("batch {}", batch));
+            if epoch == 2 && batch == 1 {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        }
+        fn on_epoch_end(&mut self, record: &EpochRecord, _nn: &Network<f64>) -> Control {
+            self.0.borrow_mut().push(format!("end {}", record.epoch));
+            Control::Continue
+        }
+        fn on_train_end(&mut self, history: &TrainingHistory, _nn: &Network<f64>) {
+            self.0.borrow_mut().push(format!("done {}", history.epochs.len()));
+        }
+    }
+
+    let data: Vec<Data<f64>> = (0..6)
+        .map(|i| Data::new(DVector::from_element(2, i as f64 / 6.0), (i % 2) as u8, 2))
+        .collect();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let (_, history) = Trainer::new()
+        .epochs(5)
+        .batch_size(2)
+        .callback(Recorder(events.clone()))
+        .train(Network::new(&[2, 2]).unwrap(), data, &Vec::new());
+    assert_eq!(history.epochs.len(), 2);
+    assert_eq!(*events.borrow(),
+               ["begin", "epoch 1", "batch 0", "batch 1", "batch 2", "end 1", "epoch 2",
+                "batch 0", "batch 1", "end 2", "done 2"]);
+}
+
+#[test]
+fn test_divergence() {
+    use nn::{Activation, NetworkBuilder, Topology};
+
+    // with linear outputs and a far too high learning rate the weights grow until they overflow
+    let data = two_classes();
+    let nn = NetworkBuilder::new(Topology::dense(&[2, 3, 2]))
+        .output(Activation::Linear)
+        .seed(5)
+        .build()
+        .unwrap();
+    let trainer = || Trainer::new().epochs(10).batch_size(4).learning_rate(1e10);
+    let finite = |nn: &Network<f64>| nn.get_layers().iter().all(|l| {
+        l.parameters().iter().all(|p| all_finite(p))
+    });
+
+    // aborting keeps the network of the last complete epoch
+    let (aborted, history) = trainer().train(nn.clone(), data.clone(), &Vec::new());
+    assert!(history.epochs.len() < 10);
+    assert!(finite(&aborted));
+
+    // rolling back finishes the training with a lower learning rate
+    let rollback = Divergence::Rollback {
+        factor: 1e-10,
+        retries: 1,
+    };
+    let (rolled_back, history) =
+        trainer().divergence(rollback).train(nn.clone(), data.clone(), &Vec::new());
+    assert_eq!(history.epochs.len(), 10);
+    assert_eq!(history.epochs[9].learning_rate, 1.0);
+    assert!(finite(&rolled_back));
+
+    assert_eq!("rollback:0.1".parse::<Divergence>().unwrap(),
+               Divergence::Rollback {
+                   factor: 0.1,
+                   retries: 5,
+               });
+    assert!("rollback:2".parse::<Divergence>().is_err());
+    assert_eq!(find_max(&[0.1, ::std::f64::NAN, 0.3, 0.2]), 2);
+}
+
+#[test]
+fn test_gradient_clipping() {
+    use nn::{NetworkBuilder, Topology};
+
+    let gradients: Vec<Vec<Vec<f64>>> = vec![vec![vec![3.0, -4.0]], vec![vec![0.0]]];
+    let clipped = |clip_value, clip_norm| {
+        let mut gradients = gradients.clone();
+        let norm = clip_gradients(&mut gradients, clip_value, clip_norm);
+        (norm, gradients)
+    };
+    assert_eq!(clipped(None, None), (5.0, gradients.clone()));
+    let close = |a: &[Vec<Vec<f64>>], b: &[f64]| {
+        a.concat().concat().iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-12)
+    };
+    assert!(close(&clipped(None, Some(1.0)).1, &[0.6, -0.8, 0.0]));
+    assert_eq!(clipped(Some(2.0), None), (5.0, vec![vec![vec![2.0, -2.0]], vec![vec![0.0]]]));
+    let (norm, both) = clipped(Some(2.0), Some(1.0));
+    assert_eq!(norm, 5.0);
+    assert!(close(&both, &[0.5f64.sqrt(), -0.5f64.sqrt(), 0.0]));
+    // the norm of large gradients does not overflow
+    let norm: f64 = global_norm(&[vec![vec![3e200, 4e200]]]);
+    assert!((norm / 5e200 - 1.0).abs() < 1e-12);
+
+    // the norms are recorded, and clipping bounds the step of every mini batch
+    let data = two_classes();
+    let nn = NetworkBuilder::new(Topology::dense(&[2, 3, 2])).seed(5).build().unwrap();
+    let params = |nn: &Network<f64>| -> Vec<f64> {
+        nn.get_layers().iter().flat_map(|l| l.parameters().concat()).collect()
+    };
+    let trainer = || Trainer::new().epochs(1).batch_size(8).learning_rate(1.0);
+    let (_, history) = trainer().train(nn.clone(), data.clone(), &Vec::new());
+    let record = &history.epochs[0];
+    assert!(record.gradient_norm > 0.0 && record.gradient_norm == record.max_gradient_norm);
+    let (clipped, history) =
+        trainer().clip_norm(1e-3).train(nn.clone(), data.clone(), &Vec::new());
+    assert!((history.epochs[0].gradient_norm - record.gradient_norm).abs() < 1e-12);
+    let step: Vec<f64> = params(&clipped).iter().zip(params(&nn)).map(|(a, b)| a - b).collect();
+    assert!(global_norm(&[vec![step]]) <= 1e-3 + 1e-12);
 }
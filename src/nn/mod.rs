@@ -1,16 +1,37 @@
+//! The neural network: its layers, training by stochastic gradient descent and saving it to
+//! files
+
 extern crate serde_json;
 
+pub mod augment;
+pub mod builder;
+pub mod callbacks;
+pub mod cost;
+pub mod float;
+pub mod gradcheck;
+pub mod history;
+pub mod layers;
 pub mod learning;
+pub mod topology;
+
+pub use self::augment::Augmentation;
+pub use self::builder::NetworkBuilder;
+pub use self::callbacks::Callback;
+pub use self::cost::Cost;
+pub use self::float::{Float, Precision};
+pub use self::history::TrainingHistory;
+pub use self::layers::{Activation, ActivationLayer, BatchNorm, Conv2D, Dense, Dropout,
+                       Initializer, Layer, Pool2D, Recurrent};
+pub use self::learning::Trainer;
+pub use self::topology::{LayerSpec, Shape, Topology, TopologyError};
 
-use input::util;
 use na::{DMatrix, DVector, IterableMut};
-use rand;
-use rand::distributions::normal::StandardNormal;
-use rand::Rng;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
 use std::str;
-use structs::serialnet::SerializableNet;
+use structs::Data;
+use structs::serialnet::{SavedNet, SerializableNet};
 
 
 /// Artificial Neural Network
@@ -19,189 +40,337 @@ use structs::serialnet::SerializableNet;
 /// feedforward and backpropagation. It uses Stochastic Gradient Descent(SGD)
 /// and a sigmoid activation function.
 ///
+/// The network is generic over the floating point type `N` it is computed in, see `Float`.
+///
 /// [Source](http://neuralnetworksanddeeplearning.com/chap1.html)
 ///
 /// # Example
 ///
 /// ```rust
+/// use rustle_my_net::nn::Network;
+///
 /// // This will create a new ANN with the following topology:
 /// // 3 "neurons" in the input layer
 /// // 5 "neurons" in the first hidden layer
 /// // 3 "neurons" in the second hidden layer
 /// // 2 "neurons" in the output layer
-/// let nnet = Network::new(vec![3, 5, 2]);
+/// let nnet: Network = Network::new(&[3, 5, 3, 2]).unwrap();
 /// ```
 #[derive(Debug, Clone)]
-pub struct Network {
-    /// a Vec outlining the topology of the ANN
-    /// the first entry corresponds to the inputlayer,
-    /// the intermediate entries correspond to the hidden layers
-    /// and the last entry corresponds to the outputlayer.
-    layers: Vec<u32>,
-    /// a Vec that contains the weights of the respective layer
-    weights: Vec<DMatrix<f32>>,
-    /// a Vec cointaining the biases of the respective layer
-    biases: Vec<DVector<f32>>,
+pub struct Network<N: Float = f32> {
+    /// number of inputs per sample
+    input_size: usize,
+    /// the layers the input is fed through, in order
+    layers: Vec<Box<Layer<N>>>,
+    /// the number of outputs of each layer
+    sizes: Vec<usize>,
 }
 
 
-impl Network {
+impl<N: Float> Network<N> {
     /// build a new Network with a given topology
     ///
     /// The `sizes` array specifies the size of each layer. For example,
     /// the array `[4, 5, 3]` will result in a network with 4 input layer
     /// neurons, 5 neurons in the hidden layer and 3 neurons in the output layer.
-    pub fn new(sizes: &[u32]) -> Result<Network, &'static str> {
+    /// Each layer except the input layer is a `Dense` layer followed by a sigmoid activation.
+    pub fn new(sizes: &[u32]) -> Result<Network<N>, &'static str> {
         // At least one input and one output layer is needed for the code to work
         if sizes.len() < 2 {
-            return Err("at least three layers required");
+            return Err("at least two layers required: an input and an output layer");
         }
+        Network::from_topology(&Topology::dense(sizes),
+                               &vec![N::one(); sizes.len() - 1],
+                               false,
+                               Activation::Sigmoid)
+    }
 
-        // Store the weights and biases in lists
-        // We will not need weights or biases for input layer, so ignore that (hence -1)
-        let mut weights = Vec::with_capacity(sizes.len() - 1);
-        let mut biases = Vec::with_capacity(sizes.len() - 1);
-
-        // will be needed to initialise weights and biases
-        let mut rng = rand::thread_rng();
-
-        // Biases and weights will be initialised randomly from a standard normal destribution.
-        // Choosing them so that they are around 0 and very likely between -4 and 4 will speed up
-        // learning because the sigmoid neurons will not as easily get saturated and saturated
-        // neurons cause a slower learning progress.
-        // Skip one because we need one weight matrix less than we have layers
-        for (i, layer) in sizes.iter().enumerate().skip(1) {
-            // initialize weight matrices
-            weights.push(DMatrix::from_fn(*layer as usize, sizes[i - 1] as usize, |_, _| {
-                let StandardNormal(x) = rng.gen();
-                x as f32
-            }));
-
-            // initialize biases
-            biases.push(DVector::from_fn(*layer as usize, |_| {
-                let StandardNormal(x) = rng.gen();
-                x as f32
-            }));
-        }
+    /// build a new Network with the layers described by `topology`
+    ///
+    /// Hidden dense and convolutional layers are followed by a sigmoid activation, the output
+    /// layer by the `output` activation. `keep_probs` needs one dropout keep probability for the
+    /// input and each hidden layer. If `batch_norm` is set, the weighted inputs of all hidden
+    /// dense and convolutional layers are batch normalized. See `NetworkBuilder` for more
+    /// settings.
+    pub fn from_topology(topology: &Topology,
+                         keep_probs: &[N],
+                         batch_norm: bool,
+                         output: Activation)
+                         -> Result<Network<N>, &'static str> {
+        NetworkBuilder::new(topology.clone())
+            .keep_probs(keep_probs)
+            .batch_norm(batch_norm)
+            .output(output)
+            .build()
+    }
 
+    /// build a Network from a sequence of layers
+    ///
+    /// Returns an error if a layer cannot take the outputs of the layer before as input.
+    pub fn from_layers(input_size: usize,
+                       layers: Vec<Box<Layer<N>>>)
+                       -> Result<Network<N>, &'static str> {
+        if layers.is_empty() {
+            return Err("at least one layer required");
+        }
+        let mut sizes = Vec::with_capacity(layers.len());
+        let mut size = input_size;
+        for layer in &layers {
+            size = layer.output_size(size)?;
+            sizes.push(size);
+        }
         Ok(Network {
-            layers: sizes.to_vec(),
-            weights: weights,
-            biases: biases,
+            input_size: input_size,
+            layers: layers,
+            sizes: sizes,
         })
     }
 
     /// Feed input through network, return output layer activation level
-    pub fn feedforward(&self, a: &DVector<f32>) -> DVector<f32> {
-        let mut act = a.clone();
-        for (weight, bias) in self.weights.iter().zip(self.biases.clone().into_iter()) {
-            act = sigmoid(&(weight * act + bias));
+    pub fn feedforward(&self, a: &DVector<N>) -> DVector<N> {
+        let input = DMatrix::from_column_vector(a.len(), 1, &a.at);
+        DVector { at: self.feedforward_batch(&input).into_vector() }
+    }
+
+    /// Feed a batch of inputs through the network at once.
+    ///
+    /// Each column of `inputs` is one input vector, the returned matrix holds the output layer
+    /// activation level of each sample in the corresponding column.
+    pub fn feedforward_batch(&self, inputs: &DMatrix<N>) -> DMatrix<N> {
+        let mut workspace = Workspace::new();
+        self.feedforward_batch_into(inputs, &mut workspace);
+        workspace.activations.pop().unwrap()
+    }
+
+    /// Feed a batch of inputs through the network, reusing the buffers of `workspace`.
+    ///
+    /// Works like `feedforward_batch`, but the outputs of all layers are written into
+    /// `workspace`, so no memory is allocated as long as the batch size does not change between
+    /// calls. Returns a reference to the output layer activations.
+    pub fn feedforward_batch_into<'a>(&self,
+                                      inputs: &DMatrix<N>,
+                                      workspace: &'a mut Workspace<N>)
+                                      -> &'a DMatrix<N> {
+        workspace.prepare(&self.sizes, inputs.ncols());
+        for (l, layer) in self.layers.iter().enumerate() {
+            // the previous layer's outputs are the input of the current layer
+            let (done, todo) = workspace.activations.split_at_mut(l);
+            let input = if l == 0 { inputs } else { &done[l - 1] };
+            layer.forward_into(input, &mut todo[0]);
         }
-        act
+        &workspace.activations[workspace.activations.len() - 1]
     }
 
-    /// return the layers used to initialize the ANN
-    pub fn get_layers(&self) -> &[u32] {
-        &self.layers
+    /// return the number of inputs per sample
+    pub fn input_size(&self) -> usize {
+        self.input_size
     }
 
-    /// return a vector of the weight matrices of the ANN
-    pub fn get_weights(&self) -> &[DMatrix<f32>] {
-        &self.weights
+    /// return the number of outputs per sample
+    pub fn output_size(&self) -> usize {
+        self.sizes[self.sizes.len() - 1]
     }
 
-    /// return a mutable vector of the weight matrices of the ANN
-    pub fn get_weights_mut(&mut self) -> &mut Vec<DMatrix<f32>> {
-        &mut self.weights
+    /// Check that every sample of `data` fits the input and the output layer of the network,
+    /// which is needed before feeding it forward
+    pub fn check_data(&self, data: &[Data<N>]) -> Result<(), TopologyError> {
+        for d in data {
+            if d.get_input().len() != self.input_size() {
+                return Err(TopologyError::InputSize {
+                    expected: self.input_size(),
+                    actual: d.get_shape(),
+                });
+            }
+            if d.get_class_vector().len() != self.output_size() {
+                return Err(TopologyError::OutputSize {
+                    expected: self.output_size(),
+                    actual: d.get_class_vector().len(),
+                    task: d.get_task(),
+                });
+            }
+        }
+        Ok(())
     }
 
-    /// return a vector of the bias matrices of the ANN
-    pub fn get_biases(&self) -> &[DVector<f32>] {
-        &self.biases
+    /// return the layers of the ANN
+    pub fn get_layers(&self) -> &[Box<Layer<N>>] {
+        &self.layers
     }
 
-    /// return a vector of the bias matrices of the ANN
-    pub fn get_biases_mut(&mut self) -> &mut Vec<DVector<f32>> {
-        &mut self.biases
+    /// return the mutable layers of the ANN
+    ///
+    /// Only the parameters of the layers should be changed, replacing a layer with one of
+    /// different size breaks the network.
+    pub fn get_layers_mut(&mut self) -> &mut [Box<Layer<N>>] {
+        &mut self.layers
     }
 
 
-    /// Saves a network state to the given filename and returns a result
+    /// Saves a network state to the file at `path` and returns a result
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut nn = nn::Network::new(vec![4, 5, 3]).unwrap();
-    /// let state_file_name = "state1.json";
-    /// nn.save_to_file(state_file_name).unwrap();
+    /// use rustle_my_net::nn::Network;
+    ///
+    /// let nn: Network = Network::new(&[4, 5, 3]).unwrap();
+    /// let state_file = std::env::temp_dir().join("state1.json");
+    /// nn.save_to_file(&state_file).unwrap();
     /// ```
-    pub fn save_to_file(self, filename: &str) -> Result<(), serde_json::Error> {
+    pub fn save_to_file<P: AsRef<Path>>(self, path: P) -> Result<(), serde_json::Error> {
+        self.save_with_history(path, None)
+    }
+
+    /// Saves a network state together with the history of the training run that produced it
+    ///
+    /// Errors of creating or writing the file are returned as `serde_json` errors of the `Io`
+    /// category.
+    pub fn save_with_history<P: AsRef<Path>>(self,
+                                             path: P,
+                                             history: Option<TrainingHistory>)
+                                             -> Result<(), serde_json::Error> {
         // wrap it in a SerializableNet
-        let serializable_net: SerializableNet = self.into();
-        // create the file
-        let f = File::create(util::get_root_dir().join("data/").join(filename)).unwrap();
+        let mut serializable_net: SerializableNet<N> = self.into();
+        serializable_net.history = history;
+        // create the file, errors like a missing directory are returned as io errors
+        let f = File::create(path)?;
         // create a writer
         let mut writer = BufWriter::new(f);
-        // serialize the network and return the result
-        serde_json::to_writer(&mut writer, &serializable_net)
+        // serialize the network, the writer is flushed so write errors are not lost on drop
+        serde_json::to_writer(&mut writer, &serializable_net)?;
+        Ok(writer.flush()?)
     }
 
 
-    /// Loads a network state from the given file
+    /// Loads a network state from the file at `path`
     ///
     /// Returns a result with the file or an io::Error if the specified file could
-    /// not be opened
+    /// not be opened. If the network was saved with a different precision than `N`, its weights
+    /// and biases are converted.
     ///
     /// # Examples
     ///
     /// ```
-    /// let loaded_nn = Network::from_file("state1.json");
+    /// # use rustle_my_net::nn::Network;
+    /// # let state_file = std::env::temp_dir().join("state2.json");
+    /// # Network::<f32>::new(&[4, 5, 3]).unwrap().save_to_file(&state_file).unwrap();
+    /// let loaded_nn: Network = Network::from_file(&state_file).unwrap();
     /// ```
-    pub fn from_file(filename: &str) -> Result<Self, io::Error> {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
         // attempt to open the file
-        let f = File::open(util::get_root_dir().join("data/").join(filename))?;
+        let f = File::open(path)?;
         let reader = BufReader::new(f);
         // read the SerializableNet from the file
         // we use unwrap here b/c if the file exists then we want the program to panic
         // if we cannot read from it
-        let my_net: SerializableNet = serde_json::from_reader(reader)
+        // files saved before networks were made of layers are converted
+        let saved: SavedNet<N> = serde_json::from_reader(reader)
             .expect("Could not parse Network from File");
+        let my_net: SerializableNet<N> = saved.into();
+        if my_net.precision != N::precision() {
+            info!("Converting network saved in {} precision to {}.",
+                  my_net.precision,
+                  N::precision());
+        }
         // convert into a Network and return it
         Ok(my_net.into())
     }
+
+    /// Convert the network into one that is computed in another precision
+    pub fn convert<M: Float>(&self) -> Network<M> {
+        Network {
+            input_size: self.input_size,
+            layers: self.layers
+                .iter()
+                .map(|layer| layers::from_serializable(layer.serialize().convert()).unwrap())
+                .collect(),
+            sizes: self.sizes.clone(),
+        }
+    }
 }
 
-/// calculate elementwise sigmoid function of the `input` vector.
-pub fn sigmoid(input: &DVector<f32>) -> DVector<f32> {
-    let mut sig = input.clone();
-    for elem in sig.iter_mut() {
-        *elem = 1.0 / (1.0 + (-1.0f32 * *elem).exp());
+/// Reads the precision a network was saved with from the given file, without loading it.
+pub fn precision_from_file<P: AsRef<Path>>(path: P) -> Result<Precision, io::Error> {
+    // only the precision field is read, everything else in the file is ignored
+    #[derive(Deserialize)]
+    struct Header {
+        #[serde(default)]
+        precision: Precision,
     }
-    sig
+
+    let f = File::open(path)?;
+    let header: Header = serde_json::from_reader(BufReader::new(f))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(header.precision)
 }
 
-impl From<SerializableNet> for Network {
-    fn from(ser_net: SerializableNet) -> Self {
+/// Reads the history of the training run a network was saved with from the given file, `None`
+/// if none was recorded.
+pub fn history_from_file<P: AsRef<Path>>(path: P)
+                                         -> Result<Option<TrainingHistory>, io::Error> {
+    // only the history field is read, everything else in the file is ignored
+    #[derive(Deserialize)]
+    struct Header {
+        #[serde(default)]
+        history: Option<TrainingHistory>,
+    }
 
-        let mut weights: Vec<DMatrix<f32>> = Vec::new();
-        for v in ser_net.weights {
-            let (nrows, ncols) = (v.0, v.1);
-            weights.push(DMatrix::from_column_vector(nrows, ncols, &v.2))
-        }
+    let f = File::open(path)?;
+    let header: Header = serde_json::from_reader(BufReader::new(f))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(header.history)
+}
 
-        let mut biases: Vec<DVector<f32>> = Vec::new();
-        for v in ser_net.biases {
-            biases.push(DVector::from_slice(v.len(), &v))
-        }
+/// Reusable buffers for the outputs of each layer during `Network::feedforward_batch_into`
+#[derive(Debug, Clone)]
+pub struct Workspace<N: Float = f32> {
+    /// outputs of all layers, one column per sample
+    activations: Vec<DMatrix<N>>,
+}
 
-        Network {
-            layers: ser_net.layers,
-            weights: weights,
-            biases: biases,
+impl<N: Float> Workspace<N> {
+    /// Create an empty workspace, the buffers are allocated on first use
+    pub fn new() -> Workspace<N> {
+        Workspace { activations: Vec::new() }
+    }
+
+    // Make sure there is a buffer of the right size for each layer. Buffers are only reallocated
+    // if the topology or the number of samples changed since the last call.
+    fn prepare(&mut self, sizes: &[usize], n_samples: usize) {
+        self.activations.truncate(sizes.len());
+        for (l, size) in sizes.iter().enumerate() {
+            let size = *size;
+            if l == self.activations.len() {
+                self.activations.push(DMatrix::new_zeros(size, n_samples));
+            } else if self.activations[l].nrows() != size ||
+                      self.activations[l].ncols() != n_samples {
+                self.activations[l] = DMatrix::new_zeros(size, n_samples);
+            }
         }
     }
 }
 
+/// calculate elementwise sigmoid function of the `input` vector.
+pub fn sigmoid<N: Float>(input: &DVector<N>) -> DVector<N> {
+    let mut sig = input.clone();
+    for elem in sig.iter_mut() {
+        *elem = layers::activation::sigmoid(*elem);
+    }
+    sig
+}
+
+impl<N: Float> From<SerializableNet<N>> for Network<N> {
+    fn from(ser_net: SerializableNet<N>) -> Self {
+        // we use expect here b/c a saved network that does not fit together is corrupted
+        let layers = ser_net.layers
+            .into_iter()
+            .map(layers::from_serializable)
+            .collect::<Result<_, _>>()
+            .expect("Invalid layer in file");
+        Network::from_layers(ser_net.input_size, layers).expect("Invalid network in file")
+    }
+}
+
 
 #[test]
 fn test_sigmoid() {
@@ -212,3 +381,77 @@ fn test_sigmoid() {
     assert_eq!(arr[0], 0.73105857863f32);
     assert_eq!(arr[2], 0.91786604895f32);
 }
+
+#[test]
+fn test_feedforward_batch() {
+    let dense = vec![Dense::new(3, 4), Dense::new(4, 2)];
+    let mut layers: Vec<Box<Layer<f32>>> = Vec::new();
+    for layer in &dense {
+        layers.push(Box::new(layer.clone()));
+        layers.push(Box::new(ActivationLayer::new(Activation::Sigmoid)));
+    }
+    let nn = Network::from_layers(3, layers).unwrap();
+    let inputs = DMatrix::from_fn(3, 5, |i, j| (i + 2 * j) as f32 / 10.0);
+    let outputs = nn.feedforward_batch(&inputs);
+    assert_eq!((outputs.nrows(), outputs.ncols()), (2, 5));
+    for j in 0..5 {
+        let mut act = DVector::from_fn(3, |i| inputs[(i, j)]);
+        for layer in &dense {
+            act = sigmoid(&(layer.get_weights() * act + layer.get_biases().clone()));
+        }
+        for i in 0..2 {
+            assert!((act[i] - outputs[(i, j)]).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn test_convert_precision() {
+    let nn: Network<f32> = Network::new(&[3, 4, 2]).unwrap();
+    let converted: Network<f32> = nn.convert::<f64>().convert();
+    for (layer, conv_layer) in nn.get_layers().iter().zip(converted.get_layers().iter()) {
+        assert_eq!(layer.parameters(), conv_layer.parameters());
+    }
+}
+
+#[test]
+fn test_dropout_disabled_in_feedforward() {
+    let nn: Network<f64> = Network::new(&[3, 8, 2]).unwrap();
+    let mut layers = nn.get_layers().to_vec();
+    layers.insert(0, Box::new(Dropout::new(0.8)));
+    layers.insert(3, Box::new(Dropout::new(0.5)));
+    let with_dropout = Network::from_layers(3, layers).unwrap();
+    let input = DVector::from_slice(3, &[0.2, 0.4, 0.6]);
+    assert_eq!(nn.feedforward(&input), with_dropout.feedforward(&input));
+    let topology = Topology::dense(&[3, 8, 2]);
+    let sigmoid = Activation::Sigmoid;
+    assert!(Network::<f64>::from_topology(&topology, &[0.5], false, sigmoid).is_err());
+    assert!(Network::<f64>::from_topology(&topology, &[0.0, 0.5], false, sigmoid).is_err());
+}
+
+#[test]
+fn test_from_layers_checks_sizes() {
+    let layers: Vec<Box<Layer<f64>>> = vec![Box::new(Dense::new(3, 4)),
+                                            Box::new(BatchNorm::new(5))];
+    assert!(Network::from_layers(3, layers).is_err());
+    let layers: Vec<Box<Layer<f64>>> = vec![Box::new(Dense::new(3, 4))];
+    assert!(Network::from_layers(2, layers).is_err());
+}
+
+#[test]
+fn test_check_data() {
+    let nn: Network<f32> = Network::new(&[4, 5, 3]).unwrap();
+    let flower = Data::new(DVector::from_element(4, 0.5), 1, 3);
+    assert_eq!(nn.check_data(&[flower.clone()]), Ok(()));
+
+    let image = Data::new(DVector::from_element(784, 0.5), 1, 10).with_shape(Shape::new(28, 28, 1));
+    assert_eq!(nn.check_data(&[flower.clone(), image]),
+               Err(TopologyError::InputSize {
+                   expected: 4,
+                   actual: Shape::new(28, 28, 1),
+               }));
+    let err = Network::<f32>::new(&[4, 5, 2]).unwrap().check_data(&[flower]).unwrap_err();
+    assert_eq!(err.to_string(),
+               "the output layer has 2 neurons, but the samples have 3 classes, use 3 neurons \
+                in the last layer of the topology");
+}
@@ -0,0 +1,411 @@
+//! Description of the layers of a network, as given on the command line.
+//!
+//! A topology is a list of tokens. The first one is the shape of the input, either the number of
+//! inputs (e.g. `4`) or `HEIGHTxWIDTHxCHANNELS` for images (e.g. `28x28x1`). Each following token
+//! is a layer:
+//!
+//! * `N`: a fully connected layer of `N` sigmoid neurons
+//! * `conv:C:K[:S[:P]]`: a convolution with `C` output channels, a `K`x`K` kernel, stride `S`
+//!   (default 1) and zero padding `P` (default 0), followed by a sigmoid activation
+//! * `maxpool:K[:S]` and `avgpool:K[:S]`: max and average pooling over `K`x`K` windows with
+//!   stride `S` (default `K`)
+//! * `rnn:N[:seq]`, `lstm:N[:seq]` and `gru:N[:seq]`: recurrent layers with `N` units (Elman,
+//!   long short-term memory and gated recurrent units) that pass on their output after the last
+//!   step, or the outputs of all steps if `:seq` is given
+//!
+//! For example `28x28x1 conv:8:5:1:2 maxpool:2 100 10` describes a network for MNIST images.
+//! Sequences of `S` steps with `F` features each are written as `SxFx1`, e.g.
+//! `50x3x1 lstm:16 4` classifies sequences of 50 readings of a 3 axis sensor.
+
+use std::fmt;
+use std::str::FromStr;
+use structs::Task;
+
+/// The shape of the inputs or outputs of a layer
+///
+/// The values of a sample are stored channel by channel, each channel row by row. A flat vector
+/// of `n` values has height and width 1 and `n` channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shape {
+    /// number of rows of each channel
+    pub height: usize,
+    /// number of columns of each channel
+    pub width: usize,
+    /// number of channels
+    pub channels: usize,
+}
+
+impl Shape {
+    /// Create the shape of an image
+    pub fn new(height: usize, width: usize, channels: usize) -> Shape {
+        Shape {
+            height: height,
+            width: width,
+            channels: channels,
+        }
+    }
+
+    /// Create the shape of a flat vector of `size` values
+    pub fn flat(size: usize) -> Shape {
+        Shape::new(1, 1, size)
+    }
+
+    /// The number of values of a sample of this shape
+    pub fn len(&self) -> usize {
+        self.height * self.width * self.channels
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.height == 1 && self.width == 1 {
+            write!(f, "{}", self.channels)
+        } else {
+            write!(f, "{}x{}x{}", self.height, self.width, self.channels)
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = &'static str;
+
+    /// Parse a shape from `N` or `HEIGHTxWIDTHxCHANNELS`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let dims: Vec<usize> = s.split('x')
+            .map(|d| d.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| "input shape must be a number or HEIGHTxWIDTHxCHANNELS")?;
+        let shape = match dims.len() {
+            1 => Shape::flat(dims[0]),
+            3 => Shape::new(dims[0], dims[1], dims[2]),
+            _ => return Err("input shape must be a number or HEIGHTxWIDTHxCHANNELS"),
+        };
+        if shape.len() == 0 {
+            return Err("input shape must not be empty");
+        }
+        Ok(shape)
+    }
+}
+
+/// The kind of pooling of a pooling layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoolMode {
+    /// the largest value of each window
+    Max,
+    /// the mean of each window
+    Average,
+}
+
+/// The kind of cell of a recurrent layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cell {
+    /// a simple recurrent unit, `h = tanh(W x + U h + b)`
+    Elman,
+    /// long short-term memory with input, forget and output gates
+    Lstm,
+    /// gated recurrent unit with update and reset gates
+    Gru,
+}
+
+/// Description of a single layer of a topology
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerSpec {
+    /// fully connected layer with the given number of sigmoid neurons
+    Dense(usize),
+    /// convolution followed by a sigmoid activation
+    Conv {
+        /// number of output channels
+        channels: usize,
+        /// height and width of the kernel
+        kernel: usize,
+        /// step between two applications of the kernel
+        stride: usize,
+        /// number of zeros added at each border of the input
+        padding: usize,
+    },
+    /// pooling over windows of each channel
+    Pool {
+        /// whether the maximum or the mean of each window is taken
+        mode: PoolMode,
+        /// height and width of the windows
+        size: usize,
+        /// step between two windows
+        stride: usize,
+    },
+    /// recurrent layer over the steps of a sequence
+    Recurrent {
+        /// the kind of the recurrent units
+        cell: Cell,
+        /// number of units
+        size: usize,
+        /// whether the outputs of all steps are passed on, otherwise only the last one
+        sequences: bool,
+        /// number of steps the error is backpropagated through time, 0 for all steps
+        bptt_length: usize,
+    },
+}
+
+impl fmt::Display for LayerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayerSpec::Dense(size) => write!(f, "{}", size),
+            LayerSpec::Conv { channels, kernel, stride, padding } => {
+                write!(f, "conv:{}:{}:{}:{}", channels, kernel, stride, padding)
+            }
+            LayerSpec::Pool { mode, size, stride } => {
+                let name = match mode {
+                    PoolMode::Max => "maxpool",
+                    PoolMode::Average => "avgpool",
+                };
+                write!(f, "{}:{}:{}", name, size, stride)
+            }
+            LayerSpec::Recurrent { cell, size, sequences, .. } => {
+                let name = match cell {
+                    Cell::Elman => "rnn",
+                    Cell::Lstm => "lstm",
+                    Cell::Gru => "gru",
+                };
+                write!(f, "{}:{}{}", name, size, if sequences { ":seq" } else { "" })
+            }
+        }
+    }
+}
+
+impl FromStr for LayerSpec {
+    type Err = &'static str;
+
+    /// Parse a layer from `N`, `conv:C:K[:S[:P]]`, `maxpool:K[:S]` or `avgpool:K[:S]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split(':').collect();
+        let name = parts.remove(0);
+        // recurrent layers can be told to pass on all outputs
+        let sequences = parts.last() == Some(&"seq");
+        if sequences {
+            parts.pop();
+        }
+        let args: Vec<usize> = parts.iter()
+            .map(|a| a.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| "layer arguments must be non-negative integers")?;
+
+        let spec = match name {
+            "conv" => {
+                if args.len() < 2 || args.len() > 4 {
+                    return Err("convolutions are written as conv:CHANNELS:KERNEL[:STRIDE[:PAD]]");
+                }
+                LayerSpec::Conv {
+                    channels: args[0],
+                    kernel: args[1],
+                    stride: *args.get(2).unwrap_or(&1),
+                    padding: *args.get(3).unwrap_or(&0),
+                }
+            }
+            "maxpool" | "avgpool" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err("pooling layers are written as maxpool:SIZE[:STRIDE] or \
+                                avgpool:SIZE[:STRIDE]");
+                }
+                LayerSpec::Pool {
+                    mode: if name == "maxpool" {
+                        PoolMode::Max
+                    } else {
+                        PoolMode::Average
+                    },
+                    size: args[0],
+                    stride: *args.get(1).unwrap_or(&args[0]),
+                }
+            }
+            "rnn" | "lstm" | "gru" => {
+                if args.len() != 1 {
+                    return Err("recurrent layers are written as rnn:SIZE[:seq], lstm:SIZE[:seq] \
+                                or gru:SIZE[:seq]");
+                }
+                LayerSpec::Recurrent {
+                    cell: match name {
+                        "rnn" => Cell::Elman,
+                        "lstm" => Cell::Lstm,
+                        _ => Cell::Gru,
+                    },
+                    size: args[0],
+                    sequences: sequences,
+                    bptt_length: 0,
+                }
+            }
+            _ => {
+                let size = name.parse().map_err(|_| "unknown layer")?;
+                if !args.is_empty() {
+                    return Err("dense layers are written as a single number");
+                }
+                LayerSpec::Dense(size)
+            }
+        };
+
+        if sequences && !is_recurrent(&spec) {
+            return Err("only recurrent layers can pass on sequences");
+        }
+        match spec {
+            LayerSpec::Dense(0) |
+            LayerSpec::Recurrent { size: 0, .. } => Err("layers must not be empty"),
+            LayerSpec::Conv { channels, kernel, stride, .. } if channels == 0 || kernel == 0 ||
+                                                                stride == 0 => {
+                Err("channels, kernel size and stride of a convolution must be positive")
+            }
+            LayerSpec::Pool { size, stride, .. } if size == 0 || stride == 0 => {
+                Err("size and stride of a pooling layer must be positive")
+            }
+            _ => Ok(spec),
+        }
+    }
+}
+
+// Whether `spec` describes a recurrent layer
+fn is_recurrent(spec: &LayerSpec) -> bool {
+    match *spec {
+        LayerSpec::Recurrent { .. } => true,
+        _ => false,
+    }
+}
+
+/// The input shape and layers of a network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    /// shape of the inputs
+    pub input: Shape,
+    /// the layers in order, the last one is the output layer
+    pub layers: Vec<LayerSpec>,
+}
+
+impl Topology {
+    /// Parse a topology from its tokens, the input shape followed by the layers
+    pub fn parse<'a, I>(tokens: I) -> Result<Topology, &'static str>
+        where I: IntoIterator<Item = &'a str>
+    {
+        let mut tokens = tokens.into_iter();
+        let input = tokens.next().ok_or("the topology needs an input shape")?.parse()?;
+        let layers: Vec<LayerSpec> = tokens.map(|t| t.parse()).collect::<Result<_, _>>()?;
+        if layers.is_empty() {
+            return Err("at least one layer besides the input required");
+        }
+        Ok(Topology {
+            input: input,
+            layers: layers,
+        })
+    }
+
+    /// A topology of fully connected layers, `sizes` holds the number of neurons of each layer
+    /// including in- and output layer
+    pub fn dense(sizes: &[u32]) -> Topology {
+        Topology {
+            input: Shape::flat(*sizes.first().unwrap_or(&0) as usize),
+            layers: sizes.iter().skip(1).map(|size| LayerSpec::Dense(*size as usize)).collect(),
+        }
+    }
+
+    /// Set the number of steps all recurrent layers backpropagate the error through time, 0 for
+    /// the whole sequence
+    pub fn set_bptt_length(&mut self, steps: usize) {
+        for spec in &mut self.layers {
+            if let LayerSpec::Recurrent { ref mut bptt_length, .. } = *spec {
+                *bptt_length = steps;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.input)?;
+        for layer in &self.layers {
+            write!(f, " {}", layer)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Why the samples given to a network do not fit its topology
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyError {
+    /// the input layer takes `expected` values, but a sample has an input of the shape `actual`
+    InputSize { expected: usize, actual: Shape },
+    /// the output layer has `expected` neurons, but a sample has `actual` classes, labels or
+    /// targets, depending on `task`
+    OutputSize {
+        expected: usize,
+        actual: usize,
+        task: Task,
+    },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TopologyError::InputSize { expected, actual } => {
+                write!(f,
+                       "the input layer takes {} values, but the samples have {} values",
+                       expected,
+                       actual.len())?;
+                if actual.height > 1 || actual.width > 1 {
+                    write!(f, " of the shape {}", actual)?;
+                }
+                write!(f, ", use '{}' as the input of the topology", actual)
+            }
+            TopologyError::OutputSize { expected, actual, task } => {
+                let targets = match task {
+                    Task::Classification => "classes",
+                    Task::MultiLabel => "labels",
+                    Task::Regression => "targets",
+                };
+                write!(f,
+                       "the output layer has {} neurons, but the samples have {} {}, use {} \
+                        neurons in the last layer of the topology",
+                       expected,
+                       actual,
+                       targets,
+                       actual)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_topology() {
+    let topology = Topology::parse("28x28x1 conv:8:5:1:2 maxpool:2 avgpool:3:1 10"
+            .split(' '))
+        .unwrap();
+    assert_eq!(topology.input, Shape::new(28, 28, 1));
+    assert_eq!(topology.layers,
+               vec![LayerSpec::Conv {
+                        channels: 8,
+                        kernel: 5,
+                        stride: 1,
+                        padding: 2,
+                    },
+                    LayerSpec::Pool {
+                        mode: PoolMode::Max,
+                        size: 2,
+                        stride: 2,
+                    },
+                    LayerSpec::Pool {
+                        mode: PoolMode::Average,
+                        size: 3,
+                        stride: 1,
+                    },
+                    LayerSpec::Dense(10)]);
+    assert_eq!(Topology::parse(topology.to_string().split(' ')), Ok(topology));
+    assert_eq!(Topology::parse("4 5 3".split(' ')), Ok(Topology::dense(&[4, 5, 3])));
+    assert!(Topology::parse("4".split(' ')).is_err());
+    assert!(Topology::parse("28x28 10".split(' ')).is_err());
+    assert!(Topology::parse("4 conv:8 3".split(' ')).is_err());
+    assert!(Topology::parse("4 maxpool:0 3".split(' ')).is_err());
+    let topology = Topology::parse("20x3x1 lstm:8:seq gru:4 2".split(' ')).unwrap();
+    assert_eq!(topology.layers[0],
+               LayerSpec::Recurrent {
+                   cell: Cell::Lstm,
+                   size: 8,
+                   sequences: true,
+                   bptt_length: 0,
+               });
+    assert_eq!(Topology::parse(topology.to_string().split(' ')), Ok(topology));
+    assert!(Topology::parse("20x3x1 16:seq 2".split(' ')).is_err());
+}
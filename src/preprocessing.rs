@@ -0,0 +1,84 @@
+//! Preparing data for a network: splitting it, scaling its inputs, balancing its classes and
+//! augmenting images.
+//!
+//! The helpers are gathered here from `input::util`, `nn::learning` and `nn::augment`, so they can
+//! be used without the settings of the command line. Scaling is fitted on the training data only
+//! and then applied to every data set, otherwise the test data would leak into training.
+//!
+//! ```
+//! extern crate nalgebra;
+//! extern crate rustle_my_net;
+//!
+//! use nalgebra::DVector;
+//! use rustle_my_net::preprocessing::{self, Standardization};
+//! use rustle_my_net::structs::Data;
+//!
+//! # fn main() {
+//! let mut data: Vec<Data> = (0..10)
+//!     .map(|i| Data::new(DVector::from_slice(2, &[i as f32, 100.0 * i as f32]), i % 2, 2))
+//!     .collect();
+//! let (training_data, test_data) = preprocessing::split_data(&mut data, 2);
+//!
+//! let scaling = Standardization::fit(&training_data).unwrap();
+//! let training_data: Vec<Data> = training_data.iter().map(|d| scaling.apply(d)).collect();
+//! let test_data: Vec<Data> = test_data.iter().map(|d| scaling.apply(d)).collect();
+//!
+//! let mean: f32 = training_data.iter().map(|d| d.get_input()[1]).sum::<f32>() / 8.0;
+//! assert!(mean.abs() < 1e-5);
+//! assert_eq!(test_data.len(), 2);
+//! # }
+//! ```
+
+pub use input::util::{generic_to_data, split_data};
+pub use nn::augment::Augmentation;
+pub use nn::learning::{inverse_frequency_weights, ClassBalance, Resampling};
+
+use na::DVector;
+use nn::Float;
+use structs::Data;
+
+/// Scales every input of the data to zero mean and unit variance
+///
+/// Inputs that are constant in the fitted data are only shifted to zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standardization<N> {
+    /// the mean of each input
+    pub mean: DVector<N>,
+    /// the standard deviation of each input, 1 for constant inputs
+    pub std_dev: DVector<N>,
+}
+
+impl<N: Float> Standardization<N> {
+    /// Fit the mean and standard deviation of each input to `data`, `None` if `data` is empty
+    pub fn fit(data: &[Data<N>]) -> Option<Standardization<N>> {
+        let size = data.first()?.get_input().len();
+        let n = data.len() as f64;
+        let mut mean: Vec<f64> = vec![0.0; size];
+        for d in data {
+            for (m, &x) in mean.iter_mut().zip(d.get_input().at.iter()) {
+                *m += x.to_f64() / n;
+            }
+        }
+        let mut variance: Vec<f64> = vec![0.0; size];
+        for d in data {
+            for ((v, m), &x) in variance.iter_mut().zip(&mean).zip(d.get_input().at.iter()) {
+                *v += (x.to_f64() - m).powi(2) / n;
+            }
+        }
+        Some(Standardization {
+            mean: DVector::from_fn(size, |i| N::from_f64(mean[i])),
+            std_dev: DVector::from_fn(size, |i| if variance[i] > 0.0 {
+                N::from_f64(variance[i].sqrt())
+            } else {
+                N::one()
+            }),
+        })
+    }
+
+    /// Return a copy of `data` with scaled inputs
+    pub fn apply(&self, data: &Data<N>) -> Data<N> {
+        let input = data.get_input();
+        let scaled = DVector::from_fn(input.len(), |i| (input[i] - self.mean[i]) / self.std_dev[i]);
+        data.clone().with_input(scaled)
+    }
+}
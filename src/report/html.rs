@@ -0,0 +1,224 @@
+//! A single, self-contained HTML page that summarizes a training run.
+//!
+//! The charts are inlined as SVG and the page uses no scripts, so it can be viewed offline and
+//! passed around as one file.
+
+use input::config::LearningConfig;
+use nn::{Shape, Topology, TrainingHistory};
+use nn::learning::{ConfusionMatrix, Evaluation, Misclassified};
+use report::{self, svg};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+const STYLE: &'static str = "body { font-family: sans-serif; max-width: 1000px; margin: 2em auto; \
+                             color: #222; }\n\
+                             table { border-collapse: collapse; margin: 1em 0; }\n\
+                             th, td { border: 1px solid #ccc; padding: 4px 10px; \
+                             text-align: left; }\n\
+                             th { background: #f4f4f4; }\n\
+                             svg { margin: 0 1em 1em 0; vertical-align: top; }\n\
+                             .sample { display: inline-block; margin: 0 1em 1em 0; }\n";
+// the width inputs that are drawn as images are scaled to, in pixels
+const IMAGE_WIDTH: f64 = 112.0;
+// inputs that are not drawn as images show at most this many values
+const MAX_VALUES: usize = 10;
+
+/// Everything the HTML report of a training run shows
+#[derive(Debug, Clone)]
+pub struct TrainingReport<'a> {
+    /// the settings of the run
+    pub config: &'a LearningConfig,
+    /// the topology of the trained network
+    pub topology: &'a Topology,
+    /// the number of training samples
+    pub n_training: usize,
+    /// the number of test samples
+    pub n_test: usize,
+    /// the shape of a single input
+    pub input_shape: Shape,
+    /// the number of training samples of each class, `None` unless every sample has one class
+    pub class_counts: Option<Vec<usize>>,
+    /// the progress of every epoch
+    pub history: &'a TrainingHistory,
+    /// the metrics of the trained network on the test data, `None` without test data
+    pub evaluation: Option<Evaluation>,
+    /// the confusion matrix on the test data, `None` unless it is single-label classification
+    /// data
+    pub confusion: Option<ConfusionMatrix>,
+    /// the worst misclassified test samples together with their inputs
+    pub misclassified: Vec<(Misclassified, Vec<f64>)>,
+}
+
+impl<'a> TrainingReport<'a> {
+    /// Render the report as an HTML page
+    pub fn to_html(&self) -> String {
+        let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                                <title>Training report</title>\n<style>\n{}</style>\n</head>\n\
+                                <body>\n<h1>Training report</h1>\n",
+                               STYLE);
+        self.write_network(&mut html);
+        self.write_data(&mut html);
+        self.write_curves(&mut html);
+        self.write_evaluation(&mut html);
+        self.write_misclassified(&mut html);
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// Write the report as an HTML file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        File::create(path)?.write_all(self.to_html().as_bytes())
+    }
+
+    // The topology and the hyperparameters
+    fn write_network(&self, html: &mut String) {
+        let cfg = self.config;
+        let augmentation = cfg.augmentation
+            .as_ref()
+            .map_or_else(|| "none".to_string(), |a| format!("{:?}", a));
+        let optional = |x: Option<f64>| x.map_or_else(|| "none".to_string(), |x| x.to_string());
+        let rows = vec![("Topology", self.topology.to_string()),
+                        ("Learning rate", cfg.learning_rate.to_string()),
+                        ("Epochs", cfg.epochs.to_string()),
+                        ("Mini batch size", cfg.batch_size.to_string()),
+                        ("Keep probabilities", format!("{:?}", cfg.keep_probs)),
+                        ("Input keep probability", cfg.input_keep_prob.to_string()),
+                        ("Batch normalization", cfg.batch_norm.to_string()),
+                        ("BPTT length", cfg.bptt_length.to_string()),
+                        ("Output activation", format!("{:?}", cfg.output)),
+                        ("Cost", cfg.cost.to_string()),
+                        ("Gradient clipping value", optional(cfg.clip_value)),
+                        ("Gradient clipping norm", optional(cfg.clip_norm)),
+                        ("On divergence", format!("{:?}", cfg.divergence)),
+                        ("Threshold", cfg.threshold.to_string()),
+                        ("Class weights", format!("{:?}", cfg.class_weights)),
+                        ("Resampling", format!("{:?}", cfg.resampling)),
+                        ("Augmentation", augmentation),
+                        ("Precision", cfg.precision.to_string())];
+        html.push_str("<h2>Network</h2>\n");
+        write_table(html,
+                    &["Setting", "Value"],
+                    rows.into_iter().map(|(k, v)| vec![k.to_string(), v]));
+    }
+
+    // The sizes of the training and test data and the classes of the training data
+    fn write_data(&self, html: &mut String) {
+        html.push_str("<h2>Data</h2>\n");
+        let rows = vec![vec!["Training samples".to_string(), self.n_training.to_string()],
+                        vec!["Test samples".to_string(), self.n_test.to_string()],
+                        vec!["Input shape".to_string(), self.input_shape.to_string()]];
+        write_table(html, &["", ""], rows.into_iter());
+        if let Some(ref counts) = self.class_counts {
+            let total = counts.iter().sum::<usize>().max(1) as f64;
+            let rows = counts.iter()
+                .enumerate()
+                .map(|(c, &n)| {
+                    vec![c.to_string(), n.to_string(), format!("{:.1} %", 100.0 * n as f64 / total)]
+                });
+            write_table(html, &["Class", "Training samples", "Share"], rows);
+        }
+    }
+
+    // The charts of the training history and the time it took
+    fn write_curves(&self, html: &mut String) {
+        html.push_str("<h2>Learning curves</h2>\n");
+        let epochs = &self.history.epochs;
+        if epochs.is_empty() {
+            html.push_str("<p>No epochs were trained.</p>\n");
+            return;
+        }
+        let seconds: f64 = epochs.iter().map(|r| r.seconds).sum();
+        write!(html,
+               "<p>{} epochs in {:.1} s, final training cost {:.4}.</p>\n",
+               epochs.len(),
+               seconds,
+               epochs[epochs.len() - 1].training_cost)
+            .unwrap();
+        html.push_str(&report::cost_chart(self.history));
+        if let Some(chart) = report::accuracy_chart(self.history) {
+            html.push_str(&chart);
+        }
+        html.push_str(&report::gradient_norm_chart(self.history));
+    }
+
+    // The metrics on the test data, the confusion matrix and the metrics of each class
+    fn write_evaluation(&self, html: &mut String) {
+        html.push_str("<h2>Evaluation on the test data</h2>\n");
+        match self.evaluation {
+            None => html.push_str("<p>No test data.</p>\n"),
+            Some(Evaluation::Classification(correct, total)) => {
+                write!(html,
+                       "<p>{}/{} correctly classified ({:.2} %).</p>\n",
+                       correct,
+                       total,
+                       100.0 * correct as f64 / total as f64)
+                    .unwrap()
+            }
+            Some(ref evaluation) => {
+                write!(html, "<p>{}</p>\n", svg::escape(&evaluation.to_string())).unwrap()
+            }
+        }
+        if let Some(ref confusion) = self.confusion {
+            html.push_str(&report::confusion_chart(confusion));
+            let rows = (0..confusion.counts.len()).map(|c| {
+                vec![c.to_string(),
+                     format!("{:.4}", confusion.precision(c)),
+                     format!("{:.4}", confusion.recall(c)),
+                     format!("{:.4}", confusion.f1(c)),
+                     confusion.support(c).to_string()]
+            });
+            write_table(html, &["Class", "Precision", "Recall", "F1", "Support"], rows);
+        }
+    }
+
+    // The worst misclassified samples, inputs with more than one row and column are drawn as
+    // images
+    fn write_misclassified(&self, html: &mut String) {
+        if self.misclassified.is_empty() {
+            return;
+        }
+        html.push_str("<h2>Worst misclassified samples</h2>\n");
+        let shape = self.input_shape;
+        let is_image = shape.height > 1 && shape.width > 1;
+        for &(ref sample, ref input) in &self.misclassified {
+            write!(html,
+                   "<div class=\"sample\">\n<p>Test sample {}: class {}, predicted {} \
+                    (margin {:.3})</p>\n",
+                   sample.index,
+                   sample.actual,
+                   sample.predicted,
+                   sample.margin)
+                .unwrap();
+            if is_image {
+                let scale = (IMAGE_WIDTH / shape.width as f64).max(1.0);
+                html.push_str(&svg::image(input, shape, scale));
+            } else {
+                let mut values: Vec<String> =
+                    input.iter().take(MAX_VALUES).map(|x| format!("{:.3}", x)).collect();
+                if input.len() > MAX_VALUES {
+                    values.push("…".to_string());
+                }
+                write!(html, "<p><code>{}</code></p>\n", values.join(", ")).unwrap();
+            }
+            html.push_str("</div>\n");
+        }
+    }
+}
+
+// Write a table with the given column headers, an empty header row is left out
+fn write_table<I: Iterator<Item = Vec<String>>>(html: &mut String, headers: &[&str], rows: I) {
+    html.push_str("<table>\n");
+    if headers.iter().any(|h| !h.is_empty()) {
+        let headers: Vec<String> =
+            headers.iter().map(|h| format!("<th>{}</th>", svg::escape(h))).collect();
+        write!(html, "<tr>{}</tr>\n", headers.concat()).unwrap();
+    }
+    for row in rows {
+        let cells: Vec<String> =
+            row.iter().map(|c| format!("<td>{}</td>", svg::escape(c))).collect();
+        write!(html, "<tr>{}</tr>\n", cells.concat()).unwrap();
+    }
+    html.push_str("</table>\n");
+}
@@ -0,0 +1,114 @@
+//! Charts that show how a training run went.
+//!
+//! They are generated from the `TrainingHistory` saved with a network and from evaluating the
+//! network on data. `html` combines them with the settings of the run into a single page.
+
+pub mod html;
+pub mod svg;
+
+use nn::TrainingHistory;
+use nn::learning::ConfusionMatrix;
+use self::svg::Series;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Chart of the training and validation cost of every epoch
+pub fn cost_chart(history: &TrainingHistory) -> String {
+    let series = [Series {
+                      name: "training".to_string(),
+                      points: history.epochs
+                          .iter()
+                          .map(|r| (r.epoch as f64, r.training_cost))
+                          .collect(),
+                  },
+                  Series {
+                      name: "validation".to_string(),
+                      points: history.epochs
+                          .iter()
+                          .filter_map(|r| r.validation_cost.map(|c| (r.epoch as f64, c)))
+                          .collect(),
+                  }];
+    svg::line_chart("Cost per sample", "epoch", "cost", &series)
+}
+
+/// Chart of the training and validation accuracy of every epoch, `None` if no accuracy was
+/// recorded
+pub fn accuracy_chart(history: &TrainingHistory) -> Option<String> {
+    let series = [Series {
+                      name: "training".to_string(),
+                      points: history.epochs
+                          .iter()
+                          .filter_map(|r| r.training_accuracy.map(|a| (r.epoch as f64, a)))
+                          .collect(),
+                  },
+                  Series {
+                      name: "validation".to_string(),
+                      points: history.epochs
+                          .iter()
+                          .filter_map(|r| r.accuracy.map(|a| (r.epoch as f64, a)))
+                          .collect(),
+                  }];
+    if series.iter().all(|s| s.points.is_empty()) {
+        return None;
+    }
+    Some(svg::line_chart("Accuracy", "epoch", "accuracy", &series))
+}
+
+/// Heatmap of a confusion matrix, the actual classes are the rows
+pub fn confusion_chart(confusion: &ConfusionMatrix) -> String {
+    let labels: Vec<String> = (0..confusion.counts.len()).map(|c| c.to_string()).collect();
+    svg::heatmap("Confusion matrix",
+                 "actual class",
+                 "predicted class",
+                 &labels,
+                 &confusion.counts)
+}
+
+/// Chart of the mean and largest global norm of the gradients of the mini batches in every epoch,
+/// before they were clipped
+pub fn gradient_norm_chart(history: &TrainingHistory) -> String {
+    let series = [Series {
+                      name: "mean".to_string(),
+                      points: history.epochs
+                          .iter()
+                          .map(|r| (r.epoch as f64, r.gradient_norm))
+                          .collect(),
+                  },
+                  Series {
+                      name: "max".to_string(),
+                      points: history.epochs
+                          .iter()
+                          .map(|r| (r.epoch as f64, r.max_gradient_norm))
+                          .collect(),
+                  }];
+    svg::line_chart("Gradient norm", "epoch", "L2 norm", &series)
+}
+
+/// Write the charts of the history and of the confusion matrix as SVG files into `dir`, which is
+/// created if necessary. Returns the paths of the written files.
+pub fn write_charts(dir: &Path,
+                    history: Option<&TrainingHistory>,
+                    confusion: Option<&ConfusionMatrix>)
+                    -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut charts = Vec::new();
+    if let Some(history) = history {
+        charts.push(("cost.svg", cost_chart(history)));
+        if let Some(chart) = accuracy_chart(history) {
+            charts.push(("accuracy.svg", chart));
+        }
+        charts.push(("gradient_norm.svg", gradient_norm_chart(history)));
+    }
+    if let Some(confusion) = confusion {
+        charts.push(("confusion.svg", confusion_chart(confusion)));
+    }
+
+    let mut written = Vec::new();
+    for (name, chart) in charts {
+        let path = dir.join(name);
+        File::create(&path)?.write_all(chart.as_bytes())?;
+        written.push(path);
+    }
+    Ok(written)
+}
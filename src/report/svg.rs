@@ -0,0 +1,339 @@
+//! Self-contained SVG charts, written by hand so no plotting tools are needed.
+
+use nn::Shape;
+use std::fmt::Write;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 400.0;
+// space around the plot area for the title, ticks and axis labels: top, right, bottom, left
+const MARGIN: (f64, f64, f64, f64) = (40.0, 20.0, 50.0, 70.0);
+const COLORS: [&'static str; 4] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728"];
+// lines with at most this many points also get a marker at every point
+const MAX_MARKERS: usize = 50;
+
+/// A named line of a line chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    /// name shown in the legend
+    pub name: String,
+    /// the points in the order they are connected, (x, y)
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Draw the series as lines into a chart with axes, ticks and a legend
+///
+/// The y axis always includes 0. Series without points are left out, and lines are interrupted
+/// at points that are not finite, e.g. the cost of a diverged epoch.
+pub fn line_chart(title: &str, x_label: &str, y_label: &str, series: &[Series]) -> String {
+    let points = series.iter().flat_map(|s| s.points.iter());
+    let (mut x_min, mut x_max, mut y_min, mut y_max) = (::std::f64::INFINITY,
+                                                        ::std::f64::NEG_INFINITY,
+                                                        0.0f64,
+                                                        ::std::f64::NEG_INFINITY);
+    for &(x, y) in points.filter(|p| p.0.is_finite() && p.1.is_finite()) {
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    if !x_min.is_finite() {
+        // no points at all
+        x_min = 0.0;
+        x_max = 1.0;
+        y_max = 1.0;
+    }
+    let (x_ticks, y_ticks) = (ticks(x_min, x_max), ticks(y_min, y_max));
+    let (x_min, x_max) = (x_ticks[0], x_ticks[x_ticks.len() - 1]);
+    let (y_min, y_max) = (y_ticks[0], y_ticks[y_ticks.len() - 1]);
+
+    let (top, right, bottom, left) = MARGIN;
+    let (plot_width, plot_height) = (WIDTH - left - right, HEIGHT - top - bottom);
+    let px = |x: f64| left + (x - x_min) / (x_max - x_min) * plot_width;
+    let py = |y: f64| top + (y_max - y) / (y_max - y_min) * plot_height;
+
+    let mut svg = header(WIDTH, HEIGHT, title);
+    // grid lines and tick labels
+    for (&y, label) in y_ticks.iter().zip(tick_labels(&y_ticks)) {
+        write!(svg,
+               "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#ddd\"/>\n\
+                <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+               left,
+               py(y),
+               left + plot_width,
+               py(y),
+               left - 6.0,
+               py(y) + 4.0,
+               label)
+            .unwrap();
+    }
+    for (&x, label) in x_ticks.iter().zip(tick_labels(&x_ticks)) {
+        write!(svg,
+               "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>\n",
+               px(x),
+               top + plot_height + 18.0,
+               label)
+            .unwrap();
+    }
+    write!(svg,
+           "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" \
+            stroke=\"#333\"/>\n\
+            <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>\n\
+            <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" \
+            transform=\"rotate(-90 {:.1} {:.1})\">{}</text>\n",
+           left,
+           top,
+           plot_width,
+           plot_height,
+           left + plot_width / 2.0,
+           HEIGHT - 10.0,
+           escape(x_label),
+           18.0,
+           top + plot_height / 2.0,
+           18.0,
+           top + plot_height / 2.0,
+           escape(y_label))
+        .unwrap();
+
+    // the lines and the legend
+    let series = series.iter().filter(|s| !s.points.is_empty());
+    for (i, s) in series.enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let finite = |&&(x, y): &&(f64, f64)| x.is_finite() && y.is_finite();
+        for segment in s.points.split(|p| !finite(&p)).filter(|segment| !segment.is_empty()) {
+            let coordinates: Vec<String> = segment.iter()
+                .map(|&(x, y)| format!("{:.1},{:.1}", px(x), py(y)))
+                .collect();
+            write!(svg,
+                   "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+                   coordinates.join(" "),
+                   color)
+                .unwrap();
+        }
+        if s.points.len() <= MAX_MARKERS {
+            for &(x, y) in s.points.iter().filter(finite) {
+                write!(svg,
+                       "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2.5\" fill=\"{}\"/>\n",
+                       px(x),
+                       py(y),
+                       color)
+                    .unwrap();
+            }
+        }
+        let legend_y = top + 16.0 + 18.0 * i as f64;
+        write!(svg,
+               "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" \
+                stroke-width=\"2\"/>\n\
+                <text x=\"{:.1}\" y=\"{:.1}\">{}</text>\n",
+               left + plot_width - 150.0,
+               legend_y - 4.0,
+               left + plot_width - 130.0,
+               legend_y - 4.0,
+               color,
+               left + plot_width - 124.0,
+               legend_y,
+               escape(&s.name))
+            .unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Draw a matrix of counts as a grid of cells, e.g. a confusion matrix
+///
+/// `counts[row][column]` is written into each cell. The cells are colored by their share of the
+/// sum of their row, so rows with few samples are as readable as large ones.
+pub fn heatmap(title: &str,
+               row_label: &str,
+               column_label: &str,
+               labels: &[String],
+               counts: &[Vec<usize>])
+               -> String {
+    let n = counts.len();
+    let cell = (480.0 / n.max(1) as f64).min(48.0).max(12.0);
+    let (top, left) = (MARGIN.0 + 30.0, MARGIN.3 + 10.0);
+    let width = left + cell * n as f64 + MARGIN.1;
+    let height = top + cell * n as f64 + MARGIN.2;
+
+    let mut svg = header(width, height, title);
+    write!(svg,
+           "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>\n\
+            <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" \
+            transform=\"rotate(-90 {:.1} {:.1})\">{}</text>\n",
+           left + cell * n as f64 / 2.0,
+           top - 24.0,
+           escape(column_label),
+           18.0,
+           top + cell * n as f64 / 2.0,
+           18.0,
+           top + cell * n as f64 / 2.0,
+           escape(row_label))
+        .unwrap();
+    for (i, label) in labels.iter().enumerate().take(n) {
+        let center = cell * (i as f64 + 0.5);
+        write!(svg,
+               "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>\n\
+                <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+               left + center,
+               top - 6.0,
+               escape(label),
+               left - 6.0,
+               top + center + 4.0,
+               escape(label))
+            .unwrap();
+    }
+
+    for (i, row) in counts.iter().enumerate() {
+        let total = row.iter().sum::<usize>().max(1) as f64;
+        for (j, &count) in row.iter().enumerate() {
+            let share = count as f64 / total;
+            // from white to dark blue
+            let channel = |dark: f64| (255.0 - share * (255.0 - dark)).round() as u8;
+            write!(svg,
+                   "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" \
+                    fill=\"#{:02x}{:02x}{:02x}\" stroke=\"#fff\"/>\n",
+                   left + cell * j as f64,
+                   top + cell * i as f64,
+                   cell,
+                   cell,
+                   channel(8.0),
+                   channel(81.0),
+                   channel(156.0))
+                .unwrap();
+            if cell >= 24.0 {
+                write!(svg,
+                       "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"{}\">{}\
+                        </text>\n",
+                       left + cell * (j as f64 + 0.5),
+                       top + cell * (i as f64 + 0.5) + 4.0,
+                       if share > 0.5 { "#fff" } else { "#000" },
+                       count)
+                    .unwrap();
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Draw an input of the given shape as a grayscale image, each pixel as a square of `scale`
+/// units
+///
+/// The channels are averaged. The values are stretched from white (lowest) to black (highest),
+/// which shows e.g. MNIST digits as dark strokes on white.
+pub fn image(input: &[f64], shape: Shape, scale: f64) -> String {
+    let area = shape.height * shape.width;
+    let channels = shape.channels as f64;
+    let pixels: Vec<f64> = (0..area)
+        .map(|i| (0..shape.channels).map(|c| input[c * area + i]).sum::<f64>() / channels)
+        .collect();
+    let min = pixels.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+    let max = pixels.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+
+    let (width, height) = (shape.width as f64 * scale, shape.height as f64 * scale);
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" \
+                           height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n\
+                           <rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>\n",
+                          width,
+                          height);
+    for (i, &p) in pixels.iter().enumerate() {
+        let darkness = if max > min { (p - min) / (max - min) } else { 0.0 };
+        // white pixels are covered by the background already
+        if darkness > 0.0 {
+            let gray = (255.0 * (1.0 - darkness)).round() as u8;
+            write!(svg,
+                   "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                    fill=\"#{:02x}{:02x}{:02x}\"/>\n",
+                   (i % shape.width) as f64 * scale,
+                   (i / shape.width) as f64 * scale,
+                   scale,
+                   scale,
+                   gray,
+                   gray,
+                   gray)
+                .unwrap();
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// The opening tag of an SVG document of the given size with a title at the top
+fn header(width: f64, height: f64, title: &str) -> String {
+    format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>\n\
+             <text x=\"{2:.1}\" y=\"24\" text-anchor=\"middle\" font-size=\"16\">{3}</text>\n",
+            width,
+            height,
+            width / 2.0,
+            escape(title))
+}
+
+// Between 3 and 11 evenly spaced, round tick values that cover `[min, max]`, the step is 1, 2 or
+// 5 times a power of 10
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+    let (min, max) = if max > min {
+        (min, max)
+    } else {
+        (min - 0.5, min + 0.5)
+    };
+    let rough = (max - min) / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|f| f * magnitude)
+        .find(|&s| s >= rough)
+        .unwrap();
+    let first = (min / step).floor() as i64;
+    let last = (max / step).ceil() as i64;
+    // multiplying avoids accumulating rounding errors
+    (first..last + 1).map(|i| i as f64 * step).collect()
+}
+
+// The tick values with as many decimals as the step between them needs
+fn tick_labels(ticks: &[f64]) -> Vec<String> {
+    let step = if ticks.len() > 1 { ticks[1] - ticks[0] } else { 1.0 };
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    ticks.iter().map(|t| format!("{:.*}", decimals, t)).collect()
+}
+
+/// Replace the characters that have a special meaning in XML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+#[test]
+fn test_charts() {
+    assert_eq!(tick_labels(&ticks(0.0, 1.0)), ["0.0", "0.2", "0.4", "0.6", "0.8", "1.0"]);
+    assert_eq!(tick_labels(&ticks(1.0, 30.0)), ["0", "10", "20", "30"]);
+    assert_eq!(tick_labels(&ticks(3.0, 3.0)), ["2.4", "2.6", "2.8", "3.0", "3.2", "3.4", "3.6"]);
+
+    let chart = line_chart("cost", "epoch", "cost", &[Series {
+                                                          name: "a < b".to_string(),
+                                                          points: vec![(1.0, 0.5), (2.0, 0.25)],
+                                                      }]);
+    assert!(chart.starts_with("<svg") && chart.ends_with("</svg>\n"));
+    assert!(chart.contains("<polyline") && chart.contains("a &lt; b"));
+    // a NaN cost splits the line and gets no marker
+    let nan = ::std::f64::NAN;
+    let chart = line_chart("cost", "epoch", "cost", &[Series {
+                                                          name: "cost".to_string(),
+                                                          points: vec![(1.0, 0.5),
+                                                                       (2.0, 0.4),
+                                                                       (3.0, nan),
+                                                                       (4.0, 0.3)],
+                                                      }]);
+    assert_eq!(chart.matches("<polyline").count(), 2);
+    assert_eq!(chart.matches("<circle").count(), 3);
+    assert!(!chart.contains("NaN"));
+
+    let labels = vec!["0".to_string(), "1".to_string()];
+    let map = heatmap("confusion", "actual", "predicted", &labels, &[vec![3, 1], vec![0, 4]]);
+    assert_eq!(map.matches("<rect").count(), 5);
+
+    // a 2x2 image with one black and one gray pixel besides the white background
+    let picture = image(&[0.0, 1.0, 0.5, 0.0], Shape::new(2, 2, 1), 3.0);
+    assert_eq!(picture.matches("<rect").count(), 3);
+    assert!(picture.contains("x=\"3\" y=\"0\" width=\"3\" height=\"3\" fill=\"#000000\""));
+}
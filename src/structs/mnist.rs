@@ -1,3 +1,4 @@
+use nn::Shape;
 use std::str::FromStr;
 use std::io;
 
@@ -13,6 +14,10 @@ pub struct Mnist {
 }
 
 impl Mnist {
+    /// the shape of the pictures: 28x28 pixels with a single grey channel
+    pub fn shape() -> Shape {
+        Shape::new(28, 28, 1)
+    }
     /// returns the class as an u8
     pub fn get_class(&self) -> u8 {
         self.result.clone()
@@ -1,39 +1,130 @@
+//! The datasets the network learns from, and the serialized form of networks
+
 pub mod flower;
 pub mod serialnet;
 pub mod mnist;
+pub mod regression;
+pub mod sequence;
 
 use na::DVector;
+use nn::{Float, Shape};
 use structs::flower::Flower;
 use structs::mnist::Mnist;
+use structs::regression::Observation;
+
 
+/// The kinds of targets a `Data` can have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// exactly one of the classes is correct, the class vector is one-hot
+    Classification,
+    /// any number of labels can be correct, the class vector holds 1 for each of them
+    MultiLabel,
+    /// the class vector holds real values that should be predicted
+    Regression,
+}
 
 /// Struct for u8
 #[derive(Clone, Debug)]
-pub struct Data {
+pub struct Data<N = f32> {
     /// Input Vector for the input layer of the NN
-    input: DVector<f32>,
-    /// actual class vector of the for the NN (Result)
-    class_vector: DVector<f32>,
+    input: DVector<N>,
+    /// actual class vector of the for the NN (Result), the labels of a multi-label sample or the
+    /// real-valued targets of a regression
+    class_vector: DVector<N>,
+    /// what the class vector holds
+    task: Task,
+    /// shape of the input, e.g. of an image or a sequence
+    shape: Shape,
 }
 
-impl Data {
+impl<N: Float> Data<N> {
     /// Generates a new Data struct with a Vector and an u8(class)
-    pub fn new(vec: DVector<f32>, class: u8, output_neurons: usize) -> Data {
-        let mut class_v = DVector::from_element(output_neurons, 0.0f32);
-        class_v[class as usize] = 1.0f32;
+    pub fn new(vec: DVector<N>, class: u8, output_neurons: usize) -> Data<N> {
+        let mut class_v = DVector::from_element(output_neurons, N::zero());
+        class_v[class as usize] = N::one();
         Data {
+            shape: Shape::flat(vec.len()),
             input: vec,
             class_vector: class_v,
+            task: Task::Classification,
+        }
+    }
+    /// Generates a new Data struct with real-valued targets instead of a class, for regression
+    pub fn new_regression(vec: DVector<N>, targets: DVector<N>) -> Data<N> {
+        Data {
+            shape: Shape::flat(vec.len()),
+            input: vec,
+            class_vector: targets,
+            task: Task::Regression,
+        }
+    }
+    /// Generates a new Data struct with any number of labels, `labels` holds 1 for each label the
+    /// sample has and 0 for the others
+    pub fn new_multi_label(vec: DVector<N>, labels: DVector<N>) -> Data<N> {
+        Data {
+            shape: Shape::flat(vec.len()),
+            input: vec,
+            class_vector: labels,
+            task: Task::MultiLabel,
         }
     }
+    /// Generates a new Data struct for a sequence of equally long feature vectors and an u8(class)
+    ///
+    /// The steps are stored one after another, the shape of the input is
+    /// `steps x features x 1`.
+    pub fn new_sequence(steps: &[Vec<N>], class: u8, output_neurons: usize) -> Data<N> {
+        let n_features = steps.first().map_or(0, |step| step.len());
+        let input: Vec<N> = steps.iter().flat_map(|step| step.iter().cloned()).collect();
+        Data::new(DVector { at: input }, class, output_neurons)
+            .with_shape(Shape::new(steps.len(), n_features, 1))
+    }
+    /// Set the shape of the input, it must have as many values as the input
+    pub fn with_shape(mut self, shape: Shape) -> Data<N> {
+        assert_eq!(shape.len(), self.input.len());
+        self.shape = shape;
+        self
+    }
+    /// Replace the input, e.g. by a transformed copy, the shape stays the same
+    pub fn with_input(mut self, input: DVector<N>) -> Data<N> {
+        assert_eq!(input.len(), self.input.len());
+        self.input = input;
+        self
+    }
     /// getter for the Input
-    pub fn get_input(&self) -> &DVector<f32> {
+    pub fn get_input(&self) -> &DVector<N> {
         &self.input
     }
     /// Get the class_vector
-    pub fn get_class_vector(&self) -> &DVector<f32> {
+    pub fn get_class_vector(&self) -> &DVector<N> {
         &self.class_vector
     }
+    /// Get the class of single-label classification data, `None` for other tasks
+    pub fn get_class(&self) -> Option<usize> {
+        if self.task != Task::Classification {
+            return None;
+        }
+        // the class vector is one-hot
+        self.class_vector.at.iter().position(|&x| x == N::one())
+    }
+    /// Get the kind of task the data is meant for
+    pub fn get_task(&self) -> Task {
+        self.task
+    }
+    /// Get the shape of the input
+    pub fn get_shape(&self) -> Shape {
+        self.shape
+    }
+    /// Convert the data into another precision
+    pub fn convert<M: Float>(&self) -> Data<M> {
+        Data {
+            input: DVector::from_fn(self.input.len(), |i| M::from_float(self.input[i])),
+            class_vector: DVector::from_fn(self.class_vector.len(),
+                                           |i| M::from_float(self.class_vector[i])),
+            task: self.task,
+            shape: self.shape,
+        }
+    }
 }
 
 impl From<Flower> for Data {
@@ -46,9 +137,20 @@ impl From<Flower> for Data {
 impl From<Mnist> for Data {
     /// parses a Mnist into a Data struct
     fn from(mnist: Mnist) -> Data {
-        Data::new(DVector::from_slice(784, mnist.get_slice()),
+        Data::new(DVector::from_slice(Mnist::shape().len(), mnist.get_slice()),
                   mnist.get_class(),
                   10)
+            .with_shape(Mnist::shape())
+    }
+}
+
+impl From<Observation> for Data {
+    /// parses an Observation into a Data struct with real-valued targets
+    fn from(observation: Observation) -> Data {
+        let features = observation.get_features();
+        let targets = observation.get_targets();
+        Data::new_regression(DVector::from_slice(features.len(), features),
+                             DVector::from_slice(targets.len(), targets))
     }
 }
 
@@ -0,0 +1,52 @@
+use std::io;
+use std::str::FromStr;
+
+/// Observation type that contains features and the real values that should be predicted from them
+///
+/// In a file every observation is one line: the features separated by `,`, followed by `;` and
+/// the targets separated by `,`. For example `0.5,1.2,3.0;7.1` has 3 features and one target.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// the input values
+    features: Vec<f32>,
+    /// the values that should be predicted
+    targets: Vec<f32>,
+}
+
+impl Observation {
+    /// returns the features
+    pub fn get_features(&self) -> &[f32] {
+        &self.features
+    }
+    /// returns the targets
+    pub fn get_targets(&self) -> &[f32] {
+        &self.targets
+    }
+}
+
+impl FromStr for Observation {
+    type Err = io::Error;
+
+    /// Parse an Observation from a String
+    ///
+    /// Returns a `Result<Observation, io::Error>`, in case the incoming string cannot be parsed
+    /// into a valid observation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(';').collect();
+        if parts.len() != 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Features and targets need to be separated by ';'."));
+        }
+        let parse = |part: &str| {
+            part.split(',')
+                .map(|f| f.trim().parse())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        };
+
+        Ok(Observation {
+            features: parse(parts[0])?,
+            targets: parse(parts[1])?,
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use std::io;
+use std::str::FromStr;
+
+/// Sequence type that contains a series of sensor readings and its class
+///
+/// In a file every sequence is one line: the class, followed by the steps separated by `;`, the
+/// features of each step separated by `,`. For example `2;0.1,0.5;0.2,0.4;0.4,0.1` is a sequence
+/// of class 2 with 3 steps of 2 features each.
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    /// the class of the sequence, a number starting at 0
+    class: u8,
+    /// the feature vector of each step
+    steps: Vec<Vec<f32>>,
+}
+
+impl Sequence {
+    /// returns the class as an u8
+    pub fn get_class(&self) -> u8 {
+        self.class
+    }
+    /// returns the feature vectors of the steps
+    pub fn get_steps(&self) -> &[Vec<f32>] {
+        &self.steps
+    }
+}
+
+impl FromStr for Sequence {
+    type Err = io::Error;
+
+    /// Parse a Sequence from a String
+    ///
+    /// Returns a `Result<Sequence, io::Error>`, in case the incoming string cannot be parsed
+    /// into a valid sequence.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(';');
+        let class = parts.next()
+            .unwrap()
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut steps: Vec<Vec<f32>> = Vec::new();
+        for part in parts {
+            let step = part.split(',')
+                .map(|f| f.trim().parse())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            if !steps.is_empty() && step.len() != steps[0].len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "All steps of a sequence need the same features."));
+            }
+            steps.push(step);
+        }
+        if steps.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "A sequence needs at least one step."));
+        }
+
+        Ok(Sequence {
+            class: class,
+            steps: steps,
+        })
+    }
+}
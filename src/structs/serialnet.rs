@@ -1,35 +1,331 @@
-use nn::Network;
+use na::DVector;
+use nn::{Activation, BatchNorm, Float, Network, Precision, TrainingHistory};
+use nn::topology::{Cell, PoolMode, Shape};
 
 /// Struct used as a container for serializing a network state
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SerializableNet {
+pub struct SerializableNet<N> {
+    /// the precision the network was computed in, older files without it are `f32`
+    #[serde(default)]
+    pub precision: Precision,
+    /// the number of inputs per sample
+    pub input_size: usize,
+    /// a Vec that contains the networks layers in order
+    pub layers: Vec<SerializableLayer<N>>,
+    /// the progress of the training run that produced the network, if it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<TrainingHistory>,
+}
+
+/// Container for serializing a single layer, the kind of layer is stored in the field `kind`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SerializableLayer<N> {
+    /// a fully connected layer
+    #[serde(rename = "dense")]
+    Dense {
+        /// the weight matrix (nrows, ncols, column-major-vector)
+        weights: (usize, usize, Vec<N>),
+        /// the biases of each output
+        biases: Vec<N>,
+    },
+    /// a 2D convolution
+    #[serde(rename = "conv2d")]
+    Conv2D {
+        /// the shape of the input images
+        input_shape: Shape,
+        /// height and width of the kernels
+        kernel: usize,
+        /// step between two applications of the kernels
+        stride: usize,
+        /// number of zeros added at each border of the input
+        padding: usize,
+        /// the kernels (nrows, ncols, column-major-vector), one row per output channel
+        weights: (usize, usize, Vec<N>),
+        /// the biases of each output channel
+        biases: Vec<N>,
+    },
+    /// max or average pooling
+    #[serde(rename = "pool2d")]
+    Pool2D {
+        /// whether the maximum or the mean of each window is taken
+        mode: PoolMode,
+        /// the shape of the input images
+        input_shape: Shape,
+        /// height and width of the windows
+        size: usize,
+        /// step between two windows
+        stride: usize,
+    },
+    /// an Elman, LSTM or GRU layer
+    #[serde(rename = "recurrent")]
+    Recurrent {
+        /// the kind of cell
+        cell: Cell,
+        /// the shape of the input sequences, steps x features x 1
+        input_shape: Shape,
+        /// whether the states after all steps are passed on
+        sequences: bool,
+        /// number of steps the error is propagated back, 0 for all steps
+        bptt_length: usize,
+        /// the weights of the inputs (nrows, ncols, column-major-vector), one block per gate
+        weights: (usize, usize, Vec<N>),
+        /// the weights of the previous state (nrows, ncols, column-major-vector)
+        recurrent_weights: (usize, usize, Vec<N>),
+        /// the biases of each gate
+        biases: Vec<N>,
+    },
+    /// an elementwise activation function
+    #[serde(rename = "activation")]
+    Activation {
+        /// the applied function
+        function: Activation,
+    },
+    /// inverted dropout
+    #[serde(rename = "dropout")]
+    Dropout {
+        /// the probability with which each input is kept
+        keep_prob: N,
+    },
+    /// a batch normalization
+    #[serde(rename = "batch_norm")]
+    BatchNorm(SerializableBatchNorm<N>),
+}
+
+/// The state of a network as it was saved before networks were made of layers
+///
+/// Such a network consists of sigmoid layers only, optionally with dropout and batch
+/// normalization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacySerializableNet<N> {
+    /// the precision the network was computed in, older files without it are `f32`
+    #[serde(default)]
+    pub precision: Precision,
     /// a Vec hat contains the networks layers
     pub layers: Vec<u32>,
     /// a Vec that contains the weights of the respective layer
     /// (nrows, ncols, column-major-vector)
-    pub weights: Vec<(usize, usize, Vec<f32>)>,
+    pub weights: Vec<(usize, usize, Vec<N>)>,
     /// a Vec cointaining the biases of the respective layer
-    pub biases: Vec<Vec<f32>>,
+    pub biases: Vec<Vec<N>>,
+    /// a Vec containing the dropout keep probabilities of the input and hidden layers,
+    /// empty in older files
+    #[serde(default)]
+    pub keep_probs: Vec<N>,
+    /// a Vec containing the batch normalization of each layer except the input layer,
+    /// empty in older files
+    #[serde(default)]
+    pub batch_norms: Vec<Option<SerializableBatchNorm<N>>>,
 }
 
-impl From<Network> for SerializableNet {
-    fn from(network: Network) -> Self {
+/// Any format a network state can be saved in
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged, bound(deserialize = "N: Float"))]
+pub enum SavedNet<N> {
+    /// the current format
+    Layers(SerializableNet<N>),
+    /// the format before networks were made of layers
+    Legacy(LegacySerializableNet<N>),
+}
 
-        let mut weights: Vec<(usize, usize, Vec<f32>)> = Vec::new();
-        for matrix in network.get_weights() {
-            // TODO: clone?!
-            weights.push((matrix.nrows(), matrix.ncols(), matrix.clone().into_vector()))
+/// Struct used as a container for serializing the parameters and running statistics of a
+/// batch normalization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializableBatchNorm<N> {
+    /// learnable scale of each neuron
+    pub gamma: Vec<N>,
+    /// learnable shift of each neuron
+    pub beta: Vec<N>,
+    /// running average of the batch means
+    pub running_mean: Vec<N>,
+    /// running average of the batch variances
+    pub running_var: Vec<N>,
+    /// weight of the old value when the running averages are updated
+    pub momentum: N,
+    /// constant that is added to the variance
+    pub epsilon: N,
+}
+
+impl<N: Float> From<Network<N>> for SerializableNet<N> {
+    fn from(network: Network<N>) -> Self {
+        SerializableNet {
+            precision: N::precision(),
+            input_size: network.input_size(),
+            layers: network.get_layers().iter().map(|layer| layer.serialize()).collect(),
+            history: None,
+        }
+    }
+}
+
+impl<N: Float> From<SavedNet<N>> for SerializableNet<N> {
+    fn from(saved: SavedNet<N>) -> Self {
+        match saved {
+            SavedNet::Layers(ser_net) => ser_net,
+            SavedNet::Legacy(legacy) => legacy.into(),
         }
+    }
+}
 
-        let mut biases: Vec<Vec<f32>> = Vec::new();
-        for vec in network.get_biases() {
-            // TODO: clone?!
-            biases.push(vec.clone().at);
+impl<N: Float> From<LegacySerializableNet<N>> for SerializableNet<N> {
+    fn from(legacy: LegacySerializableNet<N>) -> Self {
+        let mut layers = Vec::new();
+        let mut batch_norms = legacy.batch_norms.into_iter();
+        for (l, (weights, biases)) in legacy.weights.into_iter().zip(legacy.biases).enumerate() {
+            // networks saved without dropout rates did not use dropout
+            if let Some(keep_prob) = legacy.keep_probs.get(l) {
+                if *keep_prob < N::one() {
+                    layers.push(SerializableLayer::Dropout { keep_prob: *keep_prob });
+                }
+            }
+            layers.push(SerializableLayer::Dense {
+                weights: weights,
+                biases: biases,
+            });
+            // networks saved without batch normalization did not use it
+            if let Some(Some(bn)) = batch_norms.next() {
+                layers.push(SerializableLayer::BatchNorm(bn));
+            }
+            layers.push(SerializableLayer::Activation { function: Activation::Sigmoid });
         }
         SerializableNet {
-            layers: network.get_layers().to_vec(),
-            weights: weights,
-            biases: biases,
+            precision: legacy.precision,
+            input_size: legacy.layers[0] as usize,
+            layers: layers,
+            history: None,
         }
     }
 }
+
+impl<N: Float> SerializableLayer<N> {
+    /// Convert the layer into another precision
+    pub fn convert<M: Float>(self) -> SerializableLayer<M> {
+        let conv = |v: Vec<N>| v.into_iter().map(M::from_float).collect();
+        match self {
+            SerializableLayer::Dense { weights, biases } => {
+                SerializableLayer::Dense {
+                    weights: (weights.0, weights.1, conv(weights.2)),
+                    biases: conv(biases),
+                }
+            }
+            SerializableLayer::Conv2D { input_shape, kernel, stride, padding, weights, biases } => {
+                SerializableLayer::Conv2D {
+                    input_shape: input_shape,
+                    kernel: kernel,
+                    stride: stride,
+                    padding: padding,
+                    weights: (weights.0, weights.1, conv(weights.2)),
+                    biases: conv(biases),
+                }
+            }
+            SerializableLayer::Pool2D { mode, input_shape, size, stride } => {
+                SerializableLayer::Pool2D {
+                    mode: mode,
+                    input_shape: input_shape,
+                    size: size,
+                    stride: stride,
+                }
+            }
+            SerializableLayer::Recurrent { cell,
+                                           input_shape,
+                                           sequences,
+                                           bptt_length,
+                                           weights,
+                                           recurrent_weights,
+                                           biases } => {
+                SerializableLayer::Recurrent {
+                    cell: cell,
+                    input_shape: input_shape,
+                    sequences: sequences,
+                    bptt_length: bptt_length,
+                    weights: (weights.0, weights.1, conv(weights.2)),
+                    recurrent_weights: (recurrent_weights.0,
+                                        recurrent_weights.1,
+                                        conv(recurrent_weights.2)),
+                    biases: conv(biases),
+                }
+            }
+            SerializableLayer::Activation { function } => {
+                SerializableLayer::Activation { function: function }
+            }
+            SerializableLayer::Dropout { keep_prob } => {
+                SerializableLayer::Dropout { keep_prob: M::from_float(keep_prob) }
+            }
+            SerializableLayer::BatchNorm(bn) => {
+                SerializableLayer::BatchNorm(SerializableBatchNorm {
+                    gamma: conv(bn.gamma),
+                    beta: conv(bn.beta),
+                    running_mean: conv(bn.running_mean),
+                    running_var: conv(bn.running_var),
+                    momentum: M::from_float(bn.momentum),
+                    epsilon: M::from_float(bn.epsilon),
+                })
+            }
+        }
+    }
+}
+
+impl<'a, N: Float> From<&'a BatchNorm<N>> for SerializableBatchNorm<N> {
+    fn from(bn: &'a BatchNorm<N>) -> Self {
+        SerializableBatchNorm {
+            gamma: bn.get_gamma().at.clone(),
+            beta: bn.get_beta().at.clone(),
+            running_mean: bn.get_running_mean().at.clone(),
+            running_var: bn.get_running_var().at.clone(),
+            momentum: bn.get_momentum(),
+            epsilon: bn.get_epsilon(),
+        }
+    }
+}
+
+impl<N: Float> From<SerializableBatchNorm<N>> for BatchNorm<N> {
+    fn from(ser_bn: SerializableBatchNorm<N>) -> Self {
+        BatchNorm::from_parts(DVector { at: ser_bn.gamma },
+                              DVector { at: ser_bn.beta },
+                              DVector { at: ser_bn.running_mean },
+                              DVector { at: ser_bn.running_var },
+                              ser_bn.momentum,
+                              ser_bn.epsilon)
+    }
+}
+
+
+#[test]
+fn test_batch_norm_serialization() {
+    use na::DMatrix;
+    use nn::Layer;
+
+    let bn = BatchNorm::from_parts(DVector::from_slice(2, &[2.0f64, 0.5]),
+                                   DVector::from_slice(2, &[1.0, -1.0]),
+                                   DVector::from_slice(2, &[1.0, 3.0]),
+                                   DVector::from_slice(2, &[4.0, 0.25]),
+                                   0.8,
+                                   1e-3);
+    let ser_bn = SerializableBatchNorm::from(&bn);
+    assert_eq!(ser_bn.running_mean, vec![1.0, 3.0]);
+    assert_eq!(ser_bn.running_var, vec![4.0, 0.25]);
+    let restored = BatchNorm::from(ser_bn.clone());
+    assert_eq!(restored.get_gamma(), bn.get_gamma());
+    assert_eq!(restored.get_beta(), bn.get_beta());
+    assert_eq!(restored.get_running_mean(), bn.get_running_mean());
+    assert_eq!(restored.get_running_var(), bn.get_running_var());
+    assert_eq!((restored.get_momentum(), restored.get_epsilon()), (0.8, 1e-3));
+
+    // networks of the old format keep the running statistics of their batch normalization
+    let legacy = LegacySerializableNet {
+        precision: Precision::Double,
+        layers: vec![2, 2, 1],
+        weights: vec![(2, 2, vec![1.0, 0.0, 0.0, 1.0]), (1, 2, vec![1.0, -1.0])],
+        biases: vec![vec![0.0, 0.0], vec![0.5]],
+        keep_probs: Vec::new(),
+        batch_norms: vec![Some(ser_bn), None],
+    };
+    let nn: Network<f64> = SerializableNet::from(legacy).into();
+    let kinds: Vec<_> = nn.get_layers().iter().map(|l| l.kind()).collect();
+    assert_eq!(kinds, vec!["dense", "batch norm", "activation", "dense", "activation"]);
+    let input = DMatrix::from_column_vector(2, 1, &[0.3, -0.6]);
+    let mut expected = DMatrix::new_zeros(2, 1);
+    bn.forward_into(&input, &mut expected);
+    let mut output = DMatrix::new_zeros(2, 1);
+    nn.get_layers()[1].forward_into(&input, &mut output);
+    assert_eq!(output, expected);
+}
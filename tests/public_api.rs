@@ -0,0 +1,74 @@
+//! Uses the library only through its public API, like a crate that depends on it would: the data
+//! is preprocessed, a network is built, trained, evaluated, saved and loaded again.
+
+extern crate nalgebra;
+extern crate rand;
+extern crate rustle_my_net;
+
+use nalgebra::DVector;
+use rand::{SeedableRng, StdRng};
+use rustle_my_net::nn::{Network, NetworkBuilder, Shape, Topology, Trainer};
+use rustle_my_net::nn::learning::{self, Evaluation};
+use rustle_my_net::preprocessing::{self, Augmentation, ClassBalance, Resampling,
+                                   Standardization};
+use rustle_my_net::structs::Data;
+
+// Two classes of points, those with a larger first coordinate and the others
+fn points() -> Vec<Data> {
+    (0..40)
+        .map(|i| {
+            let (x, y) = ((i * 7 % 40) as f32, (i * 13 % 40) as f32 * 10.0);
+            Data::new(DVector::from_slice(2, &[x, y]), (x * 10.0 > y) as u8, 2)
+        })
+        .collect()
+}
+
+#[test]
+fn test_train_and_save() {
+    let mut data = points();
+    let (training_data, test_data) = preprocessing::split_data(&mut data, 10);
+    assert_eq!((training_data.len(), test_data.len()), (30, 10));
+
+    let scaling = Standardization::fit(&training_data).unwrap();
+    let training_data: Vec<Data> = training_data.iter().map(|d| scaling.apply(d)).collect();
+    let test_data: Vec<Data> = test_data.iter().map(|d| scaling.apply(d)).collect();
+
+    let topology = Topology::parse(vec!["2", "6", "2"]).unwrap();
+    let nn: Network = NetworkBuilder::new(topology).seed(3).build().unwrap();
+    let weights = preprocessing::inverse_frequency_weights(&training_data, 2);
+    let (nn, history) = Trainer::new()
+        .epochs(3)
+        .batch_size(5)
+        .learning_rate(1.0)
+        .class_balance(ClassBalance {
+            weights: Some(weights),
+            resampling: Resampling::Over,
+        })
+        .train(nn, training_data, &test_data);
+    assert_eq!(history.epochs.len(), 3);
+    match learning::evaluate_metrics(&nn, &test_data, 0.5) {
+        Evaluation::Classification(_, total) => assert_eq!(total, 10),
+        _ => panic!("the points are classified"),
+    }
+
+    // the loaded network computes the same outputs
+    let output = nn.feedforward(test_data[0].get_input());
+    let state_file = std::env::temp_dir().join("rustle-my-net-public-api.json");
+    nn.save_to_file(&state_file).unwrap();
+    let loaded: Network = Network::from_file(&state_file).unwrap();
+    std::fs::remove_file(&state_file).unwrap();
+    assert_eq!(loaded.feedforward(test_data[0].get_input()).at, output.at);
+}
+
+#[test]
+fn test_augmentation() {
+    let shape = Shape::new(4, 4, 1);
+    let image = Data::new(DVector::from_fn(16, |i| (i % 4 == 1) as u8 as f32), 1, 2)
+        .with_shape(shape);
+    let augmentation = Augmentation::parse(vec!["shift:1", "noise:0.1"]).unwrap();
+    let mut rng: StdRng = SeedableRng::from_seed(&[1][..]);
+    let augmented = augmentation.apply(&image, &mut rng);
+    assert_eq!(augmented.get_shape(), shape);
+    assert_eq!(augmented.get_class(), Some(1));
+    assert!(augmented.get_input().at.iter().all(|&x| x >= 0.0 && x <= 1.0));
+}